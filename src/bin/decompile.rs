@@ -1,29 +1,83 @@
+use wasm2rs::options::EmitOptions;
 use wasm2rs::parser::parse;
 use std::env::args;
 use std::fs;
 use std::io::prelude::*;
+use std::path::Path;
 
 
 fn main() {
-    let inputs = args().collect::<Vec<_>>();
+    let (flags, inputs): (Vec<_>, Vec<_>) = args().skip(1).partition(|arg| arg.starts_with("--"));
 
-    if let Some(filename) = inputs.get(1) {
-        if filename == "-" {
+    let mut options = EmitOptions::default();
+    let mut crate_dir = None;
+
+    for flag in flags {
+        match flag.as_str() {
+            "--trap" => options.trapping = true,
+            "--sandbox" => options.sandboxed = true,
+            "--instance" => options.instance = true,
+            "--auto-setup" => options.auto_setup = true,
+            "--safe" => options.safe = true,
+            _ => if let Some(dir) = flag.strip_prefix("--data-dir=") {
+                options.data_dir = Some(dir.to_string());
+            } else if let Some(dir) = flag.strip_prefix("--crate=") {
+                crate_dir = Some(dir.to_string());
+            } else {
+                panic!("Unknown option {flag:?}.");
+            },
+        }
+    }
+
+    if let Some(filename) = inputs.first() {
+        let content = if filename == "-" {
             let mut buffer = vec![];
     
             std::io::stdin().read_to_end(&mut buffer).expect("Unable to read stdin.");
-    
-            let context = parse(&buffer);
-    
-            println!("{}", context.emit_code().join("\n"));
+
+            buffer
         } else {
-            let content = fs::read(filename).expect("Unable to read file");
-    
-            let context = parse(&content);
-    
-            println!("{}", context.emit_code().join("\n"));
+            fs::read(filename).expect("Unable to read file")
         };
+
+        let context = match parse(&content) {
+            Ok(context) => context,
+            Err(error) => {
+                eprintln!("Unable to decompile the module: {error}.");
+                std::process::exit(1);
+            },
+        };
+
+        let unsupported = |feature| -> ! {
+            eprintln!("The module uses {feature}, which cannot be decompiled to safe Rust.");
+            std::process::exit(1);
+        };
+
+        // a crate is named after its input file when the module has no name
+        if let Some(dir) = &crate_dir {
+            let name = Path::new(filename).file_stem().and_then(|stem| stem.to_str()).filter(|_| filename != "-").unwrap_or("module");
+            let files = context.emit_crate(name, &options).unwrap_or_else(|feature| unsupported(feature));
+            for (path, bytes) in files {
+                let path = Path::new(dir).join(path);
+                fs::create_dir_all(path.parent().unwrap()).expect("Unable to create crate directory.");
+                fs::write(path, bytes).expect("Unable to write crate file.");
+            }
+            return
+        }
+
+        let lines = context.emit_code(&options).unwrap_or_else(|feature| unsupported(feature));
+
+        // the data directory is resolved from the working directory here
+        // and from the emitted file by `include_bytes!`
+        if let Some(dir) = &options.data_dir {
+            fs::create_dir_all(dir).expect("Unable to create data directory.");
+            for (name, bytes) in context.data_files() {
+                fs::write(Path::new(dir).join(name), bytes).expect("Unable to write data file.");
+            }
+        }
+
+        println!("{}", lines.join("\n"));
     } else {
         panic!("Missing WASM file path or \"-\" for reading from stdin.")
     }
-}
\ No newline at end of file
+}
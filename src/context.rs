@@ -1,18 +1,86 @@
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
 use crate::function::Function;
+use crate::memory::Memory;
+use crate::names::{self, Names};
+use crate::global::{Global, GlobalKind, ImportedGlobal};
+use crate::table::Table;
 use crate::func_type::FuncType;
-use crate::expression::{self, Expression};
+use crate::expression::{self, Expression, ParserError};
 use crate::statement::{INDENTATION, Statement};
+use crate::options::EmitOptions;
+use crate::runtime;
+use crate::wasm_type::WASMType;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
 
 #[derive(Debug, Clone)]
 pub struct Context {
-    pub functions: HashMap<u32, FunctionKind>,
-    pub types: HashMap<u32, FuncType>,
-    pub memory_size: usize,
-    pub data: Vec<(i32, Vec<u8>)>
+    pub functions: BTreeMap<u32, FunctionKind>,
+    pub types: BTreeMap<u32, FuncType>,
+    pub globals: BTreeMap<u32, GlobalKind>,
+    pub tables: BTreeMap<u32, Table>,
+    pub memories: BTreeMap<u32, Memory>,
+    /// Active data segments by segment index, as (memory index, offset, bytes).
+    pub data: BTreeMap<u32, (u32, u64, Vec<u8>)>,
+    pub passive_data: BTreeMap<u32, Vec<u8>>,
+    pub passive_elements: BTreeMap<u32, (WASMType, Vec<Expression>)>,
+    /// Active element segments whose offset is only known on instantiation,
+    /// as (table index, offset). They are kept with the passive segments
+    /// and applied by `setup`.
+    pub offset_elements: BTreeMap<u32, (u32, Expression)>,
+    /// Active data segments whose offset is only known on instantiation,
+    /// as (memory index, offset), like `offset_elements`.
+    pub offset_data: BTreeMap<u32, (u32, Expression)>,
+    /// Types of the values carried by exceptions with each tag.
+    pub tags: BTreeMap<u32, FuncType>,
+    /// Function run once the module is instantiated, called by `setup`.
+    pub start: Option<u32>,
+    /// Imported functions and globals in import section order.
+    pub imports: Vec<Import>,
+    /// Names from the `name` section, as they appear there.
+    pub names: Names,
+    /// Identifiers of the statics holding named passive data segments.
+    pub data_names: BTreeMap<u32, String>,
+    /// Features beyond the MVP that the code of the functions uses.
+    pub features: BTreeSet<Feature>,
+}
+
+/// Feature of WASM that not every kind of emitted code can express.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Feature {
+    Atomics,
+    /// `v128` values and the operators on them, which need the `simd128`
+    /// target feature.
+    Simd,
+    SimdMemory,
+    Exceptions,
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Atomics => write!(f, "atomics"),
+            Self::Simd => write!(f, "SIMD"),
+            Self::SimdMemory => write!(f, "SIMD memory accesses"),
+            Self::Exceptions => write!(f, "exception handling"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Import {
+    Function(u32),
+    Global(u32),
+}
+
+/// Trait an instance calls the imports of one module through.
+struct HostTrait {
+    module: String,
+    name: String,
+    methods: Vec<(Import, String)>,
+    /// Names of the methods.
+    used: BTreeSet<String>,
 }
 
 #[derive(Default, Clone)]
@@ -23,7 +91,13 @@ pub struct ContextBuilder<'cb_lt> {
     code_sections: Vec<wasmparser::FunctionBody<'cb_lt>>,
     exports: Option<wasmparser::ExportSectionReader<'cb_lt>>,
     memory: Option<wasmparser::MemorySectionReader<'cb_lt>>,
+    globals: Option<wasmparser::GlobalSectionReader<'cb_lt>>,
+    tables: Option<wasmparser::TableSectionReader<'cb_lt>>,
+    elements: Option<wasmparser::ElementSectionReader<'cb_lt>>,
+    tags: Option<wasmparser::TagSectionReader<'cb_lt>>,
     data: Vec<wasmparser::DataSectionReader<'cb_lt>>,
+    start: Option<u32>,
+    names: Names,
 }
 
 impl Context {
@@ -31,82 +105,726 @@ impl Context {
         self.functions.get(&index)
     }
 
+    pub fn get_global_by_index(&self, index: u32) -> Option<&GlobalKind> {
+        self.globals.get(&index)
+    }
+
+    /// Name of the static holding passive data segment `index`. Active
+    /// segments are dropped once applied and have none.
+    pub fn get_passive_data_by_index(&self, index: u32) -> Option<String> {
+        self.passive_data.contains_key(&index).then(|| self.data_names.get(&index).cloned().unwrap_or_else(|| format!("__w2r_data{index}")))
+    }
+
+    /// Name of the static holding passive element segment `index`, like
+    /// [`Context::get_passive_data_by_index`].
+    pub fn get_passive_element_by_index(&self, index: u32) -> Option<String> {
+        self.passive_elements.contains_key(&index).then(|| format!("__w2r_elem{index}"))
+    }
+
+    pub fn get_table_by_index(&self, index: u32) -> Option<&Table> {
+        self.tables.get(&index)
+    }
+
+    pub fn get_tag_by_index(&self, index: u32) -> Option<&FuncType> {
+        self.tags.get(&index)
+    }
+
+    /// Variable holding local `index` of `function`.
+    pub fn local_name(&self, function: u32, index: u32) -> String {
+        match self.get_function_by_index(function) {
+            Some(FunctionKind::Defined(func)) => func.local(index),
+            _ => format!("p{index}"),
+        }
+    }
+
+    /// Name of label `index` of `function`, counting blocks in the order
+    /// they appear.
+    pub fn label_name(&self, function: u32, index: u32) -> Option<&str> {
+        self.names.labels.get(&function)?.get(&index).map(String::as_str)
+    }
+
+    pub fn get_memory_by_index(&self, index: u32) -> Option<&Memory> {
+        self.memories.get(&index)
+    }
+
+    /// Address `addr` into memory `index`, tagged with its memory. Addresses
+    /// into the native memory are emitted as they are, those into emulated
+    /// memories as pointers, and sandboxed accesses go through the memory.
+    pub fn address(&self, index: u32, addr: Expression) -> Expression {
+        match self.get_memory_by_index(index) {
+            Some(memory) => Expression::MemoryAddress(memory.clone(), Box::new(addr)),
+            None => addr,
+        }
+    }
+
+    pub fn canonical_type_index(&self, ty: &FuncType) -> Option<u32> {
+        canonical_type_index(&self.types, ty)
+    }
+
+    pub fn ref_func(&self, func: &FunctionKind) -> Expression {
+        ref_func(&self.types, func)
+    }
+
+    /// The data segments of the module with the names of the files to write
+    /// them to, for [`EmitOptions::data_dir`].
+    pub fn data_files(&self) -> impl Iterator<Item = (String, &[u8])> + '_ {
+        let active = self.data.iter().map(|(index, (_, _, bytes))| (*index, bytes));
+        let mut segments = active.chain(self.passive_data.iter().map(|(index, bytes)| (*index, bytes))).collect::<Vec<_>>();
+        segments.sort_by_key(|(index, _)| *index);
+        segments.into_iter().map(|(index, bytes)| (data_file_name(index), bytes.as_slice()))
+    }
+
+    /// Whether this module is a program rather than a library: it has a
+    /// start function and exports no functions to call instead.
+    pub fn is_command(&self) -> bool {
+        self.start.is_some() && !self.functions.values().any(|fk| matches!(fk, FunctionKind::Defined(func) if func.exported))
+    }
+
+    /// End of what the module uses of the native `memory` without growing
+    /// it: its initial pages and its data, rounded up to 16 bytes.
+    fn native_memory_end(&self, memory: &Memory) -> u64 {
+        let data_end = self.data.values().filter(|(index, ..)| *index == memory.index).map(|(_, offset, bytes)| *offset + bytes.len() as u64);
+        let end = data_end.fold(memory.initial << 16, u64::max);
+        end.div_ceil(16) * 16
+    }
+
+    /// Whether the module exports a function or global named `name`.
+    pub fn exports_symbol(&self, name: &str) -> bool {
+        let functions = self.functions.values().filter_map(|fk| match fk {
+            FunctionKind::Defined(func) if func.exported => func.export_name.as_deref(),
+            _ => None,
+        });
+        let globals = self.globals.values().filter_map(|gk| match gk {
+            GlobalKind::Defined(global) if global.exported => global.export_name.as_deref(),
+            _ => None,
+        });
+        functions.chain(globals).any(|export| export == name)
+    }
+
+    /// A feature of this module that code emitted with `options` cannot
+    /// express: safe code has no raw pointers for SIMD accesses and does not
+    /// unwind.
+    pub fn unsupported_feature(&self, options: &EmitOptions) -> Option<Feature> {
+        self.features.iter().copied().find(|feature| options.safe && matches!(feature, Feature::SimdMemory | Feature::Exceptions))
+    }
+
     pub fn builder<'a>() -> ContextBuilder<'a> {
         ContextBuilder::new()
     }
 
-    pub fn emit_code(&self) -> Vec<String> {
+    /// The source of this module as a single file, or the feature it uses
+    /// that code emitted with `options` cannot express.
+    pub fn emit_code(&self, options: &EmitOptions) -> Result<Vec<String>, Feature> {
+        if let Some(feature) = self.unsupported_feature(options) {
+            return Err(feature)
+        }
+        let options = &implied_options(options);
+        let mut lines = self.emit_root(options, &[]);
+        let code = lines.clone();
+        emit_runtime(&mut lines, &code, options);
+        Ok(lines)
+    }
+
+    /// The files of a crate building this module, with their paths in the
+    /// crate: a manifest, the root source file, one module file for each
+    /// import module and group of functions, and the data segments. The
+    /// crate is named after the module, or `name` when it has none. Fails
+    /// like `emit_code`.
+    pub fn emit_crate(&self, name: &str, options: &EmitOptions) -> Result<Vec<(String, Vec<u8>)>, Feature> {
+        if let Some(feature) = self.unsupported_feature(options) {
+            return Err(feature)
+        }
+        // the sources include the data from `src`
+        let options = &implied_options(&EmitOptions {
+            modules: true,
+            data_dir: Some("../data".to_string()),
+            ..options.clone()
+        });
+        let modules = self.emit_modules(options);
+        let mut root = self.emit_root(options, &modules);
+        let code = root.iter().chain(modules.iter().flat_map(|module| module.lines.iter())).cloned().collect::<Vec<_>>();
+        emit_runtime(&mut root, &code, options);
+
+        let mut files = Vec::new();
+        let program = self.is_command() && !options.instance;
+
+        // an instance is a library for Rust hosts on any target, the free
+        // functions build a wasm32 library or program
+        let mut manifest = vec![
+            "[package]".to_string(),
+            format!("name = \"{}\"", names::identifier(self.names.module.as_deref().unwrap_or(name))),
+            "version = \"0.1.0\"".to_string(),
+            "edition = \"2021\"".to_string(),
+        ];
+        if !program {
+            manifest.push("".to_string());
+            manifest.push("[lib]".to_string());
+            manifest.push(format!("crate-type = [\"{}\"]", if options.instance { "rlib" } else { "cdylib" }));
+        }
+        manifest.push("".to_string());
+        manifest.push("# not part of any enclosing workspace".to_string());
+        manifest.push("[workspace]".to_string());
+        files.push(("Cargo.toml".to_string(), manifest));
+
+        if !options.instance {
+            let mut features = Vec::new();
+            let mut flags = Vec::new();
+            if self.get_memory_by_index(0).is_some_and(|memory| memory.shared && !memory.is_sandboxed(options)) {
+                features.extend(["+atomics", "+bulk-memory"]);
+                flags.extend(["\"-C\"".to_string(), "\"link-arg=--shared-memory\"".to_string()]);
+            }
+            if self.features.contains(&Feature::Simd) || self.features.contains(&Feature::SimdMemory) {
+                features.push("+simd128");
+            }
+            if !features.is_empty() {
+                flags.splice(0..0, ["\"-C\"".to_string(), format!("\"target-feature={}\"", features.join(","))]);
+            }
+            // the crate's own data and stack go after everything the module
+            // uses of the native memory, rather than at 1 MiB after a stack
+            // of its own like the data of the module they would overwrite
+            if let Some(base) = self.get_memory_by_index(0).filter(|memory| !memory.is_sandboxed(options)).map(|memory| self.native_memory_end(memory)) {
+                flags.extend([
+                    "\"-C\"".to_string(), format!("\"link-arg=--global-base={base}\""),
+                    "\"-C\"".to_string(), format!("\"link-arg=-zstack-size={STACK_SIZE}\""),
+                    "\"-C\"".to_string(), "\"link-arg=--no-stack-first\"".to_string(),
+                ]);
+            }
+
+            let mut config = vec!["[build]".to_string(), "target = \"wasm32-unknown-unknown\"".to_string()];
+            if !flags.is_empty() {
+                config.push("".to_string());
+                config.push("[target.wasm32-unknown-unknown]".to_string());
+                config.push(format!("rustflags = [{}]", flags.join(", ")));
+            }
+            files.push((".cargo/config.toml".to_string(), config));
+        }
+
+        files.push((if program { "src/main.rs" } else { "src/lib.rs" }.to_string(), root));
+        files.extend(modules.into_iter().map(|module| (format!("src/{}.rs", module.name), module.lines)));
+
+        let mut files = files
+            .into_iter()
+            .map(|(path, lines)| (path, (lines.join("\n") + "\n").into_bytes()))
+            .collect::<Vec<_>>();
+        files.extend(self.data_files().map(|(name, bytes)| (format!("data/{name}"), bytes.to_vec())));
+
+        Ok(files)
+    }
+
+    /// The single source file of the module or the root of its crate,
+    /// which declares the `modules` holding its imports and functions.
+    fn emit_root(&self, options: &EmitOptions, modules: &[ModuleFile]) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        if let Some(module) = &self.names.module {
+            lines.push(format!("//! Module name: {module}"));
+        }
+
+        if options.safe {
+            lines.push("#![forbid(unsafe_code)]".to_string());
+        }
+
+        if options.instance {
+            lines.extend(self.emit_instance(options, modules));
+        } else {
+            lines.extend(self.emit_items(options, modules));
+        }
+
+        lines
+    }
+
+    /// Declarations of the `modules` of a crate, with the imports that make
+    /// their items visible from the root and from each other.
+    fn emit_module_declarations(modules: &[ModuleFile], options: &EmitOptions) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !modules.is_empty() {
+            lines.push("".to_string());
+        }
+        lines.extend(modules.iter().map(|module| format!("mod {};", module.name)));
+        for module in modules.iter() {
+            // the functions of an instance are methods, found through the
+            // struct, and the traits of its imports are for the host
+            if !options.instance {
+                lines.push(format!("pub(crate) use self::{}::*;", module.name));
+            } else if module.imports {
+                lines.push(format!("pub use self::{}::*;", module.name));
+            }
+        }
+        lines
+    }
+
+    /// The module files of a crate: one for each import module, and the
+    /// functions grouped by the crate their demangled name is from.
+    fn emit_modules(&self, options: &EmitOptions) -> Vec<ModuleFile> {
+        let mut used = BTreeSet::new();
+        let mut modules = Vec::new();
+        let hosts = self.host_traits();
+
+        if options.instance {
+            for host in hosts.iter() {
+                let name = names::assign(&format!("{}_imports", host.module.to_lowercase()), &mut used);
+                modules.push(ModuleFile { name, imports: true, lines: self.emit_host_trait(host) });
+            }
+        } else {
+            for (module, items) in self.import_blocks(options) {
+                let name = names::assign(&format!("{}_imports", module.to_lowercase()), &mut used);
+                modules.push(ModuleFile { name, imports: true, lines: emit_import_block(&module, &items) });
+            }
+        }
+
+        let mut groups: Vec<(String, Vec<&Function>)> = Vec::new();
+        for function in self.functions.values() {
+            if let FunctionKind::Defined(func) = function {
+                let krate = func.debug_name.as_deref().and_then(names::demangle).and_then(|path| names::path_crate(&path).map(str::to_string));
+                let group = krate.map_or_else(|| "functions".to_string(), |krate| format!("{}_functions", krate.to_lowercase()));
+                match groups.iter_mut().find(|(name, _)| *name == group) {
+                    Some((_, functions)) => functions.push(func),
+                    None => groups.push((group, vec![func])),
+                }
+            }
+        }
+
+        for (group, functions) in groups.iter() {
+            for chunk in functions.chunks(FUNCTIONS_PER_MODULE) {
+                let code = chunk.iter().flat_map(|func| func.emit_code(INDENTATION, options));
+                let mut lines = vec!["use super::*;".to_string()];
+                if options.instance {
+                    lines.push("".to_string());
+                    lines.push(emit_impl_header(&hosts));
+                    lines.extend(code.skip(1).map(indent));
+                    lines.push("}".to_string());
+                } else {
+                    lines.extend(code);
+                }
+                modules.push(ModuleFile { name: names::assign(group, &mut used), imports: false, lines });
+            }
+        }
+
+        modules
+    }
+
+    /// The imports grouped by module, in the order the modules are first
+    /// imported from, as declarations in an `extern` block.
+    fn import_blocks(&self, options: &EmitOptions) -> Vec<(String, Vec<String>)> {
+        let mut imports: Vec<(String, Vec<String>)> = Vec::new();
+
+        for import in self.imports.iter() {
+            let (module, items) = match import {
+                Import::Function(index) => match self.get_function_by_index(*index) {
+                    Some(FunctionKind::Imported(func)) => (&func.module, func.emit_code(options)),
+                    _ => continue,
+                },
+                Import::Global(index) => match self.get_global_by_index(*index) {
+                    Some(GlobalKind::Imported(global)) => (&global.module, global.emit_code(options)),
+                    _ => continue,
+                },
+            };
+            match imports.iter_mut().find(|(m, _)| m == module) {
+                Some((_, group)) => group.extend(items),
+                None => imports.push((module.clone(), items)),
+            }
+        }
+
+        imports
+    }
+
+    /// The module as free functions and statics.
+    fn emit_items(&self, options: &EmitOptions, modules: &[ModuleFile]) -> Vec<String> {
         let mut lines = Vec::new();
 
-        // emit no_main; this may change once the start section is supported
-        lines.push("#![no_main]".to_string());
+        // programs run their start function from `main`
+        if !self.is_command() {
+            lines.push("#![no_main]".to_string());
+        }
+
+        if self.get_memory_by_index(0).is_some_and(|memory| memory.shared && !memory.is_sandboxed(options)) {
+            lines.push("// The memory of this module is shared between threads; build with".to_string());
+            lines.push("// `-C target-feature=+atomics,+bulk-memory -C link-arg=--shared-memory`.".to_string());
+        }
+
+        lines.extend(Self::emit_module_declarations(modules, options));
 
         // emit "setup" function
-        lines.extend(self.emit_setup_function());
+        lines.extend(self.emit_setup_function(options));
 
-        // group imports by module
-        self.functions
-            .iter()
-            .filter(|(_, fk)| matches!(fk, FunctionKind::Imported(_)))
-            .map(|(_, fk)| if let FunctionKind::Imported(func) = fk { func } else { unreachable!() })
-            .fold(HashMap::new(), |mut map, func| {
-                map.entry(func.module.clone()).or_insert_with(|| Vec::new()).push(func.clone());
-                map
-            })
+        // the linker calls the constructors listed in these sections before
+        // anything else runs: before `main` or when the library is loaded,
+        // and on wasm32 before every exported call, hence the flag
+        if options.auto_setup {
+            lines.push("".to_string());
+            lines.push("#[used]".to_string());
+            lines.push("#[cfg_attr(not(any(target_vendor = \"apple\", windows)), link_section = \".init_array\")]".to_string());
+            lines.push("#[cfg_attr(target_vendor = \"apple\", link_section = \"__DATA,__mod_init_func\")]".to_string());
+            lines.push("#[cfg_attr(windows, link_section = \".CRT$XCU\")]".to_string());
+            lines.push("static __W2R_SETUP: unsafe extern \"C\" fn() = {".to_string());
+            lines.push(format!("{:INDENTATION$}unsafe extern \"C\" fn __w2r_setup() {{", " "));
+            lines.push(format!("{:width$}static DONE: ::std::sync::atomic::AtomicBool = ::std::sync::atomic::AtomicBool::new(false);", " ", width = 2 * INDENTATION));
+            lines.push(format!("{:width$}if !DONE.swap(true, ::std::sync::atomic::Ordering::SeqCst) {{ setup() }}", " ", width = 2 * INDENTATION));
+            lines.push(format!("{:INDENTATION$}}}", " "));
+            lines.push(format!("{:INDENTATION$}__w2r_setup", " "));
+            lines.push("};".to_string());
+        }
+
+        if self.is_command() {
+            lines.push("".to_string());
+            if options.auto_setup {
+                lines.push("fn main() {}".to_string());
+            } else {
+                lines.push("fn main() {".to_string());
+                lines.push(format!("{:INDENTATION$}unsafe {{ setup() }}", " "));
+                lines.push("}".to_string());
+            }
+        }
+
+        if !options.modules {
+            for (module, items) in self.import_blocks(options) {
+                lines.extend(emit_import_block(&module, &items));
+            }
+        }
+
+        // emit globals
+        self.globals
             .iter()
-            .for_each(|(module, functions)| {
-                lines.push(format!("#[link(wasm_import_module=\"{module}\")]"));
-                lines.push("extern {".to_string());
-                for func in functions {
-                    lines.extend(func.emit_code())
+            .for_each(|(_, gk)| {
+                if let GlobalKind::Defined(global) = gk {
+                    lines.extend(global.emit_code(options));
                 }
-                // code.push(functions.iter().map(|func| func.emit_code()).join("\n"));
-                lines.push("}".to_string());
             });
 
+        // emit emulated and sandboxed memories
+        self.memories
+            .values()
+            .for_each(|memory| lines.extend(memory.emit_code(options)));
+
+        // emit tables
+        self.tables
+            .values()
+            .for_each(|table| lines.extend(table.emit_code(options)));
+
+        // emit passive element segments
+        for (index, (ty, items)) in self.passive_elements.iter() {
+            lines.push("".to_string());
+            lines.push(format!("static mut __w2r_elem{index}: &[{ty}] = &["));
+            lines.extend(items.iter().map(|item| format!("{:INDENTATION$}{},", " ", item.emit_code(options))));
+            lines.push("];".to_string());
+        }
+
+        // emit passive data segments
+        for (index, bytes) in self.passive_data.iter() {
+            lines.push("".to_string());
+            lines.push(format!("static mut {}: &[u8] = {};", self.get_passive_data_by_index(*index).unwrap(), emit_data(*index, bytes, options)));
+        }
+
         // emit functions
-        self.functions
-            .iter()
-            .filter(|(_, fk)| matches!(fk, FunctionKind::Defined(_)))
-            .for_each(|(_, fk)| {
-                if let FunctionKind::Defined(func) = fk {
-                    lines.extend(func.emit_code(INDENTATION));
+        if !options.modules {
+            self.functions
+                .iter()
+                .filter(|(_, fk)| matches!(fk, FunctionKind::Defined(_)))
+                .for_each(|(_, fk)| {
+                    if let FunctionKind::Defined(func) = fk {
+                        lines.extend(func.emit_code(INDENTATION, options));
+                    }
+                });
+        }
+
+        lines
+    }
+
+    /// The module as an `Instance` struct: memories, globals, tables and
+    /// segments become its fields and functions its methods, and imports are
+    /// called on a host implementing one trait per import module.
+    fn emit_instance(&self, options: &EmitOptions, modules: &[ModuleFile]) -> Vec<String> {
+        let mut lines = Self::emit_module_declarations(modules, options);
+        let hosts = self.host_traits();
+
+        if !options.modules {
+            for host in hosts.iter() {
+                lines.push("".to_string());
+                lines.extend(self.emit_host_trait(host));
+            }
+        }
+
+        // fields and the values `new` starts them with
+        let mut fields = vec![("pub host: H".to_string(), vec!["host".to_string()])];
+        for global in self.globals.values() {
+            match global {
+                GlobalKind::Imported(global) => fields.push((format!("{global}: {}", global.ty), vec![format!("{global}: {}", global.ty.zero())])),
+                GlobalKind::Defined(global) => fields.push((global.emit_field(), vec![format!("{global}: {}", global.emit_init(options))])),
+            }
+        }
+        for memory in self.memories.values() {
+            fields.push((format!("{memory}: __W2RMemory"), vec![format!("{memory}: {}", memory.emit_new())]));
+        }
+        for table in self.tables.values() {
+            fields.push((table.emit_field(), table.emit_owned(options)));
+        }
+        for (index, (ty, items)) in self.passive_elements.iter() {
+            let segment = self.get_passive_element_by_index(*index).unwrap();
+            let mut init = vec![format!("{segment}: vec![")];
+            init.extend(items.iter().map(|item| format!("{:INDENTATION$}{},", " ", item.emit_code(options))));
+            init.push("]".to_string());
+            fields.push((format!("{segment}: Vec<{ty}>"), init));
+        }
+        for (index, bytes) in self.passive_data.iter() {
+            let segment = self.get_passive_data_by_index(*index).unwrap();
+            fields.push((format!("{segment}: &'static [u8]"), vec![format!("{segment}: {}", emit_data(*index, bytes, options))]));
+        }
+
+        lines.push("".to_string());
+        lines.push(format!("pub struct Instance<H{}> {{", if hosts.is_empty() { " = ()" } else { "" }));
+        lines.extend(fields.iter().map(|(field, _)| format!("{:INDENTATION$}{field},", " ")));
+        lines.push("}".to_string());
+
+        lines.push("".to_string());
+        lines.push(emit_impl_header(&hosts));
+
+        // the start function may trap in safe code
+        let mut methods = vec![if options.safe {
+            "pub fn new(host: H) -> Result<Self, __W2RTrap> {".to_string()
+        } else {
+            "pub fn new(host: H) -> Self {".to_string()
+        }];
+        methods.push(format!("{:INDENTATION$}let mut instance = Self {{", " "));
+        for (_, init) in fields.iter() {
+            let (last, init) = init.split_last().unwrap();
+            methods.extend(init.iter().map(|line| format!("{:width$}{line}", " ", width = 2 * INDENTATION)));
+            methods.push(format!("{:width$}{last},", " ", width = 2 * INDENTATION));
+        }
+        methods.push(format!("{:INDENTATION$}}};", " "));
+        if options.safe {
+            methods.push(format!("{:INDENTATION$}instance.setup()?;", " "));
+            methods.push(format!("{:INDENTATION$}Ok(instance)", " "));
+        } else {
+            methods.push(format!("{:INDENTATION$}unsafe {{ instance.setup() }};", " "));
+            methods.push(format!("{:INDENTATION$}instance", " "));
+        }
+        methods.push("}".to_string());
+
+        methods.extend(self.emit_setup_function(options));
+
+        for host in hosts.iter() {
+            for (import, method) in host.methods.iter() {
+                if let Import::Function(index) = *import {
+                    if let Some(FunctionKind::Imported(func)) = self.get_function_by_index(index) {
+                        methods.extend(func.emit_method(&host.name, method, options));
+                    }
                 }
-            });
+            }
+        }
 
+        if !options.modules {
+            for function in self.functions.values() {
+                if let FunctionKind::Defined(func) = function {
+                    methods.extend(func.emit_code(INDENTATION, options));
+                }
+            }
+        }
+
+        if options.safe {
+            let code = methods.iter().chain(modules.iter().flat_map(|module| module.lines.iter())).cloned().collect::<Vec<_>>();
+            methods.extend(self.emit_dispatchers(&code, options));
+        }
+
+        lines.extend(methods.into_iter().map(indent));
+        lines.push("}".to_string());
+
+        lines
+    }
+
+    /// The trait an instance calls the imports of `host` through.
+    fn emit_host_trait(&self, host: &HostTrait) -> Vec<String> {
+        let mut lines = vec![
+            format!("/// Imports from the {:?} module.", host.module),
+            format!("pub trait {} {{", host.name),
+        ];
+        for (import, method) in host.methods.iter() {
+            match *import {
+                Import::Function(index) => if let Some(FunctionKind::Imported(func)) = self.get_function_by_index(index) {
+                    lines.push(func.emit_host_method(method));
+                },
+                Import::Global(index) => if let Some(GlobalKind::Imported(global)) = self.get_global_by_index(index) {
+                    lines.extend(global.emit_host_method(method));
+                },
+            }
+        }
+        lines.push("}".to_string());
         lines
     }
 
-    fn emit_setup_function(&self) -> Vec<String> {
+    /// Methods of a safe instance calling the function a `funcref` refers
+    /// to, one for each signature `code` calls indirectly.
+    fn emit_dispatchers(&self, code: &[String], options: &EmitOptions) -> Vec<String> {
+        let mut lines = Vec::new();
+        let code = code.join("\n");
+
+        for (index, ty) in self.types.iter() {
+            let name = format!("__w2r_call_indirect{index}");
+            if !code.contains(&format!("{name}(")) {
+                continue
+            }
+            let params = ty.params.iter().enumerate().map(|(i, param)| format!(", p{i}: {param}")).join("");
+            let args = (0..ty.params.len()).map(|i| format!("p{i}")).join(", ");
+
+            lines.push("".to_string());
+            lines.push(format!("fn {name}(&mut self, func: u32{params}){} {{", ty.emit_result_sig(options)));
+            lines.push(format!("{:INDENTATION$}match func {{", " "));
+            for func in self.functions.values() {
+                if self.canonical_type_index(func.ty()) == Some(*index) {
+                    lines.push(format!("{:width$}{} => self.{}({args}),", " ", func.index(), func.method(), width = 2 * INDENTATION));
+                }
+            }
+            lines.push(format!("{:width$}_ => Err(__W2RTrap::IndirectCallTypeMismatch),", " ", width = 2 * INDENTATION));
+            lines.push(format!("{:INDENTATION$}}}", " "));
+            lines.push("}".to_string());
+        }
+
+        lines
+    }
+
+    /// The trait for each import module, in the order the modules are first
+    /// imported from, with the method it declares for each import.
+    fn host_traits(&self) -> Vec<HostTrait> {
+        let mut used = names::RESERVED_TYPES.iter().map(|name| name.to_string()).collect();
+        let mut hosts: Vec<HostTrait> = Vec::new();
+
+        for import in self.imports.iter() {
+            let (module, name) = match *import {
+                Import::Function(index) => match self.get_function_by_index(index) {
+                    Some(FunctionKind::Imported(func)) => (&func.module, &func.name),
+                    _ => continue,
+                },
+                Import::Global(index) => match self.get_global_by_index(index) {
+                    Some(GlobalKind::Imported(global)) => (&global.module, &global.name),
+                    _ => continue,
+                },
+            };
+            let host = match hosts.iter().position(|host| host.module == *module) {
+                Some(position) => &mut hosts[position],
+                None => {
+                    hosts.push(HostTrait {
+                        module: module.clone(),
+                        name: names::assign_type(module, &mut used),
+                        methods: Vec::new(),
+                        used: BTreeSet::new(),
+                    });
+                    hosts.last_mut().unwrap()
+                },
+            };
+            let method = names::assign(name, &mut host.used);
+            host.methods.push((*import, method));
+        }
+
+        hosts
+    }
+
+    fn emit_setup_function(&self, options: &EmitOptions) -> Vec<String> {
         use Statement::*;
         use Expression::*;
 
         // how many pages do we need to start?
-        let mut statements = vec![if let Some(furthest_index) = self.data.iter().map(|(index, bytes)| *index as usize + bytes.len()).max() {
-            let extra_pages_needed = (furthest_index / (1 << 16)) + 1;
-            Drop(MemoryGrow(Box::new(
-                I32Sub(
-                    Box::new(I32Const(extra_pages_needed as _)),
-                    Box::new(MemorySize),
-                )
-            )))
-        } else {
-            Statement::Nop
-        }];
+        let furthest_index = self.data.values().filter(|(memory, ..)| *memory == 0).map(|(_, offset, bytes)| *offset + bytes.len() as u64).max();
+        let mut statements = Vec::new();
+        match (self.get_memory_by_index(0), furthest_index) {
+            (Some(memory), Some(furthest_index)) if !memory.is_sandboxed(options) => {
+                let pages_needed = (furthest_index / (1 << 16)) + 1;
+                let size = Box::new(MemorySize(memory.clone()));
+                statements.push(Drop(MemoryGrow(memory.clone(), Box::new(if memory.memory64 {
+                    I64Sub(Box::new(I64Const(pages_needed as _)), size)
+                } else {
+                    I32Sub(Box::new(I32Const(pages_needed as _)), size)
+                }))));
+            },
+            _ => {},
+        }
 
-        for (i, (index, bytes)) in self.data.iter().enumerate() {
-            let mut lines = Vec::new();
+        // allocate the emulated and sandboxed memories
+        for memory in self.memories.values() {
+            if let Some(allocation) = memory.emit_allocation(options) {
+                statements.push(RawRust(vec![allocation]));
+            }
+        }
 
+        for (i, (memory, offset, bytes)) in self.data.iter() {
             let byte_len = bytes.len();
+            let bytes = emit_data(*i, bytes, options);
+            let memory = self.get_memory_by_index(*memory);
+            if let Some(memory) = memory.filter(|memory| memory.is_sandboxed(options)) {
+                let slice = memory.emit_slice(&offset.to_string(), &byte_len.to_string(), options);
+                statements.push(RawRust(vec![format!("{slice}.copy_from_slice({bytes});")]));
+                continue;
+            }
 
-            lines.push(format!("let g{i} = ::std::ptr::slice_from_raw_parts_mut({index} as *mut u8, {byte_len});"));
-            lines.push(format!("for (i, byte) in {bytes:?}.iter().enumerate() {{"));
-            lines.push(format!("{:INDENTATION$}(*g{i})[i] = *byte;", " "));
-            lines.push("}".to_string());
+            let pointer = match memory {
+                // address 0 is null to Rust, which only volatile accesses may touch
+                _ if *offset == 0 && byte_len > 0 => {
+                    statements.push(RawRust(vec![
+                        format!("let g{i}: &[u8] = {bytes};"),
+                        format!("::std::ptr::write_volatile(0 as *mut u8, g{i}[0]);"),
+                        format!("::std::ptr::copy_nonoverlapping(g{i}[1..].as_ptr(), 1 as *mut u8, {});", byte_len - 1),
+                    ]));
+                    continue;
+                },
+                _ => format!("{offset} as *mut u8"),
+            };
+            statements.push(RawRust(vec![format!("::std::ptr::copy_nonoverlapping({bytes}.as_ptr(), {pointer}, {byte_len});")]));
+        }
 
-            statements.push(RawRust(lines));
+        // an instance reads the imported globals from its host
+        if options.instance {
+            for host in self.host_traits() {
+                for (import, method) in host.methods.iter() {
+                    if let Import::Global(index) = *import {
+                        if let Some(GlobalKind::Imported(global)) = self.get_global_by_index(index) {
+                            statements.push(RawRust(vec![format!("self.{global} = {}::{method}(&self.host);", host.name)]));
+                        }
+                    }
+                }
+            }
         }
 
+        // globals initialized from imported globals
+        for global in self.globals.values() {
+            if let GlobalKind::Defined(global) = global {
+                if global.initialized_in_setup() {
+                    statements.push(GlobalSet(global.to_string(), global.init.clone()));
+                }
+            }
+        }
+
+        // segments at offsets read from globals, elements first
+        for (segment, (table, offset)) in self.offset_elements.iter() {
+            if let (Some(table), Some((ty, items))) = (self.get_table_by_index(*table), self.passive_elements.get(segment)) {
+                statements.push(TableInit {
+                    table: table.to_string(),
+                    ty: *ty,
+                    segment: self.get_passive_element_by_index(*segment),
+                    dst: offset.clone(),
+                    src: I32Const(0),
+                    len: I32Const(items.len() as i32),
+                });
+                statements.push(ElemDrop(self.get_passive_element_by_index(*segment)));
+            }
+        }
+        for (segment, (memory, offset)) in self.offset_data.iter() {
+            if let Some(bytes) = self.passive_data.get(segment) {
+                statements.push(MemoryInit {
+                    segment: self.get_passive_data_by_index(*segment),
+                    dst: self.address(*memory, offset.clone()),
+                    src: I32Const(0),
+                    len: I32Const(bytes.len() as i32),
+                });
+                statements.push(DataDrop(self.get_passive_data_by_index(*segment)));
+            }
+        }
+
+        // the start function runs last, once memories and globals are initialized
+        if let Some(start) = self.start.and_then(|index| self.get_function_by_index(index)) {
+            statements.push(Statement::Call(Expression::Call(start.clone(), vec![])));
+        }
+
+        // the symbol may be taken by an export of the module itself
+        let export_name = if self.exports_symbol("setup") { "__w2r_setup" } else { "setup" };
+
         let f = Function {
             index: u32::MAX,
             ty: FuncType {
@@ -115,12 +833,18 @@ impl Context {
             },
             locals: vec![],
             statements,
-            exported: true,
-            export_name: Some("setup".to_string()),
+            // an instance sets itself up when it is created, and a module
+            // set up automatically must not be set up again
+            exported: !options.instance && !options.auto_setup,
+            export_name: Some(export_name.to_string()),
             debug_name: None,
+            identifier: Some("setup".to_string()),
+            method: None,
+            local_names: BTreeMap::new(),
+            tail_recursive: false,
         };
 
-        f.emit_code(INDENTATION)
+        f.emit_code(INDENTATION, options)
     }
 }
 
@@ -129,24 +853,63 @@ impl<'a> ContextBuilder<'a> {
         Default::default()
     }
 
-    pub fn build(mut self) -> Context {
+    pub fn build(mut self) -> Result<Context, ParserError<'a>> {
         let mut ty_index = 0;
-        let mut types: HashMap<u32, FuncType> = HashMap::new();
+        let mut types: BTreeMap<u32, FuncType> = BTreeMap::new();
         let mut func_index = 0;
-        let mut functions = HashMap::new();
+        let mut functions = BTreeMap::new();
+        let mut global_index = 0;
+        let mut globals = BTreeMap::new();
+        let mut tag_index = 0;
+        let mut tags = BTreeMap::new();
+        let mut memory_index = 0;
+        let mut memories = BTreeMap::new();
+        let mut table_index = 0;
+        let mut tables = BTreeMap::new();
+        let mut imports_order = Vec::new();
 
         // types
         if let Some(mut tys) = self.types {
-            loop {
-                if let Ok(wasmparser::TypeDef::Func(td)) = tys.read() {
-                    types.insert(ty_index, td.into());
-                } else {
-                    break
-                }
+            while let Ok(wasmparser::TypeDef::Func(td)) = tys.read() {
+                types.insert(ty_index, td.into());
                 ty_index += 1;
             }
         }
         
+        // exports; read up front so references to exported items use their names
+        let mut function_exports = BTreeMap::new();
+        let mut global_exports = BTreeMap::new();
+
+        if let Some(mut exps) = self.exports {
+            loop {
+                match exps.read() {
+                    Ok(wasmparser::Export { name, kind: wasmparser::ExternalKind::Func, index }) => {
+                        function_exports.insert(index, name.to_string());
+                    },
+                    Ok(wasmparser::Export { name, kind: wasmparser::ExternalKind::Global, index }) => {
+                        global_exports.insert(index, name.to_string());
+                    },
+                    Ok(_) => continue,
+                    Err(_) => break
+                }
+            }
+        }
+
+        // functions, statics and the prelude items the code calls share one
+        // namespace, in which exports are named first to keep their names
+        let mut used: BTreeSet<String> = names::RESERVED_ITEMS.iter().map(|name| name.to_string()).collect();
+        let function_export_names = function_exports.iter().map(|(index, name)| (*index, names::assign(name, &mut used))).collect::<BTreeMap<_, _>>();
+        let global_export_names = global_exports.iter().map(|(index, name)| (*index, names::assign_static(name, &mut used))).collect::<BTreeMap<_, _>>();
+        // statics must also stay clear of the variables the emitted code binds
+        let mut identifier = |name: Option<&String>, assign: fn(&str, &mut BTreeSet<String>) -> String| name.map(|name| assign(name, &mut used));
+        // mangled Rust symbols are named after the item at the end of their path
+        let function_names = self
+            .names
+            .functions
+            .iter()
+            .map(|(index, name)| (*index, names::demangle(name).map_or_else(|| name.clone(), |path| names::item_name(&path))))
+            .collect::<BTreeMap<_, _>>();
+
         // imports 
         if let Some(mut imports) = self.imports.clone() {
             loop {
@@ -163,115 +926,312 @@ impl<'a> ContextBuilder<'a> {
                                     index: func_index,
                                     name: name.to_string(),
                                     module: module.to_string(),
-                                    ty: ft.clone()
+                                    ty: ft.clone(),
+                                    identifier: identifier(function_names.get(&func_index), names::assign),
                                 })
                             );
+                            imports_order.push(Import::Function(func_index));
                         }
                         func_index += 1;
                     },
-                    Ok(_) => continue,
+                    Ok(wasmparser::Import {
+                        ty: wasmparser::TypeRef::Global(ty),
+                        module,
+                        name
+                    }) => {
+                        globals.insert(
+                            global_index,
+                            GlobalKind::Imported(ImportedGlobal {
+                                index: global_index,
+                                ty: ty.content_type.into(),
+                                mutable: ty.mutable,
+                                module: module.to_string(),
+                                name: name.to_string(),
+                                identifier: identifier(self.names.globals.get(&global_index), names::assign_static),
+                            })
+                        );
+                        imports_order.push(Import::Global(global_index));
+                        global_index += 1;
+                    },
+                    Ok(wasmparser::Import {
+                        ty: wasmparser::TypeRef::Memory(ty),
+                        ..
+                    }) => {
+                        memories.insert(memory_index, Memory::new(memory_index, ty));
+                        memory_index += 1;
+                    },
+                    // like `__indirect_function_table` in position-independent code
+                    Ok(wasmparser::Import {
+                        ty: wasmparser::TypeRef::Table(ty),
+                        ..
+                    }) => {
+                        tables.insert(table_index, Table::new(table_index, ty));
+                        table_index += 1;
+                    },
+                    Ok(wasmparser::Import {
+                        ty: wasmparser::TypeRef::Tag(ty),
+                        ..
+                    }) => {
+                        if let Some(ft) = types.get(&ty.func_type_idx) {
+                            tags.insert(tag_index, ft.clone());
+                        }
+                        tag_index += 1;
+                    },
                     Err(_) => break,
                 }
             }
         }
 
+        let num_imported_functions = func_index;
+
         // functions
         if let Some(mut funcs) = self.funcs {
             let mut code_iter = self.code_sections.iter();
-            loop {
-                if let Ok(func_type_index) = funcs.read() {
-                    if let Some(code) = code_iter.next() {
-                        if let Some(ft) = types.get(&func_type_index) {
-                            let locals = code.get_locals_reader().expect("Could not get locals reader").into_iter().collect::<wasmparser::Result<Vec<(u32, wasmparser::Type)>>>().expect("locals");
-        
-                            let func = Function {
-                                index: func_index,
-                                ty: ft.clone(),
-                                locals: locals.into_iter().map(|(count, t)| (count, t.into())).collect(),
-                                statements: vec![],
-                                exported: false,
-                                export_name: None,
-                                debug_name: None,
-                            };
-
-                            functions.insert(func_index, FunctionKind::Defined(func));
-                            func_index += 1;
-                        }
+            while let Ok(func_type_index) = funcs.read() {
+                if let Some(code) = code_iter.next() {
+                    if let Some(ft) = types.get(&func_type_index) {
+                        let locals = code.get_locals_reader().expect("Could not get locals reader").into_iter().collect::<wasmparser::Result<Vec<(u32, wasmparser::Type)>>>().expect("locals");
+    
+                        let func = Function {
+                            index: func_index,
+                            ty: ft.clone(),
+                            locals: locals.into_iter().map(|(count, t)| (count, t.into())).collect(),
+                            statements: vec![],
+                            exported: function_exports.contains_key(&func_index),
+                            export_name: function_exports.get(&func_index).cloned(),
+                            debug_name: self.names.functions.get(&func_index).cloned(),
+                            identifier: function_export_names.get(&func_index).cloned().or_else(|| identifier(function_names.get(&func_index), names::assign)),
+                            method: None,
+                            local_names: BTreeMap::new(),
+                            tail_recursive: false,
+                        };
+
+                        functions.insert(func_index, FunctionKind::Defined(func));
+                        func_index += 1;
                     }
-                } else {
-                    break
                 }
             }
         }
 
-        // exports
-        if let Some(mut exps) = self.exports {
-            loop {
-                match exps.read() {
-                    Ok(wasmparser::Export { name, kind: wasmparser::ExternalKind::Func, index }) => {
-                        if let Some(FunctionKind::Defined(f)) = functions.get_mut(&index) {
-                            f.exported = true;
-                            f.export_name = Some(name.to_string());
-                        }
-                    },
-                    Ok(_) => continue,
-                    Err(_) => break
+        // the exports of an instance are methods, named apart from its others
+        let mut methods: BTreeSet<String> = names::RESERVED_METHODS.iter().map(|name| name.to_string()).collect();
+        methods.extend(functions.values().filter(|fk| !matches!(fk, FunctionKind::Defined(func) if func.exported)).map(FunctionKind::method));
+        for fk in functions.values_mut() {
+            if let FunctionKind::Defined(func) = fk {
+                if let Some(name) = func.export_name.as_ref().filter(|_| func.exported) {
+                    func.method = Some(names::assign(name, &mut methods));
                 }
             }
         }
 
-        // convert operators to statements
-        let funcs_copy = functions.clone();
+        // globals
+        if let Some(mut globs) = self.globals {
+            while let Ok(wasmparser::Global { ty, init_expr }) = globs.read() {
+                let init = constant_expression(&init_expr, &types, &functions, &globals)?;
 
-        for (index, fk) in functions.iter_mut() {
-            if let FunctionKind::Defined(func) = fk {
-                let num_imports = if let Some(imps) = self.imports.clone() {
-                    imps.get_count()
-                } else {
-                    0
+                globals.insert(
+                    global_index,
+                    GlobalKind::Defined(Global {
+                        index: global_index,
+                        ty: ty.content_type.into(),
+                        mutable: ty.mutable,
+                        init,
+                        exported: global_exports.contains_key(&global_index),
+                        export_name: global_exports.get(&global_index).cloned(),
+                        identifier: global_export_names.get(&global_index).cloned().or_else(|| identifier(self.names.globals.get(&global_index), names::assign_static)),
+                    })
+                );
+                global_index += 1;
+            }
+        }
+
+        // tables
+        if let Some(mut tabs) = self.tables {
+            while let Ok(ty) = tabs.read() {
+                tables.insert(table_index, Table::new(table_index, ty));
+                table_index += 1;
+            }
+        }
+
+        // element segments; active ones are applied to their table and dropped
+        let mut passive_elements = BTreeMap::new();
+        let mut offset_elements = BTreeMap::new();
+
+        if let Some(mut elements) = self.elements {
+            for segment_index in 0..elements.get_count() {
+                let element = match elements.read() {
+                    Ok(element) => element,
+                    Err(_) => break,
                 };
 
-                if let Some(code) = self.code_sections.get((index - num_imports) as usize) {
-                    let operators: Vec<wasmparser::Operator<'a>> = code.get_operators_reader().expect("Could not get ops reader").into_iter().collect::<wasmparser::Result<Vec<wasmparser::Operator>>>().expect("ops");
-                    let mut iter = operators.into_iter();
-                    func.statements = expression::statements_from_operators(
-                        &mut iter,
-                        &funcs_copy,
-                        None
-                    ).unwrap();
+                let mut reader = element.items.get_items_reader().expect("element items");
+                let mut items = Vec::new();
+                for _ in 0..reader.get_count() {
+                    items.push(match reader.read() {
+                        Ok(wasmparser::ElementItem::Func(index)) => {
+                            ref_func(&types, functions.get(&index).expect("element refers to unknown function"))
+                        },
+                        Ok(wasmparser::ElementItem::Expr(init_expr)) => constant_expression(&init_expr, &types, &functions, &globals)?,
+                        Err(_) => break,
+                    });
+                }
+
+                match element.kind {
+                    wasmparser::ElementKind::Active { table_index, init_expr } => {
+                        match constant_expression(&init_expr, &types, &functions, &globals)? {
+                            Expression::I32Const(offset) => if let Some(table) = tables.get_mut(&table_index) {
+                                table.init(offset as u32 as usize, &items);
+                            },
+                            // like `__table_base` in position-independent code
+                            offset => {
+                                offset_elements.insert(segment_index, (table_index, offset));
+                                passive_elements.insert(segment_index, (element.ty.into(), items));
+                            },
+                        }
+                    },
+                    wasmparser::ElementKind::Passive => {
+                        passive_elements.insert(segment_index, (element.ty.into(), items));
+                    },
+                    wasmparser::ElementKind::Declared => {},
                 }
             }
         }
 
-        // memory; add exported "setup" function to context
-        let memory_size;
+        // tags
+        if let Some(mut tgs) = self.tags {
+            while let Ok(ty) = tgs.read() {
+                if let Some(ft) = types.get(&ty.func_type_idx) {
+                    tags.insert(tag_index, ft.clone());
+                }
+                tag_index += 1;
+            }
+        }
 
+        // memories; emulated ones are allocated by the exported "setup" function
         if let Some(mut memory) = self.memory {
-            memory_size = memory.read().expect("memory type").initial as usize;
-        } else {
-            memory_size = 16;  // this seems to be the default amount for Rust programs
+            while let Ok(ty) = memory.read() {
+                memories.insert(memory_index, Memory::new(memory_index, ty));
+                memory_index += 1;
+            }
         }
 
+        // offsets into 64-bit memories may exceed 32 bits
+        let memory64 = memories.values().any(|memory| memory.memory64);
+        self.code_sections.iter_mut().for_each(|code| code.allow_memarg64(memory64));
+
         // data sections
-        let mut data = Vec::new();
+        let mut data = BTreeMap::new();
+        let mut passive_data = BTreeMap::new();
+        let mut offset_data = BTreeMap::new();
+        let mut data_names = BTreeMap::new();
+        let mut segment_index = 0;
 
         for datum in self.data.iter_mut() {
-            if let Ok(wasmparser::Data { kind: wasmparser::DataKind::Active { memory_index: 0, init_expr }, data: d, .. }) = datum.read() {
-                let mut opreader = init_expr.get_operators_reader();
-                let index = opreader.read();
-                let end = opreader.read();
-                if let (Ok(wasmparser::Operator::I32Const { value }), Ok(wasmparser::Operator::End)) = (index, end) {
-                    data.push((value, d.into()))
+            for _ in 0..datum.get_count() {
+                match datum.read() {
+                    Ok(wasmparser::Data { kind: wasmparser::DataKind::Active { memory_index, init_expr }, data: d, .. }) => {
+                        match constant_expression(&init_expr, &types, &functions, &globals)? {
+                            Expression::I32Const(value) => {
+                                data.insert(segment_index, (memory_index, value as u32 as u64, d.into()));
+                            },
+                            Expression::I64Const(value) => {
+                                data.insert(segment_index, (memory_index, value as u64, d.into()));
+                            },
+                            // like `__memory_base` in position-independent code
+                            offset => {
+                                offset_data.insert(segment_index, (memory_index, offset));
+                                passive_data.insert(segment_index, d.into());
+                                if let Some(name) = identifier(self.names.data.get(&segment_index), names::assign_static) {
+                                    data_names.insert(segment_index, name);
+                                }
+                            },
+                        }
+                    },
+                    Ok(wasmparser::Data { kind: wasmparser::DataKind::Passive, data: d, .. }) => {
+                        passive_data.insert(segment_index, d.into());
+                        if let Some(name) = identifier(self.names.data.get(&segment_index), names::assign_static) {
+                            data_names.insert(segment_index, name);
+                        }
+                    },
+                    Err(_) => break,
                 }
+                segment_index += 1;
             }
         }
 
-        Context {
+        let mut context = Context {
             functions,
             types,
-            memory_size,
-            data
+            globals,
+            tables,
+            memories,
+            data,
+            passive_data,
+            passive_elements,
+            offset_elements,
+            offset_data,
+            tags,
+            start: self.start,
+            imports: imports_order,
+            names: self.names.clone(),
+            data_names,
+            features: BTreeSet::new(),
+        };
+
+        // a module may also just pass `v128` values around
+        let v128 = context.types.values().flat_map(|ty| ty.params.iter().chain(ty.returns.iter())).any(|ty| *ty == WASMType::V128)
+            || context.globals.values().any(|global| matches!(
+                global,
+                GlobalKind::Defined(Global { ty: WASMType::V128, .. }) | GlobalKind::Imported(ImportedGlobal { ty: WASMType::V128, .. })
+            ))
+            || context.functions.values().any(|fk| matches!(fk, FunctionKind::Defined(func) if func.locals.iter().any(|(_, ty)| *ty == WASMType::V128)));
+        if v128 {
+            context.features.insert(Feature::Simd);
         }
+
+        // locals must not shadow any of the items named above
+        for fk in context.functions.values_mut() {
+            if let FunctionKind::Defined(func) = fk {
+                if let Some(locals) = self.names.locals.get(&func.index) {
+                    let mut used = used.clone();
+                    used.extend(names::RESERVED_LOCALS.iter().map(|name| name.to_string()));
+                    func.local_names = locals.iter().map(|(index, name)| (*index, names::assign(name, &mut used))).collect();
+                }
+            }
+        }
+
+        // convert operators to statements
+        let module = context.clone();
+
+        for (index, fk) in context.functions.iter_mut() {
+            if let FunctionKind::Defined(func) = fk {
+                if let Some(code) = self.code_sections.get((index - num_imported_functions) as usize) {
+                    let reader = code.get_operators_reader().expect("Could not get ops reader");
+                    let mut body = reader.get_binary_reader();
+                    let start = body.original_position();
+                    let bytes = body.read_bytes(body.bytes_remaining()).expect("function body");
+                    let operators: Vec<wasmparser::Operator<'a>> = reader
+                        .into_iter_with_offsets()
+                        .map(|op| op.map(|(mut op, position)| {
+                            reread_memarg(&mut op, &bytes[position - start..]);
+                            op
+                        }))
+                        .collect::<wasmparser::Result<Vec<wasmparser::Operator>>>()
+                        .expect("ops");
+                    context.features.extend(operators.iter().filter_map(expression::operator_feature));
+                    func.tail_recursive = operators.iter().any(|op| matches!(op, wasmparser::Operator::ReturnCall { function_index } if function_index == index));
+                    let mut iter = operators.into_iter();
+                    func.statements = expression::statements_from_operators(
+                        &mut iter,
+                        &module,
+                        *index,
+                        &func.ty,
+                    )?;
+                }
+            }
+        }
+
+        Ok(context)
     }
 
     pub fn set_types(mut self, types: wasmparser::TypeSectionReader<'a>) -> Self {
@@ -304,10 +1264,40 @@ impl<'a> ContextBuilder<'a> {
         self
     }
 
+    pub fn set_globals(mut self, globals: wasmparser::GlobalSectionReader<'a>) -> Self {
+        self.globals = Some(globals);
+        self
+    }
+
+    pub fn set_tables(mut self, tables: wasmparser::TableSectionReader<'a>) -> Self {
+        self.tables = Some(tables);
+        self
+    }
+
+    pub fn set_elements(mut self, elements: wasmparser::ElementSectionReader<'a>) -> Self {
+        self.elements = Some(elements);
+        self
+    }
+
+    pub fn set_tags(mut self, tags: wasmparser::TagSectionReader<'a>) -> Self {
+        self.tags = Some(tags);
+        self
+    }
+
     pub fn add_data_section(mut self, data_section: wasmparser::DataSectionReader<'a>) -> Self {
         self.data.push(data_section);
         self
     }
+
+    pub fn set_start(mut self, func: u32) -> Self {
+        self.start = Some(func);
+        self
+    }
+
+    pub fn set_names(mut self, names: Names) -> Self {
+        self.names = names;
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -316,33 +1306,24 @@ pub struct ImportedFunction {
     pub ty: FuncType,
     pub module: String,
     pub name: String,
+    /// Identifier derived from the `name` section.
+    pub identifier: Option<String>,
 }
 
 impl ImportedFunction {
-    pub fn emit_code(&self) -> Vec<String> {
-        let mut code = Vec::new();
-
-        code.push(self.emit_signature(INDENTATION));
-
-        code
+    pub fn emit_code(&self, options: &EmitOptions) -> Vec<String> {
+        vec![self.emit_signature(INDENTATION, options)]
     }
 
-    fn emit_signature(&self, indentation: usize) -> String {
-        let return_sig = match self.ty.returns.len() {
-            0 => "".to_string(),
-            1 => {
-                format!("-> {}", &self.ty.returns[0])
-            },
-            _ => unimplemented!()
-        };
-
+    fn emit_signature(&self, indentation: usize, options: &EmitOptions) -> String {
         format!(
-            "{:>indentation$}#[link_name=\"{}\"]\n    fn {}({}) {};",
+            "{:>indentation$}#[link_name=\"{}\"]\n    {}fn {}({}){};",
             " ",
             self.name,
+            options.visibility(),
             self,
             self.emit_param_types(),
-            return_sig
+            self.ty.emit_return_sig()
         )
     }
 
@@ -351,11 +1332,43 @@ impl ImportedFunction {
             format!("p{}: {}", i, param)
         }).join(", ")
     }
+
+    /// Declaration of this import in the trait of its module.
+    pub fn emit_host_method(&self, method: &str) -> String {
+        format!("{:>INDENTATION$}fn {method}(&mut self{}){};", " ", self.emit_method_params(), self.ty.emit_return_sig())
+    }
+
+    /// Method of an instance passing calls to this import on to its host.
+    pub fn emit_method(&self, host: &str, method: &str, options: &EmitOptions) -> Vec<String> {
+        let args = (0..self.ty.params.len()).map(|i| format!(", p{i}")).join("");
+        let call = format!("{host}::{method}(&mut self.host{args})");
+        vec![
+            "".to_string(),
+            format!(
+                "{}fn {self}(&mut self{}){} {{",
+                if options.safe { "" } else { "unsafe " },
+                self.emit_method_params(),
+                self.ty.emit_result_sig(options)
+            ),
+            format!("{:>INDENTATION$}{}", " ", if options.safe { format!("Ok({call})") } else { call }),
+            "}".to_string(),
+        ]
+    }
+
+    fn emit_method_params(&self) -> String {
+        match self.emit_param_types().as_str() {
+            "" => String::new(),
+            params => format!(", {params}"),
+        }
+    }
 }
 
 impl Display for ImportedFunction {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "__w2r_f{}", self.index)
+        match &self.identifier {
+            Some(identifier) => write!(f, "{identifier}"),
+            None => write!(f, "__w2r_f{}", self.index),
+        }
     }
 }
 
@@ -366,6 +1379,30 @@ pub enum FunctionKind {
     // table function?
 }
 
+impl FunctionKind {
+    pub fn ty(&self) -> &FuncType {
+        match self {
+            Self::Defined(func) => &func.ty,
+            Self::Imported(func) => &func.ty,
+        }
+    }
+
+    pub fn index(&self) -> u32 {
+        match self {
+            Self::Defined(func) => func.index,
+            Self::Imported(func) => func.index,
+        }
+    }
+
+    /// Name of this function as a method of an instance.
+    pub fn method(&self) -> String {
+        match self {
+            Self::Defined(func) => func.method(),
+            Self::Imported(func) => func.to_string(),
+        }
+    }
+}
+
 impl Display for FunctionKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
@@ -373,4 +1410,275 @@ impl Display for FunctionKind {
             Self::Imported(func) => write!(f, "{}", func),
         }
     }
-}
\ No newline at end of file
+}
+
+/// The bytes of data segment `index`, as a `&[u8; N]` byte string literal
+/// or included from the file named by [`data_file_name`].
+fn emit_data(index: u32, bytes: &[u8], options: &EmitOptions) -> String {
+    match &options.data_dir {
+        Some(dir) => format!("include_bytes!({:?})", format!("{dir}/{}", data_file_name(index))),
+        None => format!("b\"{}\"", bytes.escape_ascii()),
+    }
+}
+
+/// Most functions in one module file of a crate.
+const FUNCTIONS_PER_MODULE: usize = 256;
+
+/// Size of the stack of a crate, the default of rustc for wasm32.
+const STACK_SIZE: u64 = 1 << 20;
+
+/// A module file of a crate, holding the declarations of one import module
+/// or a group of functions.
+struct ModuleFile {
+    name: String,
+    imports: bool,
+    lines: Vec<String>,
+}
+
+/// Settings the emitted code follows from those asked for: safe code is an
+/// instance that returns its traps, and an instance owns its memories, so
+/// they cannot be native.
+fn implied_options(options: &EmitOptions) -> EmitOptions {
+    let instance = options.instance || options.safe;
+    EmitOptions {
+        trapping: options.trapping || options.safe,
+        sandboxed: options.sandboxed || instance,
+        instance,
+        ..options.clone()
+    }
+}
+
+/// Append the runtime helpers that `code` references to `lines`.
+fn emit_runtime(lines: &mut Vec<String>, code: &[String], options: &EmitOptions) {
+    lines.extend(runtime::emit_helpers(code, options));
+
+    // `memory.atomic.wait`/`notify` intrinsics are still unstable
+    if code.iter().any(|line| line.contains("::std::arch::wasm32::memory_atomic_")) {
+        lines.insert(0, "#![feature(stdarch_wasm_atomic_wait)]".to_string());
+    }
+}
+
+/// The `extern` block declaring the `items` imported from `module`.
+fn emit_import_block(module: &str, items: &[String]) -> Vec<String> {
+    let mut lines = vec![format!("#[link(wasm_import_module=\"{module}\")]"), "extern {".to_string()];
+    lines.extend(items.iter().cloned());
+    lines.push("}".to_string());
+    lines
+}
+
+/// The `impl` block header of an instance calling the imports of `hosts`.
+fn emit_impl_header(hosts: &[HostTrait]) -> String {
+    if hosts.is_empty() {
+        "impl<H> Instance<H> {".to_string()
+    } else {
+        format!("impl<H: {}> Instance<H> {{", hosts.iter().map(|host| &host.name).join(" + "))
+    }
+}
+
+/// `line` indented one level, blank lines staying blank.
+fn indent(line: String) -> String {
+    if line.is_empty() {
+        line
+    } else {
+        format!("{:INDENTATION$}{line}", " ")
+    }
+}
+
+/// Name of the file holding data segment `index` in the data directory.
+pub fn data_file_name(index: u32) -> String {
+    format!("data{index}.bin")
+}
+
+/// Re-read the memory immediate of `op` from `bytes`, its encoding. The
+/// multi-memory proposal settled on the memory index coming before the
+/// offset, where wasmparser 0.85 reads it after.
+fn reread_memarg(op: &mut wasmparser::Operator, bytes: &[u8]) {
+    use wasmparser::Operator::*;
+
+    let memarg = match op {
+        I32Load { memarg, .. }
+        | I64Load { memarg, .. }
+        | F32Load { memarg, .. }
+        | F64Load { memarg, .. }
+        | I32Load8S { memarg, .. }
+        | I32Load8U { memarg, .. }
+        | I32Load16S { memarg, .. }
+        | I32Load16U { memarg, .. }
+        | I64Load8S { memarg, .. }
+        | I64Load8U { memarg, .. }
+        | I64Load16S { memarg, .. }
+        | I64Load16U { memarg, .. }
+        | I64Load32S { memarg, .. }
+        | I64Load32U { memarg, .. }
+        | I32Store { memarg, .. }
+        | I64Store { memarg, .. }
+        | F32Store { memarg, .. }
+        | F64Store { memarg, .. }
+        | I32Store8 { memarg, .. }
+        | I32Store16 { memarg, .. }
+        | I64Store8 { memarg, .. }
+        | I64Store16 { memarg, .. }
+        | I64Store32 { memarg, .. }
+        | MemoryAtomicNotify { memarg, .. }
+        | MemoryAtomicWait32 { memarg, .. }
+        | MemoryAtomicWait64 { memarg, .. }
+        | I32AtomicLoad { memarg, .. }
+        | I64AtomicLoad { memarg, .. }
+        | I32AtomicLoad8U { memarg, .. }
+        | I32AtomicLoad16U { memarg, .. }
+        | I64AtomicLoad8U { memarg, .. }
+        | I64AtomicLoad16U { memarg, .. }
+        | I64AtomicLoad32U { memarg, .. }
+        | I32AtomicStore { memarg, .. }
+        | I64AtomicStore { memarg, .. }
+        | I32AtomicStore8 { memarg, .. }
+        | I32AtomicStore16 { memarg, .. }
+        | I64AtomicStore8 { memarg, .. }
+        | I64AtomicStore16 { memarg, .. }
+        | I64AtomicStore32 { memarg, .. }
+        | I32AtomicRmwAdd { memarg, .. }
+        | I64AtomicRmwAdd { memarg, .. }
+        | I32AtomicRmw8AddU { memarg, .. }
+        | I32AtomicRmw16AddU { memarg, .. }
+        | I64AtomicRmw8AddU { memarg, .. }
+        | I64AtomicRmw16AddU { memarg, .. }
+        | I64AtomicRmw32AddU { memarg, .. }
+        | I32AtomicRmwSub { memarg, .. }
+        | I64AtomicRmwSub { memarg, .. }
+        | I32AtomicRmw8SubU { memarg, .. }
+        | I32AtomicRmw16SubU { memarg, .. }
+        | I64AtomicRmw8SubU { memarg, .. }
+        | I64AtomicRmw16SubU { memarg, .. }
+        | I64AtomicRmw32SubU { memarg, .. }
+        | I32AtomicRmwAnd { memarg, .. }
+        | I64AtomicRmwAnd { memarg, .. }
+        | I32AtomicRmw8AndU { memarg, .. }
+        | I32AtomicRmw16AndU { memarg, .. }
+        | I64AtomicRmw8AndU { memarg, .. }
+        | I64AtomicRmw16AndU { memarg, .. }
+        | I64AtomicRmw32AndU { memarg, .. }
+        | I32AtomicRmwOr { memarg, .. }
+        | I64AtomicRmwOr { memarg, .. }
+        | I32AtomicRmw8OrU { memarg, .. }
+        | I32AtomicRmw16OrU { memarg, .. }
+        | I64AtomicRmw8OrU { memarg, .. }
+        | I64AtomicRmw16OrU { memarg, .. }
+        | I64AtomicRmw32OrU { memarg, .. }
+        | I32AtomicRmwXor { memarg, .. }
+        | I64AtomicRmwXor { memarg, .. }
+        | I32AtomicRmw8XorU { memarg, .. }
+        | I32AtomicRmw16XorU { memarg, .. }
+        | I64AtomicRmw8XorU { memarg, .. }
+        | I64AtomicRmw16XorU { memarg, .. }
+        | I64AtomicRmw32XorU { memarg, .. }
+        | I32AtomicRmwXchg { memarg, .. }
+        | I64AtomicRmwXchg { memarg, .. }
+        | I32AtomicRmw8XchgU { memarg, .. }
+        | I32AtomicRmw16XchgU { memarg, .. }
+        | I64AtomicRmw8XchgU { memarg, .. }
+        | I64AtomicRmw16XchgU { memarg, .. }
+        | I64AtomicRmw32XchgU { memarg, .. }
+        | I32AtomicRmwCmpxchg { memarg, .. }
+        | I64AtomicRmwCmpxchg { memarg, .. }
+        | I32AtomicRmw8CmpxchgU { memarg, .. }
+        | I32AtomicRmw16CmpxchgU { memarg, .. }
+        | I64AtomicRmw8CmpxchgU { memarg, .. }
+        | I64AtomicRmw16CmpxchgU { memarg, .. }
+        | I64AtomicRmw32CmpxchgU { memarg, .. }
+        | V128Load { memarg, .. }
+        | V128Load8x8S { memarg, .. }
+        | V128Load8x8U { memarg, .. }
+        | V128Load16x4S { memarg, .. }
+        | V128Load16x4U { memarg, .. }
+        | V128Load32x2S { memarg, .. }
+        | V128Load32x2U { memarg, .. }
+        | V128Load8Splat { memarg, .. }
+        | V128Load16Splat { memarg, .. }
+        | V128Load32Splat { memarg, .. }
+        | V128Load64Splat { memarg, .. }
+        | V128Load32Zero { memarg, .. }
+        | V128Load64Zero { memarg, .. }
+        | V128Store { memarg, .. }
+        | V128Load8Lane { memarg, .. }
+        | V128Load16Lane { memarg, .. }
+        | V128Load32Lane { memarg, .. }
+        | V128Load64Lane { memarg, .. }
+        | V128Store8Lane { memarg, .. }
+        | V128Store16Lane { memarg, .. }
+        | V128Store32Lane { memarg, .. }
+        | V128Store64Lane { memarg, .. } => memarg,
+        _ => return,
+    };
+
+    let mut reader = wasmparser::BinaryReader::new(bytes);
+    // the opcode, followed by a sub-opcode for the prefixed ones
+    if matches!(reader.read_u8(), Ok(0xfc..=0xfe)) && reader.read_var_u32().is_err() {
+        return
+    }
+    let Ok(flags) = reader.read_var_u32() else {
+        return
+    };
+    let memory = if flags & (1 << 6) != 0 {
+        match reader.read_var_u32() {
+            Ok(memory) => memory,
+            Err(_) => return,
+        }
+    } else {
+        0
+    };
+    if let Ok(offset) = reader.read_var_u64() {
+        memarg.memory = memory;
+        memarg.offset = offset;
+    }
+}
+
+/// Lowest type index with the signature `ty`; indirect calls compare
+/// signatures structurally, so equal types share one index.
+fn canonical_type_index(types: &BTreeMap<u32, FuncType>, ty: &FuncType) -> Option<u32> {
+    types.iter().filter(|(_, t)| *t == ty).map(|(index, _)| *index).min()
+}
+
+fn ref_func(types: &BTreeMap<u32, FuncType>, func: &FunctionKind) -> Expression {
+    let ty = canonical_type_index(types, func.ty()).expect("function type missing from type section");
+    Expression::RefFunc(func.clone(), ty)
+}
+
+/// Value of a constant expression, as used by global initializers and
+/// element segment items. A defined global read by the expression is
+/// replaced by its own, constant, initializer.
+fn constant_expression<'a>(
+    init_expr: &wasmparser::InitExpr<'a>,
+    types: &BTreeMap<u32, FuncType>,
+    functions: &BTreeMap<u32, FunctionKind>,
+    globals: &BTreeMap<u32, GlobalKind>,
+) -> Result<Expression, ParserError<'a>> {
+    let mut opreader = init_expr.get_operators_reader();
+    let operator = opreader.read().expect("constant expression");
+    let expression = match (&operator, opreader.read()) {
+        (wasmparser::Operator::I32Const { value }, Ok(wasmparser::Operator::End)) => Expression::I32Const(*value),
+        (wasmparser::Operator::I64Const { value }, Ok(wasmparser::Operator::End)) => Expression::I64Const(*value),
+        (wasmparser::Operator::F32Const { value }, Ok(wasmparser::Operator::End)) => Expression::F32Const(value.bits()),
+        (wasmparser::Operator::F64Const { value }, Ok(wasmparser::Operator::End)) => Expression::F64Const(value.bits()),
+        (wasmparser::Operator::V128Const { value }, Ok(wasmparser::Operator::End)) => Expression::V128Const(u128::from_le_bytes(*value.bytes())),
+        (wasmparser::Operator::RefNull { .. }, Ok(wasmparser::Operator::End)) => Expression::RefNull,
+        (wasmparser::Operator::RefFunc { function_index }, Ok(wasmparser::Operator::End)) => match functions.get(function_index) {
+            Some(func) => ref_func(types, func),
+            None => return Err(ParserError::Invalid { expressions: vec![], statements: vec![], operator }),
+        },
+        (wasmparser::Operator::GlobalGet { global_index }, Ok(wasmparser::Operator::End)) => match globals.get(global_index) {
+            Some(GlobalKind::Defined(global)) => global.init.clone(),
+            Some(global) => Expression::GlobalGet(global.to_string()),
+            None => return Err(ParserError::Invalid { expressions: vec![], statements: vec![], operator }),
+        },
+        // like the arithmetic of the extended constant expressions proposal
+        _ => {
+            use wasmparser::Operator::*;
+            let unsupported = init_expr.get_operators_reader().into_iter().flatten().find(|op| !matches!(
+                op,
+                I32Const { .. } | I64Const { .. } | F32Const { .. } | F64Const { .. } | V128Const { .. } | RefNull { .. } | RefFunc { .. } | GlobalGet { .. } | End
+            ));
+            return Err(ParserError::Unimplemented(unsupported.unwrap_or(operator)))
+        },
+    };
+    Ok(expression)
+}
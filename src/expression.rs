@@ -1,8 +1,14 @@
-use std::collections::HashMap;
-use crate::statement::Statement;
-use crate::context::FunctionKind;
-use wasmparser::{Operator, BlockType};
+use std::collections::BTreeSet;
+use crate::options::EmitOptions;
+use crate::statement::{Catch, Escape, Statement};
+use crate::context::{Context, Feature, FunctionKind};
+use crate::func_type::FuncType;
+use crate::memory::Memory;
+use crate::names;
+use crate::wasm_type::WASMType;
+use wasmparser::{Operator, BlockType, MemoryImmediate};
 use itertools::Itertools;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 
 
 #[allow(dead_code)]
@@ -11,9 +17,24 @@ pub enum Expression {
     Select(Box<Expression>, Box<Expression>, Box<Expression>),
     LocalGet(String),
     LocalTee(String, Box<Expression>),
-    IfElse(Box<Expression>, Box<Expression>),
+    GlobalGet(String),
+    IfElse {
+        cond: Box<Expression>,
+        then_stmts: Vec<Statement>,
+        then_value: Option<Box<Expression>>,
+        else_stmts: Vec<Statement>,
+        else_value: Option<Box<Expression>>,
+        /// Label of the block, if branched to.
+        label: Option<String>,
+    },
     Call(FunctionKind, Vec<Expression>),
-    CallIndirect(Vec<Expression>),
+    CallIndirect {
+        table: String,
+        type_index: u32,
+        ty: FuncType,
+        args: Vec<Expression>,
+        index: Box<Expression>,
+    },
     I32Load(Box<Expression>, u8, u64),
     I64Load(Box<Expression>, u8, u64),
     F32Load(Box<Expression>, u8, u64),
@@ -156,47 +177,130 @@ pub enum Expression {
     I64Extend8S(Box<Expression>),
     I64Extend16S(Box<Expression>),
     I64Extend32S(Box<Expression>),
+    AtomicLoad(AtomicAccess, Box<Expression>, u64),
+    AtomicRmw(AtomicAccess, &'static str, Box<Expression>, Box<Expression>, u64),
+    AtomicCmpxchg(AtomicAccess, Box<Expression>, Box<Expression>, Box<Expression>, u64),
+    MemoryAtomicNotify(Box<Expression>, Box<Expression>, u64),
+    MemoryAtomicWait32(Box<Expression>, Box<Expression>, Box<Expression>, u64),
+    MemoryAtomicWait64(Box<Expression>, Box<Expression>, Box<Expression>, u64),
+    V128Const(u128),
+    /// SIMD load from `addr + offset`; lane loads also take the vector they update.
+    SimdLoad(SimdAccess, Box<Expression>, Option<Box<Expression>>, u64),
+    /// Call of a SIMD intrinsic with its const lane arguments.
+    Simd(SimdIntrinsic, Vec<u8>, Vec<Expression>),
+    I32TruncSatF32S(Box<Expression>),
+    I32TruncSatF32U(Box<Expression>),
+    I32TruncSatF64S(Box<Expression>),
+    I32TruncSatF64U(Box<Expression>),
+    I64TruncSatF32S(Box<Expression>),
+    I64TruncSatF32U(Box<Expression>),
+    I64TruncSatF64S(Box<Expression>),
+    I64TruncSatF64U(Box<Expression>),
     I32Const(i32),  // or u32?
     I64Const(i64),  // or u64?
     F32Const(u32),
     F64Const(u64),
-    MemoryGrow(Box<Expression>),
-    MemorySize,
+    MemoryGrow(Memory, Box<Expression>),
+    MemorySize(Memory),
+    /// Address into an emulated memory, as a pointer.
+    MemoryAddress(Memory, Box<Expression>),
+    Tuple(Vec<Expression>),
+    RefNull,
+    RefIsNull(Box<Expression>),
+    /// Reference to a function, tagged with its canonical type index.
+    RefFunc(FunctionKind, u32),
+    TableGet(String, Box<Expression>),
+    TableSize(String),
+    /// Grow a table by a number of copies of a reference, up to its maximum size.
+    TableGrow(String, Box<Expression>, Box<Expression>, u32),
 }
 
-impl<'a> Expression {
-    pub fn emit_code(&self) -> String {
+impl Expression {
+    pub fn emit_code(&self, options: &EmitOptions) -> String {
+        if let Some(load) = self.emit_sandboxed_load(options) {
+            return load
+        }
+
         match self {
             Self::Select(expr1, expr2, cond) => format!(
-                "{{ let e1 = {}; let e2 = {}; if {} == 1 {{ e1 }} else {{ e2 }} }}",
-                expr1.emit_code(),
-                expr2.emit_code(),
-                cond.emit_code()
-            ),
-            Self::LocalGet(name) => format!(
-                "{}",
-                name
+                "{{ let e1 = {}; let e2 = {}; if {} != 0 {{ e1 }} else {{ e2 }} }}",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
+                cond.emit_code(options)
             ),
+            Self::LocalGet(name) => name.to_string(),
+            Self::GlobalGet(name) => options.state(name),
             Self::LocalTee(name, expr) => format!(
                 "{{ {} = {}; {} }}",
                 name,
-                expr.emit_code(),
+                expr.emit_code(options),
                 name
             ),
-            // Self::IfElse(Box<Expression>, Box<Expression>),
+            Self::IfElse { cond, then_stmts, then_value, else_stmts, else_value, label } => {
+                let branch = |stmts: &[Statement], value: &Option<Box<Expression>>| {
+                    let mut code = stmts.iter()
+                        .flat_map(|stmt| stmt.emit_code(0, options))
+                        .map(|line| line.trim().to_string())
+                        .collect::<Vec<_>>();
+                    match (value, label) {
+                        (Some(value), Some(label)) => code.push(format!("break '{label} {};", value.emit_code(options))),
+                        (Some(value), None) => code.push(value.emit_code(options)),
+                        (None, _) => {},
+                    }
+                    code.join(" ")
+                };
+                let code = format!(
+                    "if {} != 0 {{ {} }} else {{ {} }}",
+                    cond.emit_code(options),
+                    branch(then_stmts, then_value),
+                    branch(else_stmts, else_value),
+                );
+                match label {
+                    Some(label) => format!("('{label}: loop {{ {code} }})"),
+                    None => format!("({code})"),
+                }
+            },
+            Self::Call(func, args) if options.instance => emit_method_call(func, args, options),
             Self::Call(func, args) => format!(
                 "{}({})",
                 func,
-                args.iter().map(|arg| arg.emit_code()).join(",")
+                args.iter().map(|arg| arg.emit_code(options)).join(",")
             ),
-            // Self::CallIndirect(Vec<Expression>),
+            Self::CallIndirect { table, type_index, ty, args, index } => {
+                // bind the operands first to keep wasm's evaluation order
+                let names = (0..args.len()).map(|i| format!("a{i}")).chain(["i".to_string()]).collect::<Vec<_>>();
+                let values = args.iter().chain([index.as_ref()]).map(|expr| expr.emit_code(options)).collect::<Vec<_>>();
+                let (mismatch, undefined) = if options.trapping {
+                    (options.trap("IndirectCallTypeMismatch"), options.trap("UndefinedElement"))
+                } else {
+                    ("panic!(\"indirect call type mismatch\")".to_string(), "panic!(\"undefined element\")".to_string())
+                };
+                // safe code calls through the instance's dispatcher for the type
+                if options.safe {
+                    return format!(
+                        "{{ let {} = {}; match {}.get(i as u32 as usize).copied() {{ Some(Some(f)) if f.ty == {type_index} => self.__w2r_call_indirect{type_index}({})?, Some(Some(_)) => {mismatch}, _ => {undefined} }} }}",
+                        if args.is_empty() { names[0].clone() } else { format!("({})", names.join(", ")) },
+                        if args.is_empty() { values[0].clone() } else { format!("({})", values.join(", ")) },
+                        options.state(table),
+                        std::iter::once("f.func".to_string()).chain(names[..args.len()].iter().cloned()).join(", "),
+                    )
+                }
+                format!(
+                    "{{ let {} = {}; match (&*::std::ptr::addr_of!({})).get(i as u32 as usize) {{ Some(Some(f)) if f.ty == {type_index} => ::std::mem::transmute::<unsafe fn(), {}>(f.func)({}), Some(Some(_)) => {mismatch}, _ => {undefined} }} }}",
+                    if args.is_empty() { names[0].clone() } else { format!("({})", names.join(", ")) },
+                    if args.is_empty() { values[0].clone() } else { format!("({})", values.join(", ")) },
+                    options.state(table),
+                    ty.emit_pointer_type(options),
+                    options.instance.then(|| "self".to_string()).into_iter().chain(names[..args.len()].iter().cloned()).join(", "),
+                )
+            },
             Self::I32Load(expr, align, offset) => {
                 let method = if *align == 2 {
                     "read"
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
                 if *offset == 0 {
                     format!("({expr_code} as *const i32).{method}()")
                 } else {
@@ -209,7 +313,7 @@ impl<'a> Expression {
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
 
                 if *offset == 0 {
                     format!("({expr_code} as *const i64).{method}()")
@@ -223,7 +327,7 @@ impl<'a> Expression {
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
 
                 if *offset == 0 {
                     format!("({expr_code} as *const f32).{method}()")
@@ -237,7 +341,7 @@ impl<'a> Expression {
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
 
                 if *offset == 0 {
                     format!("({expr_code} as *const f64).{method}()")
@@ -246,7 +350,7 @@ impl<'a> Expression {
                 }
             },
             Self::I32Load8S(expr, _, offset) => {
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
                 let method = "read";
 
                 if *offset == 0 {
@@ -256,13 +360,13 @@ impl<'a> Expression {
                 }
             },
             Self::I32Load8U(expr, _, offset) => {
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
                 let method = "read";
 
                 if *offset == 0 {
-                    format!("(({expr_code} as *const i8).{method}() as i32)")
+                    format!("(({expr_code} as *const u8).{method}() as i32)")
                 } else {
-                    format!("(({expr_code} as *const i8).add({offset}).{method}() as i32)")
+                    format!("(({expr_code} as *const u8).add({offset}).{method}() as i32)")
                 }
             },
             Self::I32Load16S(expr, align, offset) => {
@@ -271,7 +375,7 @@ impl<'a> Expression {
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
 
                 if *offset == 0 {
                     format!("(({expr_code} as *const i16).{method}() as i32)")
@@ -285,7 +389,7 @@ impl<'a> Expression {
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
 
                 if *offset == 0 {
                     format!("(({expr_code} as *const u16).{method}() as i32)")
@@ -294,7 +398,7 @@ impl<'a> Expression {
                 }
             },
             Self::I64Load8S(expr, _, offset) => {
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
                 let method = "read";
 
                 if *offset == 0 {
@@ -304,7 +408,7 @@ impl<'a> Expression {
                 }
             },
             Self::I64Load8U(expr, _, offset) => {
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
                 let method = "read";
 
                 if *offset == 0 {
@@ -319,7 +423,7 @@ impl<'a> Expression {
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
 
                 if *offset == 0 {
                     format!("(({expr_code} as *const i16).{method}() as i64)")
@@ -333,7 +437,7 @@ impl<'a> Expression {
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
 
                 if *offset == 0 {
                     format!("(({expr_code} as *const u16).{method}() as i64)")
@@ -347,7 +451,7 @@ impl<'a> Expression {
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
 
                 if *offset == 0 {
                     format!("(({expr_code} as *const i32).{method}() as i64)")
@@ -361,7 +465,7 @@ impl<'a> Expression {
                 } else {
                     "read_unaligned"
                 };
-                let expr_code = expr.emit_code();
+                let expr_code = expr.emit_code(options);
 
                 if *offset == 0 {
                     format!("(({expr_code} as *const u32).{method}() as i64)")
@@ -371,519 +475,659 @@ impl<'a> Expression {
             },
             Self::I32Eqz(expr) => format!(
                 "(({} == 0i32) as i32)",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
             Self::I32Eq(expr1, expr2) => format!(
                 "(({} == {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32Ne(expr1, expr2) => format!(
                 "(({} != {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32LtS(expr1, expr2) => format!(
                 "(({} < {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32LtU(expr1, expr2) => format!(
                 "((({} as u32) < ({} as u32)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32GtS(expr1, expr2) => format!(
                 "(({} > {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32GtU(expr1, expr2) => format!(
                 "((({} as u32) > ({} as u32)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32LeS(expr1, expr2) => format!(
                 "(({} <= {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32LeU(expr1, expr2) => format!(
                 "((({} as u32) <= ({} as u32)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32GeS(expr1, expr2) => format!(
                 "(({} >= {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32GeU(expr1, expr2) => format!(
                 "((({} as u32) >= ({} as u32)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Eqz(expr) => format!(
                 "(({} == 0i64) as i32)",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
             Self::I64Eq(expr1, expr2) => format!(
                 "(({} == {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Ne(expr1, expr2) => format!(
                 "(({} != {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64LtS(expr1, expr2) => format!(
                 "(({} < {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64LtU(expr1, expr2) => format!(
                 "((({} as u64) < ({} as u64)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64GtS(expr1, expr2) => format!(
                 "(({} > {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64GtU(expr1, expr2) => format!(
                 "((({} as u64) > ({} as u64)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64LeS(expr1, expr2) => format!(
                 "(({} <= {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64LeU(expr1, expr2) => format!(
                 "((({} as u64) <= ({} as u64)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64GeS(expr1, expr2) => format!(
                 "(({} >= {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64GeU(expr1, expr2) => format!(
                 "((({} as u64) >= ({} as u64)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Eq(expr1, expr2) => format!(
                 "(({} == {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Ne(expr1, expr2) => format!(
                 "(({} != {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Lt(expr1, expr2) => format!(
                 "(({} < {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Gt(expr1, expr2) => format!(
                 "(({} > {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Le(expr1, expr2) => format!(
                 "(({} <= {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Ge(expr1, expr2) => format!(
                 "(({} >= {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Eq(expr1, expr2) => format!(
                 "(({} == {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Ne(expr1, expr2) => format!(
                 "(({} != {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Lt(expr1, expr2) => format!(
                 "(({} < {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Gt(expr1, expr2) => format!(
                 "(({} > {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Le(expr1, expr2) => format!(
                 "(({} <= {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Ge(expr1, expr2) => format!(
                 "(({} >= {}) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32Clz(expr) => format!(
-                "{}.leading_zeros()",
-                expr.emit_code(),
+                "(i32::leading_zeros({}) as i32)",
+                expr.emit_code(options),
             ),
             Self::I32Ctz(expr) => format!(
-                "{}.trailing_zeros()",
-                expr.emit_code(),
+                "(i32::trailing_zeros({}) as i32)",
+                expr.emit_code(options),
             ),
             Self::I32Popcnt(expr) => format!(
-                "{}.count_ones()",
-                expr.emit_code(),
+                "(i32::count_ones({}) as i32)",
+                expr.emit_code(options),
             ),
             Self::I32Add(expr1, expr2) => format!(
-                "({} + {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i32::wrapping_add({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32Sub(expr1, expr2) => format!(
-                "({} - {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i32::wrapping_sub({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32Mul(expr1, expr2) => format!(
-                "({} * {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            Self::I32DivS(expr1, expr2) => format!(
-                "({} / {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            Self::I32DivU(expr1, expr2) => format!(
-                "((({} as u32) / ({} as u32)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            Self::I32RemS(expr1, expr2) => format!(
-                "({} % {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            Self::I32RemU(expr1, expr2) => format!(
-                "((({} as u32) % ({} as u32)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i32::wrapping_mul({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
+            Self::I32DivS(expr1, expr2) => if options.trapping {
+                format!(
+                    "__w2r_i32_div_s({}, {}){}",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "({} / {})",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                )
+            },
+            Self::I32DivU(expr1, expr2) => if options.trapping {
+                format!(
+                    "__w2r_i32_div_u({}, {}){}",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "((({} as u32) / ({} as u32)) as i32)",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                )
+            },
+            Self::I32RemS(expr1, expr2) => if options.trapping {
+                format!(
+                    "__w2r_i32_rem_s({}, {}){}",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "i32::wrapping_rem({}, {})",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                )
+            },
+            Self::I32RemU(expr1, expr2) => if options.trapping {
+                format!(
+                    "__w2r_i32_rem_u({}, {}){}",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "((({} as u32) % ({} as u32)) as i32)",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                )
+            },
             Self::I32And(expr1, expr2) => format!(
                 "({} & {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32Or(expr1, expr2) => format!(
                 "({} | {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32Xor(expr1, expr2) => format!(
                 "({} ^ {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32Shl(expr1, expr2) => format!(
-                "({} << ({} as u32))",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i32::wrapping_shl({}, {} as u32)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32ShrS(expr1, expr2) => format!(
-                "({} >> {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i32::wrapping_shr({}, {} as u32)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32ShrU(expr1, expr2) => format!(
-                "((({} as u32) >> ({} as u32)) as i32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "(u32::wrapping_shr({} as u32, {} as u32) as i32)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32Rotl(expr1, expr2) => format!(
-                "{}.rotate_left({} as u32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i32::rotate_left({}, {} as u32)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I32Rotr(expr1, expr2) => format!(
-                "{}.rotate_right({} as u32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i32::rotate_right({}, {} as u32)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Clz(expr) => format!(
-                "({}.leading_zeros() as i64)",
-                expr.emit_code(),
+                "(i64::leading_zeros({}) as i64)",
+                expr.emit_code(options),
             ),
             Self::I64Ctz(expr) => format!(
-                "({}.trailing_zeros() as i64)",
-                expr.emit_code(),
+                "(i64::trailing_zeros({}) as i64)",
+                expr.emit_code(options),
             ),
             Self::I64Popcnt(expr) => format!(
-                "({}.count_ones() as i64)",
-                expr.emit_code(),
+                "(i64::count_ones({}) as i64)",
+                expr.emit_code(options),
             ),
             Self::I64Add(expr1, expr2) => format!(
-                "({} + {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i64::wrapping_add({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Sub(expr1, expr2) => format!(
-                "({} - {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i64::wrapping_sub({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Mul(expr1, expr2) => format!(
-                "({} * {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            Self::I64DivS(expr1, expr2) => format!(
-                "({} / {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            Self::I64DivU(expr1, expr2) => format!(
-                "((({} as u64) / ({} as u64)) as i64)",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            Self::I64RemS(expr1, expr2) => format!(
-                "({} % {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            Self::I64RemU(expr1, expr2) => format!(
-                "((({} as u64) % ({} as u64)) as i64)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i64::wrapping_mul({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
+            Self::I64DivS(expr1, expr2) => if options.trapping {
+                format!(
+                    "__w2r_i64_div_s({}, {}){}",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "({} / {})",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                )
+            },
+            Self::I64DivU(expr1, expr2) => if options.trapping {
+                format!(
+                    "__w2r_i64_div_u({}, {}){}",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "((({} as u64) / ({} as u64)) as i64)",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                )
+            },
+            Self::I64RemS(expr1, expr2) => if options.trapping {
+                format!(
+                    "__w2r_i64_rem_s({}, {}){}",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "i64::wrapping_rem({}, {})",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                )
+            },
+            Self::I64RemU(expr1, expr2) => if options.trapping {
+                format!(
+                    "__w2r_i64_rem_u({}, {}){}",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "((({} as u64) % ({} as u64)) as i64)",
+                    expr1.emit_code(options),
+                    expr2.emit_code(options),
+                )
+            },
             Self::I64And(expr1, expr2) => format!(
                 "({} & {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Or(expr1, expr2) => format!(
                 "({} | {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Xor(expr1, expr2) => format!(
                 "({} ^ {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Shl(expr1, expr2) => format!(
-                "({} << ({} as u64))",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i64::wrapping_shl({}, {} as u32)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64ShrS(expr1, expr2) => format!(
-                "({} >> {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i64::wrapping_shr({}, {} as u32)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64ShrU(expr1, expr2) => format!(
-                "((({} as u64) >> ({} as u64)) as i64)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "(u64::wrapping_shr({} as u64, {} as u32) as i64)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Rotl(expr1, expr2) => format!(
-                "{}.rotate_left({} as u32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i64::rotate_left({}, {} as u32)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::I64Rotr(expr1, expr2) => format!(
-                "{}.rotate_right({} as u32)",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "i64::rotate_right({}, {} as u32)",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Abs(expr) => format!(
-                "{}.abs()",
-                expr.emit_code(),
+                "f32::abs({})",
+                expr.emit_code(options),
             ),
             Self::F32Neg(expr) => format!(
                 "(-{})",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
             Self::F32Ceil(expr) => format!(
-                "{}.ceil()",
-                expr.emit_code(),
+                "f32::ceil({})",
+                expr.emit_code(options),
             ),
             Self::F32Floor(expr) => format!(
-                "{}.floor()",
-                expr.emit_code(),
+                "f32::floor({})",
+                expr.emit_code(options),
             ),
             Self::F32Trunc(expr) => format!(
-                "{}.trunc()",
-                expr.emit_code(),
+                "f32::trunc({})",
+                expr.emit_code(options),
             ),
             Self::F32Nearest(expr) => format!(
-                "{}.round()",
-                expr.emit_code(),
+                "f32::round_ties_even({})",
+                expr.emit_code(options),
             ),
             Self::F32Sqrt(expr) => format!(
-                "{}.sqrt()",
-                expr.emit_code(),
+                "f32::sqrt({})",
+                expr.emit_code(options),
             ),
             Self::F32Add(expr1, expr2) => format!(
                 "({} + {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Sub(expr1, expr2) => format!(
                 "({} - {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Mul(expr1, expr2) => format!(
                 "({} * {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F32Div(expr1, expr2) => format!(
                 "({} / {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            // Self::F32Min(expr, expr) => format!(
-            //     "",
-            //     expr.emit_code(),
-            // ),
-            // Self::F32Max(expr, expr) => format!(
-            //     "",
-            //     expr.emit_code(),
-            // ),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
+            ),
+            Self::F32Min(expr1, expr2) => format!(
+                "__w2r_f32_min({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
+            ),
+            Self::F32Max(expr1, expr2) => format!(
+                "__w2r_f32_max({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
+            ),
             Self::F32Copysign(expr1, expr2) => format!(
-                "{}.copysign({})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                "f32::copysign({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Abs(expr) => format!(
-                "{}.abs()",
-                expr.emit_code(),
+                "f64::abs({})",
+                expr.emit_code(options),
             ),
             Self::F64Neg(expr) => format!(
                 "(-{})",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
             Self::F64Ceil(expr) => format!(
-                "{}.ceil()",
-                expr.emit_code(),
+                "f64::ceil({})",
+                expr.emit_code(options),
             ),
             Self::F64Floor(expr) => format!(
-                "{}.floor()",
-                expr.emit_code(),
+                "f64::floor({})",
+                expr.emit_code(options),
             ),
             Self::F64Trunc(expr) => format!(
-                "{}.trunc()",
-                expr.emit_code(),
+                "f64::trunc({})",
+                expr.emit_code(options),
             ),
             Self::F64Nearest(expr) => format!(
-                "{}.round()",
-                expr.emit_code(),
+                "f64::round_ties_even({})",
+                expr.emit_code(options),
             ),
             Self::F64Sqrt(expr) => format!(
-                "{}.sqrt()",
-                expr.emit_code(),
+                "f64::sqrt({})",
+                expr.emit_code(options),
             ),
             Self::F64Add(expr1, expr2) => format!(
                 "({} + {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Sub(expr1, expr2) => format!(
                 "({} - {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Mul(expr1, expr2) => format!(
                 "({} * {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
             Self::F64Div(expr1, expr2) => format!(
                 "({} / {})",
-                expr1.emit_code(),
-                expr2.emit_code(),
-            ),
-            // Self::F64Min(expr, expr) => format!(
-            //     "",
-            //     expr.emit_code(),
-            // ),
-            // Self::F64Max(expr, expr) => format!(
-            //     "",
-            //     expr.emit_code(),
-            // ),
-            Self::F64Copysign(expr1, expr2) => format!(
-                "{}.copysign({})",
-                expr1.emit_code(),
-                expr2.emit_code(),
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
-            Self::I32WrapI64(expr) => format!(
-                "({} as i32)",
-                expr.emit_code(),
+            Self::F64Min(expr1, expr2) => format!(
+                "__w2r_f64_min({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
-            Self::I32TruncF32S(expr) => format!(
-                "{}.to_int_unchecked::<i32>()",
-                expr.emit_code(),
+            Self::F64Max(expr1, expr2) => format!(
+                "__w2r_f64_max({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
-            Self::I32TruncF32U(expr) => format!(
-                "({}.to_int_unchecked::<u32>() as i32)",
-                expr.emit_code(),
-            ),
-            Self::I32TruncF64S(expr) => format!(
-                "{}.to_int_unchecked::<i32>()",
-                expr.emit_code(),
+            Self::F64Copysign(expr1, expr2) => format!(
+                "f64::copysign({}, {})",
+                expr1.emit_code(options),
+                expr2.emit_code(options),
             ),
-            Self::I32TruncF64U(expr) => format!(
-                "({}.to_int_unchecked::<u32>() as i32)",
-                expr.emit_code(),
+            Self::I32WrapI64(expr) => format!(
+                "({} as i32)",
+                expr.emit_code(options),
             ),
+            Self::I32TruncF32S(expr) => if options.trapping {
+                format!(
+                    "__w2r_i32_trunc_f32_s({}){}",
+                    expr.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "{}.to_int_unchecked::<i32>()",
+                    expr.emit_code(options),
+                )
+            },
+            Self::I32TruncF32U(expr) => if options.trapping {
+                format!(
+                    "__w2r_i32_trunc_f32_u({}){}",
+                    expr.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "({}.to_int_unchecked::<u32>() as i32)",
+                    expr.emit_code(options),
+                )
+            },
+            Self::I32TruncF64S(expr) => if options.trapping {
+                format!(
+                    "__w2r_i32_trunc_f64_s({}){}",
+                    expr.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "{}.to_int_unchecked::<i32>()",
+                    expr.emit_code(options),
+                )
+            },
+            Self::I32TruncF64U(expr) => if options.trapping {
+                format!(
+                    "__w2r_i32_trunc_f64_u({}){}",
+                    expr.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "({}.to_int_unchecked::<u32>() as i32)",
+                    expr.emit_code(options),
+                )
+            },
             Self::I64ExtendI32S(expr) => format!(
                 "({} as i64)",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
             Self::I64ExtendI32U(expr) => format!(
                 "({} as u64 as i64)",
-                expr.emit_code(),
-            ),
-            Self::I64TruncF32S(expr) => format!(
-                "{}.to_int_unchecked::<i32>()",
-                expr.emit_code(),
-            ),
-            Self::I64TruncF32U(expr) => format!(
-                "({}.to_int_unchecked::<u32>() as i32)",
-                expr.emit_code(),
-            ),
-            Self::I64TruncF64S(expr) => format!(
-                "{}.to_int_unchecked::<i32>()",
-                expr.emit_code(),
-            ),
-            Self::I64TruncF64U(expr) => format!(
-                "({}.to_int_unchecked::<u32>() as i32)",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
+            Self::I64TruncF32S(expr) => if options.trapping {
+                format!(
+                    "__w2r_i64_trunc_f32_s({}){}",
+                    expr.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "{}.to_int_unchecked::<i64>()",
+                    expr.emit_code(options),
+                )
+            },
+            Self::I64TruncF32U(expr) => if options.trapping {
+                format!(
+                    "__w2r_i64_trunc_f32_u({}){}",
+                    expr.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "({}.to_int_unchecked::<u64>() as i64)",
+                    expr.emit_code(options),
+                )
+            },
+            Self::I64TruncF64S(expr) => if options.trapping {
+                format!(
+                    "__w2r_i64_trunc_f64_s({}){}",
+                    expr.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "{}.to_int_unchecked::<i64>()",
+                    expr.emit_code(options),
+                )
+            },
+            Self::I64TruncF64U(expr) => if options.trapping {
+                format!(
+                    "__w2r_i64_trunc_f64_u({}){}",
+                    expr.emit_code(options),
+                    options.try_suffix(),
+                )
+            } else {
+                format!(
+                    "({}.to_int_unchecked::<u64>() as i64)",
+                    expr.emit_code(options),
+                )
+            },
             Self::F32ConvertI32S(expr)
             | Self::F32ConvertI32U(expr)
             | Self::F32ConvertI64S(expr)
             | Self::F32ConvertI64U(expr)
             | Self::F32DemoteF64(expr) => format!(
                 "({} as f32)",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
             Self::F64ConvertI32S(expr)
             | Self::F64ConvertI32U(expr)
@@ -891,23 +1135,27 @@ impl<'a> Expression {
             | Self::F64ConvertI64U(expr)
             | Self::F64PromoteF32(expr) => format!(
                 "({} as f64)",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
+            Self::I32ReinterpretF32(expr) if options.safe => format!("(f32::to_bits({}) as i32)", expr.emit_code(options)),
             Self::I32ReinterpretF32(expr) => format!(
                 "::std::mem::transmute::<f32, i32>({})",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
+            Self::I64ReinterpretF64(expr) if options.safe => format!("(f64::to_bits({}) as i64)", expr.emit_code(options)),
             Self::I64ReinterpretF64(expr) => format!(
                 "::std::mem::transmute::<f64, i64>({})",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
+            Self::F32ReinterpretI32(expr) if options.safe => format!("f32::from_bits({} as u32)", expr.emit_code(options)),
             Self::F32ReinterpretI32(expr) => format!(
                 "::std::mem::transmute::<i32, f32>({})",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
+            Self::F64ReinterpretI64(expr) if options.safe => format!("f64::from_bits({} as u64)", expr.emit_code(options)),
             Self::F64ReinterpretI64(expr) => format!(
                 "::std::mem::transmute::<i64, f64>({})",
-                expr.emit_code(),
+                expr.emit_code(options),
             ),
             Self::I32Const(num) => format!(
                 "{}i32",
@@ -925,154 +1173,1403 @@ impl<'a> Expression {
                 "f64::from_bits({})",
                 num
             ),
-            Self::MemoryGrow(delta) => format!(
-                "(::std::arch::wasm32::memory_grow(0, {} as _) as i32)",
-                delta.emit_code()
-            ),
-            Self::MemorySize => format!(
-                "(::std::arch::wasm32::memory_size(0) as i32)"
-            ),
-            Self::I32Extend8S(_) => unimplemented!(),
-            Self::I32Extend16S(_) => unimplemented!(),
-            Self::I64Extend8S(_) => unimplemented!(),
-            Self::I64Extend16S(_) => unimplemented!(),
-            Self::I64Extend32S(_) => unimplemented!(),
-            Self::IfElse(_, _) => unimplemented!(),
-            Self::CallIndirect(_) => unimplemented!(),
-            Self::F32Max(_, _) => unimplemented!(),
-            Self::F32Min(_, _) => unimplemented!(),
-            Self::F64Max(_, _) => unimplemented!(),
-            Self::F64Min(_, _) => unimplemented!(),
+            Self::MemoryGrow(memory, delta) => memory.emit_grow(&delta.emit_code(options), options),
+            Self::MemorySize(memory) => memory.emit_size(options),
+            Self::MemoryAddress(memory, addr) => memory.emit_pointer(&addr.emit_code(options), options),
+            Self::Tuple(exprs) => format!(
+                "({})",
+                exprs.iter().map(|expr| expr.emit_code(options)).join(", ")
+            ),
+            Self::I32Extend8S(expr) => format!(
+                "({} as i8 as i32)",
+                expr.emit_code(options),
+            ),
+            Self::I32Extend16S(expr) => format!(
+                "({} as i16 as i32)",
+                expr.emit_code(options),
+            ),
+            Self::I64Extend8S(expr) => format!(
+                "({} as i8 as i64)",
+                expr.emit_code(options),
+            ),
+            Self::I64Extend16S(expr) => format!(
+                "({} as i16 as i64)",
+                expr.emit_code(options),
+            ),
+            Self::I32TruncSatF32S(expr) => format!(
+                "({} as i32)",
+                expr.emit_code(options),
+            ),
+            Self::I32TruncSatF32U(expr) => format!(
+                "({} as u32 as i32)",
+                expr.emit_code(options),
+            ),
+            Self::I32TruncSatF64S(expr) => format!(
+                "({} as i32)",
+                expr.emit_code(options),
+            ),
+            Self::I32TruncSatF64U(expr) => format!(
+                "({} as u32 as i32)",
+                expr.emit_code(options),
+            ),
+            Self::I64TruncSatF32S(expr) => format!(
+                "({} as i64)",
+                expr.emit_code(options),
+            ),
+            Self::I64TruncSatF32U(expr) => format!(
+                "({} as u64 as i64)",
+                expr.emit_code(options),
+            ),
+            Self::I64TruncSatF64S(expr) => format!(
+                "({} as i64)",
+                expr.emit_code(options),
+            ),
+            Self::I64TruncSatF64U(expr) => format!(
+                "({} as u64 as i64)",
+                expr.emit_code(options),
+            ),
+            // safe code borrows its memory exclusively, so its atomics are
+            // ordinary accesses to the bytes
+            Self::AtomicLoad(access, addr, offset) => match sandboxed_address(addr, options) {
+                (Some(memory), addr) if options.safe => format!(
+                    "{{ let a = {}; ({}::from_le_bytes(*{}) as {}) }}",
+                    addr.emit_code(options),
+                    access.int_type(),
+                    access.emit_bytes("a", *offset, memory),
+                    access.ty,
+                ),
+                (memory, addr) => format!(
+                    "({}.load(::std::sync::atomic::Ordering::SeqCst) as {})",
+                    access.emit_atomic(&addr.emit_code(options), *offset, memory, options),
+                    access.ty,
+                ),
+            },
+            Self::AtomicRmw(access, method, addr, value, offset) => match sandboxed_address(addr, options) {
+                (Some(memory), addr) if options.safe => format!(
+                    "{{ let (a, v) = ({}, {} as {int}); let bytes = {}; let old = {int}::from_le_bytes(*bytes); *bytes = {}.to_le_bytes(); old as {} }}",
+                    addr.emit_code(options),
+                    value.emit_code(options),
+                    access.emit_bytes("a", *offset, memory),
+                    match *method {
+                        "fetch_add" => "old.wrapping_add(v)",
+                        "fetch_sub" => "old.wrapping_sub(v)",
+                        "fetch_and" => "(old & v)",
+                        "fetch_or" => "(old | v)",
+                        "fetch_xor" => "(old ^ v)",
+                        _ => "v",
+                    },
+                    access.ty,
+                    int = access.int_type(),
+                ),
+                (memory, addr) => format!(
+                    "({}.{method}({} as {}, ::std::sync::atomic::Ordering::SeqCst) as {})",
+                    access.emit_atomic(&addr.emit_code(options), *offset, memory, options),
+                    value.emit_code(options),
+                    access.int_type(),
+                    access.ty,
+                ),
+            },
+            Self::AtomicCmpxchg(access, addr, expected, replacement, offset) => match sandboxed_address(addr, options) {
+                (Some(memory), addr) if options.safe => format!(
+                    "{{ let (a, e, r) = ({}, {} as {int}, {} as {int}); let bytes = {}; let old = {int}::from_le_bytes(*bytes); if old == e {{ *bytes = r.to_le_bytes(); }} old as {} }}",
+                    addr.emit_code(options),
+                    expected.emit_code(options),
+                    replacement.emit_code(options),
+                    access.emit_bytes("a", *offset, memory),
+                    access.ty,
+                    int = access.int_type(),
+                ),
+                (memory, addr) => format!(
+                    "({}.compare_exchange({} as {int}, {} as {int}, ::std::sync::atomic::Ordering::SeqCst, ::std::sync::atomic::Ordering::SeqCst).unwrap_or_else(|v| v) as {})",
+                    access.emit_atomic(&addr.emit_code(options), *offset, memory, options),
+                    expected.emit_code(options),
+                    replacement.emit_code(options),
+                    access.ty,
+                    int = access.int_type(),
+                ),
+            },
+            Self::MemoryAtomicNotify(addr, count, offset) => match sandboxed_address(addr, options) {
+                (Some(memory), addr) if options.safe => format!(
+                    "{{ let (a, _) = ({}, {}); {}; 0i32 }}",
+                    addr.emit_code(options),
+                    count.emit_code(options),
+                    memory.emit_atomic_bytes("a", *offset, 4),
+                ),
+                // a sandboxed memory has no other threads to wake
+                (Some(memory), addr) => format!(
+                    "{{ let (a, _) = ({}, {}); {}; 0i32 }}",
+                    addr.emit_code(options),
+                    count.emit_code(options),
+                    memory.emit_atomic_access("a", *offset, "i32", options),
+                ),
+                (None, addr) => format!(
+                    "(::std::arch::wasm32::memory_atomic_notify({}, {} as u32) as i32)",
+                    emit_address(&addr.emit_code(options), *offset, "i32", None, options),
+                    count.emit_code(options),
+                ),
+            },
+            Self::MemoryAtomicWait32(addr, expected, timeout, offset) => emit_wait(addr, expected, timeout, *offset, WASMType::I32, options),
+            Self::MemoryAtomicWait64(addr, expected, timeout, offset) => emit_wait(addr, expected, timeout, *offset, WASMType::I64, options),
+            Self::I64Extend32S(expr) => format!(
+                "({} as i32 as i64)",
+                expr.emit_code(options),
+            ),
+            Self::RefNull => "None".to_string(),
+            Self::RefIsNull(expr) => format!("({}.is_none() as i32)", expr.emit_code(options)),
+            Self::RefFunc(func, ty) if options.safe => format!("Some(__W2RFuncRef {{ ty: {ty}, func: {} }})", func.index()),
+            Self::RefFunc(func, ty) => format!(
+                "Some(__W2RFuncRef {{ ty: {ty}, func: unsafe {{ ::std::mem::transmute::<{}, unsafe fn()>({}) }} }})",
+                func.ty().emit_pointer_type(options),
+                if options.instance { format!("Self::{}", func.method()) } else { func.to_string() },
+            ),
+            Self::TableGet(table, index) => format!(
+                "match {}.get({} as u32 as usize) {{ Some(r) => *r, None => {} }}",
+                options.state_ref(table),
+                index.emit_code(options),
+                table_out_of_bounds(options),
+            ),
+            Self::TableSize(table) => format!("({}.len() as i32)", options.state_ref(table)),
+            Self::TableGrow(table, value, delta, maximum) => format!(
+                "{{ let (v, n) = ({}, {}); let table = {}; let size = table.len(); match size.checked_add(n as u32 as usize) {{ Some(grown) if grown <= {maximum} => {{ table.to_mut().resize(grown, v); size as i32 }}, _ => -1 }} }}",
+                value.emit_code(options),
+                delta.emit_code(options),
+                if options.safe { format!("&mut {}", options.state(table)) } else { format!("&mut *::std::ptr::addr_of_mut!({})", options.state(table)) },
+            ),
+            Self::V128Const(value) => format!(
+                "::std::arch::wasm32::u64x2({:#x}, {:#x})",
+                *value as u64,
+                (*value >> 64) as u64,
+            ),
+            Self::SimdLoad(access, addr, None, offset) => {
+                let (memory, addr) = sandboxed_address(addr, options);
+                format!(
+                    "::std::arch::wasm32::{}({})",
+                    access.name,
+                    emit_address(&addr.emit_code(options), *offset, access.pointee, memory, options),
+                )
+            },
+            Self::SimdLoad(access, addr, Some(vector), offset) => {
+                let (memory, addr) = sandboxed_address(addr, options);
+                format!(
+                    "{{ let (a, v) = ({}, {}); ::std::arch::wasm32::{}{}(v, {}) }}",
+                    addr.emit_code(options),
+                    vector.emit_code(options),
+                    access.name,
+                    emit_lanes(access.lane.as_slice()),
+                    emit_address("a", *offset, access.pointee, memory, options),
+                )
+            },
+            Self::Simd(intrinsic, lanes, args) => {
+                let mut args = args.iter().map(|arg| arg.emit_code(options)).collect::<Vec<_>>();
+                if let (Some(scalar), Some(last)) = (intrinsic.scalar, args.last_mut()) {
+                    *last = format!("{last} as {scalar}");
+                }
+                let call = format!("::std::arch::wasm32::{}{}({})", intrinsic.name, emit_lanes(lanes), args.join(", "));
+                match intrinsic.result {
+                    Some(ty) => format!("({call} as {ty})"),
+                    None => call,
+                }
+            },
+        }
+    }
+
+    /// Load through a sandboxed memory, if this is a load and the backend is
+    /// sandboxed: the bytes are read as the stored type and extended to the
+    /// loaded one.
+    fn emit_sandboxed_load(&self, options: &EmitOptions) -> Option<String> {
+        let (addr, offset, stored, loaded) = match self {
+            Self::I32Load(addr, _, offset) => (addr, offset, "i32", None),
+            Self::I64Load(addr, _, offset) => (addr, offset, "i64", None),
+            Self::F32Load(addr, _, offset) => (addr, offset, "f32", None),
+            Self::F64Load(addr, _, offset) => (addr, offset, "f64", None),
+            Self::I32Load8S(addr, _, offset) => (addr, offset, "i8", Some("i32")),
+            Self::I32Load8U(addr, _, offset) => (addr, offset, "u8", Some("i32")),
+            Self::I32Load16S(addr, _, offset) => (addr, offset, "i16", Some("i32")),
+            Self::I32Load16U(addr, _, offset) => (addr, offset, "u16", Some("i32")),
+            Self::I64Load8S(addr, _, offset) => (addr, offset, "i8", Some("i64")),
+            Self::I64Load8U(addr, _, offset) => (addr, offset, "u8", Some("i64")),
+            Self::I64Load16S(addr, _, offset) => (addr, offset, "i16", Some("i64")),
+            Self::I64Load16U(addr, _, offset) => (addr, offset, "u16", Some("i64")),
+            Self::I64Load32S(addr, _, offset) => (addr, offset, "i32", Some("i64")),
+            Self::I64Load32U(addr, _, offset) => (addr, offset, "u32", Some("i64")),
+            _ => return None,
+        };
+        let (Some(memory), addr) = sandboxed_address(addr, options) else {
+            return None
+        };
+        let load = memory.emit_load(&addr.emit_code(options), *offset, stored, options);
+        Some(match loaded {
+            Some(loaded) => format!("({load} as {loaded})"),
+            None => load,
+        })
+    }
+}
+
+/// Call of a method of the instance. Operands that use the instance are
+/// evaluated first, as it cannot be borrowed again while the call borrows it.
+fn emit_method_call(func: &FunctionKind, args: &[Expression], options: &EmitOptions) -> String {
+    let values = args.iter().map(|arg| arg.emit_code(options)).collect::<Vec<_>>();
+    let simple = args.iter().all(|arg| matches!(
+        arg,
+        Expression::LocalGet(_) | Expression::I32Const(_) | Expression::I64Const(_) | Expression::F32Const(_) | Expression::F64Const(_)
+    ));
+    if simple {
+        return format!("self.{}({}){}", func.method(), values.join(", "), options.try_suffix())
+    }
+
+    let names = (0..args.len()).map(|i| format!("a{i}")).collect::<Vec<_>>();
+    format!(
+        "{{ let {} = {}; self.{}({}){} }}",
+        if args.len() == 1 { names[0].clone() } else { format!("({})", names.join(", ")) },
+        if args.len() == 1 { values[0].clone() } else { format!("({})", values.join(", ")) },
+        func.method(),
+        names.join(", "),
+        options.try_suffix(),
+    )
+}
+
+/// Expression for an access past the end of a table.
+pub fn table_out_of_bounds(options: &EmitOptions) -> String {
+    if options.trapping {
+        options.trap("TableOutOfBounds")
+    } else {
+        "panic!(\"out of bounds table access\")".to_string()
+    }
+}
+
+/// `memory.atomic.wait32` or `wait64`. Nothing could notify a wait on a
+/// sandboxed memory, so it times out at once if the value is as expected.
+fn emit_wait(addr: &Expression, expected: &Expression, timeout: &Expression, offset: u64, ty: WASMType, options: &EmitOptions) -> String {
+    match sandboxed_address(addr, options) {
+        (Some(memory), addr) if options.safe => format!(
+            "{{ let (a, e, _) = ({}, {}, {}); if {ty}::from_le_bytes(*{}) == e {{ 2i32 }} else {{ 1i32 }} }}",
+            addr.emit_code(options),
+            expected.emit_code(options),
+            timeout.emit_code(options),
+            memory.emit_atomic_bytes("a", offset, if ty == WASMType::I64 { 8 } else { 4 }),
+        ),
+        (Some(memory), addr) => format!(
+            "{{ let (a, e, _) = ({}, {}, {}); if *{} == e {{ 2i32 }} else {{ 1i32 }} }}",
+            addr.emit_code(options),
+            expected.emit_code(options),
+            timeout.emit_code(options),
+            memory.emit_atomic_access("a", offset, &ty.to_string(), options),
+        ),
+        (None, addr) => format!(
+            "::std::arch::wasm32::memory_atomic_wait{}({}, {}, {})",
+            if ty == WASMType::I64 { 64 } else { 32 },
+            emit_address(&addr.emit_code(options), offset, &ty.to_string(), None, options),
+            expected.emit_code(options),
+            timeout.emit_code(options),
+        ),
+    }
+}
+
+/// Memory that `addr` goes into, if it is sandboxed, and the address
+/// within it.
+pub fn sandboxed_address<'a>(addr: &'a Expression, options: &EmitOptions) -> (Option<&'a Memory>, &'a Expression) {
+    match addr {
+        Expression::MemoryAddress(memory, addr) if memory.is_sandboxed(options) => (Some(memory), addr),
+        _ => (None, addr),
+    }
+}
+
+/// Pointer to the `ty` at `addr + offset`, bounds-checked if `memory` is
+/// the sandboxed memory that `addr` goes into.
+pub fn emit_address(addr: &str, offset: u64, ty: &str, memory: Option<&Memory>, options: &EmitOptions) -> String {
+    if let Some(memory) = memory {
+        memory.emit_access(addr, offset, ty, options)
+    } else if offset == 0 {
+        format!("({addr} as *mut {ty})")
+    } else {
+        format!("({addr} as *mut u8).add({offset}).cast::<{ty}>()")
+    }
+}
+
+/// Value type and width in bits of an atomic memory access. Narrow accesses
+/// zero-extend the loaded value.
+#[derive(Debug, Copy, Clone)]
+pub struct AtomicAccess {
+    pub ty: WASMType,
+    pub bits: u8,
+}
+
+impl AtomicAccess {
+    /// Integer type stored in memory.
+    pub fn int_type(&self) -> String {
+        match (self.ty, self.bits) {
+            (WASMType::I32, 32) | (WASMType::I64, 64) => self.ty.to_string(),
+            (_, bits) => format!("u{bits}"),
         }
     }
+
+    /// Bytes of the memory at `addr + offset` that safe code accesses.
+    pub fn emit_bytes(&self, addr: &str, offset: u64, memory: &Memory) -> String {
+        memory.emit_atomic_bytes(addr, offset, self.bits as u32 / 8)
+    }
+
+    /// Atomic view of the memory at `addr + offset`.
+    pub fn emit_atomic(&self, addr: &str, offset: u64, memory: Option<&Memory>, options: &EmitOptions) -> String {
+        let mut atomic_type = self.int_type();
+        atomic_type[..1].make_ascii_uppercase();
+        let pointer = match memory {
+            Some(memory) => memory.emit_atomic_access(addr, offset, &self.int_type(), options),
+            None => emit_address(addr, offset, &self.int_type(), None, options),
+        };
+        format!("::std::sync::atomic::Atomic{atomic_type}::from_ptr({pointer})")
+    }
+}
+
+/// Turbofish with the const lane arguments of a SIMD intrinsic, if any.
+pub fn emit_lanes(lanes: &[u8]) -> String {
+    if lanes.is_empty() {
+        String::new()
+    } else {
+        format!("::<{}>", lanes.iter().map(|lane| lane.to_string()).collect::<Vec<_>>().join(", "))
+    }
+}
+
+/// SIMD load or store intrinsic, the type it points to in memory and the
+/// lane it accesses, if it accesses a single lane.
+#[derive(Debug, Copy, Clone)]
+pub struct SimdAccess {
+    pub name: &'static str,
+    pub pointee: &'static str,
+    pub lane: Option<u8>,
+}
+
+/// SIMD intrinsic operating on values. Scalar operands and results are
+/// converted from and to the WASM value types with `as` casts.
+#[derive(Debug, Copy, Clone)]
+pub struct SimdIntrinsic {
+    pub name: &'static str,
+    pub arity: usize,
+    /// Rust type of the trailing scalar operand (lane value or shift amount).
+    pub scalar: Option<&'static str>,
+    /// WASM type of a scalar result.
+    pub result: Option<&'static str>,
+}
+
+impl SimdIntrinsic {
+    fn new(name: &'static str, arity: usize) -> Self {
+        Self { name, arity, scalar: None, result: None }
+    }
+
+    fn scalar(self, ty: &'static str) -> Self {
+        Self { scalar: Some(ty), ..self }
+    }
+
+    fn result(self, ty: &'static str) -> Self {
+        Self { result: Some(ty), ..self }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum LevelKind {
+    /// Body of the function with the given index.
+    Function(u32),
     Block,
     Loop,
+    If,
+    /// Body of a `try`, which runs in a closure under `catch_unwind`.
+    Try,
+    /// Handler of a `catch` or `catch_all` clause.
+    Catch,
 }
 
+/// A label that can be the target of a branch.
 #[derive(Debug, Clone)]
-pub enum ParsingContext {
-    Nested {
-        stack: Vec<LevelKind>
+pub struct Level {
+    pub kind: LevelKind,
+    /// Number of values carried by a branch to this label.
+    pub arity: usize,
+    /// Variables holding the parameters of a loop or function.
+    pub params: Vec<String>,
+    /// Rust label of the block, without the leading `'`.
+    pub label: String,
+}
+
+impl Level {
+    fn new(kind: LevelKind, arity: usize, label: String) -> Self {
+        Self {
+            kind,
+            arity,
+            params: vec![],
+            label,
+        }
+    }
+}
+
+/// The operator that closed a sequence of operators.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Terminator {
+    End,
+    Else,
+    Catch(u32),
+    CatchAll,
+    Delegate(u32),
+}
+
+/// Statements parsed up to an `end` (or `else`), along with the values left on
+/// the stack and the depths of all labels branched to from within.
+struct Sequence {
+    statements: Vec<Statement>,
+    values: Vec<Expression>,
+    targets: BTreeSet<u32>,
+    /// Depths of the labels exceptions are delegated to from within.
+    delegates: BTreeSet<u32>,
+    terminator: Terminator,
+    /// Whether the end of the sequence is unreachable.
+    diverges: bool,
+}
+
+impl Sequence {
+    /// The value the sequence falls through with, if its end is reachable.
+    fn result(&mut self, arity: usize) -> Option<Expression> {
+        if self.diverges {
+            None
+        } else {
+            branch_value(self.values.split_off(self.values.len().saturating_sub(arity)))
+        }
+    }
+}
+
+/// Parameter and result types of a block, if its type index is valid.
+fn block_signature(context: &Context, ty: BlockType) -> Option<FuncType> {
+    match ty {
+        BlockType::Empty => Some(FuncType { params: vec![], returns: vec![] }),
+        BlockType::Type(t) => Some(FuncType { params: vec![], returns: vec![t.into()] }),
+        BlockType::FuncType(index) => context.types.get(&index).cloned(),
+    }
+}
+
+/// Combine the values carried by a branch into a single expression.
+fn branch_value(mut values: Vec<Expression>) -> Option<Expression> {
+    match values.len() {
+        0 => None,
+        1 => values.pop(),
+        _ => Some(Expression::Tuple(values)),
+    }
+}
+
+fn temporaries(depth: usize, spilled: &mut u32, count: usize) -> Vec<String> {
+    (0..count).map(|_| {
+        let name = format!("v{depth}_{spilled}");
+        *spilled += 1;
+        name
+    }).collect()
+}
+
+fn parse_nested<'a>(iter: &mut impl Iterator<Item=Operator<'a>>, context: &Context, block_stack: &[Level], params: Vec<Expression>, targets: &mut BTreeSet<u32>, delegates: &mut BTreeSet<u32>, labels: &mut u32) -> Result<Sequence, ParserError<'a>> {
+    let sequence = parse_sequence(iter, context, block_stack, params, labels)?;
+    // branches to this level or deeper are resolved by the nested statement
+    targets.extend(sequence.targets.range(..(block_stack.len() - 1) as u32));
+    delegates.extend(sequence.delegates.range(..(block_stack.len() - 1) as u32));
+    Ok(sequence)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_block_statement<'a>(iter: &mut impl Iterator<Item=Operator<'a>>, context: &Context, block_stack: &[Level], params: Vec<Expression>, results: &[String], targets: &mut BTreeSet<u32>, delegates: &mut BTreeSet<u32>, labels: &mut u32) -> Result<Vec<Statement>, ParserError<'a>> {
+    let mut sequence = parse_nested(iter, context, block_stack, params, targets, delegates, labels)?;
+    if !results.is_empty() {
+        if let Some(value) = sequence.result(results.len()) {
+            sequence.statements.push(Statement::Br { label: block_stack.last().unwrap().label.clone(), value: Some(value) });
+        }
+    }
+    Ok(sequence.statements)
+}
+
+/// Statements of a `catch` or `catch_all` handler, along with the clause
+/// that follows it.
+#[allow(clippy::too_many_arguments)]
+fn build_clause<'a>(iter: &mut impl Iterator<Item=Operator<'a>>, context: &Context, block_stack: &[Level], params: Vec<Expression>, results: &[String], targets: &mut BTreeSet<u32>, delegates: &mut BTreeSet<u32>, labels: &mut u32) -> Result<(Vec<Statement>, Terminator), ParserError<'a>> {
+    let mut sequence = parse_nested(iter, context, block_stack, params, targets, delegates, labels)?;
+    if !results.is_empty() {
+        if let Some(value) = sequence.result(results.len()) {
+            sequence.statements.push(Statement::Br { label: block_stack.last().unwrap().label.clone(), value: Some(value) });
+        }
+    }
+    Ok((sequence.statements, sequence.terminator))
+}
+
+/// Branches and delegations leaving the body of a `try` at the top of
+/// `stack`, each repeated from outside of the closure.
+fn try_escapes(stack: &[Level], body: &Sequence) -> Vec<Escape> {
+    let depth = stack.len() as u32;
+    let branches = body.targets.range(..depth).map(|&target| {
+        let variable = (stack[target as usize].arity > 0).then(|| escape_variable(depth, target));
+        let value = variable.as_ref().map(|variable| Expression::LocalGet(format!("{variable}.unwrap()")));
+        Escape {
+            code: target,
+            dispatch: branch(stack, depth - 1 - target, value).unwrap(),
+            variable,
+        }
+    });
+    let delegations = body.delegates.range(..depth).map(|&target| {
+        let variable = format!("d{depth}_{target}");
+        Escape {
+            code: depth + target,
+            dispatch: delegate(stack, target, Expression::LocalGet(format!("{variable}.unwrap()"))),
+            variable: Some(variable),
+        }
+    });
+    branches.chain(delegations).collect()
+}
+
+/// Move every pending non-constant value into a temporary so that it is
+/// evaluated before the side effects of the control flow that follows.
+fn spill(exprs: &mut [Expression], stmts: &mut Vec<Statement>, depth: usize, spilled: &mut u32) {
+    spill_if(exprs, stmts, depth, spilled, |expr| {
+        !matches!(expr, Expression::I32Const(_) | Expression::I64Const(_) | Expression::F32Const(_) | Expression::F64Const(_))
+    })
+}
+
+/// Move the pending values that read the local or global `name` into
+/// temporaries, so that they see it before the assignment that follows.
+fn spill_reads(exprs: &mut [Expression], stmts: &mut Vec<Statement>, depth: usize, spilled: &mut u32, name: &str) {
+    spill_if(exprs, stmts, depth, spilled, |expr| reads(&expr.emit_code(&EmitOptions::default()), name))
+}
+
+/// Move the pending values that read the global `name` or call a function,
+/// which may read any global, into temporaries before it is assigned.
+fn spill_global_reads(exprs: &mut [Expression], stmts: &mut Vec<Statement>, depth: usize, spilled: &mut u32, name: &str) {
+    spill_if(exprs, stmts, depth, spilled, |expr| {
+        let code = expr.emit_code(&EmitOptions::default());
+        reads(&code, name) || calls(&code)
+    })
+}
+
+/// Whether the emitted `code` mentions the variable `name`.
+fn reads(code: &str, name: &str) -> bool {
+    code.match_indices(name).any(|(i, _)| {
+        !code[..i].ends_with(is_ident) && !code[i + name.len()..].starts_with(is_ident)
+    })
+}
+
+/// Whether the emitted `code` may call a function of the module: a free
+/// function or a function pointer is called, rather than a method or a path
+/// like `i32::wrapping_add`.
+fn calls(code: &str) -> bool {
+    code.match_indices('(').any(|(i, _)| {
+        let callee = code[..i].trim_end_matches(is_ident);
+        code[..i].ends_with(')') || (callee.len() < i && !callee.ends_with('.') && !callee.ends_with("::"))
+    })
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn spill_if(exprs: &mut [Expression], stmts: &mut Vec<Statement>, depth: usize, spilled: &mut u32, pending: impl Fn(&Expression) -> bool) {
+    for expr in exprs.iter_mut() {
+        if !pending(expr) {
+            continue
+        }
+        let name = format!("v{depth}_{spilled}");
+        *spilled += 1;
+        let value = std::mem::replace(expr, Expression::LocalGet(name.clone()));
+        stmts.push(Statement::Let { names: vec![name], value, mutable: false });
+    }
+}
+
+/// Consume the unreachable operators following an unconditional branch up to
+/// the `end` or `else` that closes the current sequence.
+fn skip_unreachable<'a>(iter: &mut impl Iterator<Item=Operator<'a>>) -> Terminator {
+    let mut nesting = 0;
+    for op in iter {
+        match op {
+            Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } | Operator::Try { .. } => nesting += 1,
+            Operator::Else if nesting == 0 => return Terminator::Else,
+            Operator::Catch { index } if nesting == 0 => return Terminator::Catch(index),
+            Operator::CatchAll if nesting == 0 => return Terminator::CatchAll,
+            Operator::Delegate { relative_depth } if nesting == 0 => return Terminator::Delegate(relative_depth),
+            Operator::End if nesting == 0 => return Terminator::End,
+            Operator::End | Operator::Delegate { .. } => nesting -= 1,
+            _ => {}
+        }
+    }
+    Terminator::End
+}
+
+/// Statement branching unconditionally to the label `relative_depth` levels up.
+fn branch(stack: &[Level], relative_depth: u32, value: Option<Expression>) -> Option<Statement> {
+    let block_depth = (stack.len() - 1) as u32;
+    let target = block_depth.checked_sub(relative_depth)?;
+    let level = stack.get(target as usize)?;
+    if let Some(depth) = escaping_try(stack, target) {
+        return Some(Statement::Escape {
+            depth,
+            code: target,
+            variable: (level.arity > 0).then(|| escape_variable(depth, target)),
+            value,
+        })
+    }
+    Some(match level.kind {
+        LevelKind::Function(_) => Statement::Return(value),
+        LevelKind::Block | LevelKind::If | LevelKind::Try | LevelKind::Catch => Statement::Br { label: level.label.clone(), value },
+        LevelKind::Loop => Statement::Continue { label: level.label.clone(), params: level.params.clone(), value },
+    })
+}
+
+/// Depth of the innermost `try` body that a branch to the label at `target`
+/// has to leave, if any. The body runs in a closure, so the branch returns
+/// from the closure and is repeated once `catch_unwind` is done.
+pub fn escaping_try(stack: &[Level], target: u32) -> Option<u32> {
+    let nested = stack.get(target as usize + 1..)?;
+    let position = nested.iter().rposition(|level| level.kind == LevelKind::Try)?;
+    Some(target + 1 + position as u32)
+}
+
+/// Variable carrying the values of a branch to the label at `target` out of
+/// the `try` body at `depth`.
+pub fn escape_variable(depth: u32, target: u32) -> String {
+    format!("e{depth}_{target}")
+}
+
+/// Statement rethrowing a caught exception `payload` from the label at
+/// `target`, skipping the handlers of the `try` blocks nested within it.
+fn delegate(stack: &[Level], target: u32, payload: Expression) -> Statement {
+    match escaping_try(stack, target) {
+        // delegations are told apart from branches by an offset of `depth`
+        Some(depth) => Statement::Escape {
+            depth,
+            code: depth + target,
+            variable: Some(format!("d{depth}_{target}")),
+            value: Some(payload),
+        },
+        None => Statement::Resume(payload),
     }
 }
 
-fn build_block_statement_empty_type<'a>(iter: &mut impl Iterator<Item=Operator<'a>>, functions: &HashMap<u32, FunctionKind>, block_stack: &[LevelKind]) -> Result<Statement, ParserError<'a>> {
-    Ok(Statement::Block(
-        statements_from_operators(iter, &functions, Some(ParsingContext::Nested { stack: block_stack.clone().into() }))?,
-        (block_stack.len() - 1) as _
-    ))
+/// Variable holding the exception caught by the handlers of the `try` at
+/// `depth`.
+pub fn exception_variable(depth: u32) -> String {
+    format!("x{depth}")
+}
+
+/// Tuple type of the values carried by an exception.
+pub fn tuple_type(types: &[WASMType]) -> String {
+    match types {
+        [ty] => format!("({ty},)"),
+        _ => format!("({})", types.iter().map(|ty| ty.to_string()).collect::<Vec<_>>().join(", ")),
+    }
 }
 
-fn build_loop_statement_empty_type<'a>(iter: &mut impl Iterator<Item=Operator<'a>>, functions: &HashMap<u32, FunctionKind>, block_stack: &[LevelKind]) -> Result<Statement, ParserError<'a>> {
-    Ok(Statement::Loop(
-        statements_from_operators(iter, &functions, Some(ParsingContext::Nested { stack: block_stack.clone().into() }))?,
-        (block_stack.len() - 1) as _
-    ))
+/// Accessed width and memory offset of an atomic load, store or
+/// read-modify-write operator.
+fn atomic_access(op: &Operator) -> Option<(AtomicAccess, MemoryImmediate)> {
+    let (ty, bits, memarg) = match *op {
+        Operator::I32AtomicLoad { memarg }
+        | Operator::I32AtomicStore { memarg }
+        | Operator::I32AtomicRmwCmpxchg { memarg }
+        | Operator::I32AtomicRmwAdd { memarg }
+        | Operator::I32AtomicRmwSub { memarg }
+        | Operator::I32AtomicRmwAnd { memarg }
+        | Operator::I32AtomicRmwOr { memarg }
+        | Operator::I32AtomicRmwXor { memarg }
+        | Operator::I32AtomicRmwXchg { memarg } => (WASMType::I32, 32, memarg),
+        Operator::I64AtomicLoad { memarg }
+        | Operator::I64AtomicStore { memarg }
+        | Operator::I64AtomicRmwCmpxchg { memarg }
+        | Operator::I64AtomicRmwAdd { memarg }
+        | Operator::I64AtomicRmwSub { memarg }
+        | Operator::I64AtomicRmwAnd { memarg }
+        | Operator::I64AtomicRmwOr { memarg }
+        | Operator::I64AtomicRmwXor { memarg }
+        | Operator::I64AtomicRmwXchg { memarg } => (WASMType::I64, 64, memarg),
+        Operator::I32AtomicLoad8U { memarg }
+        | Operator::I32AtomicStore8 { memarg }
+        | Operator::I32AtomicRmw8CmpxchgU { memarg }
+        | Operator::I32AtomicRmw8AddU { memarg }
+        | Operator::I32AtomicRmw8SubU { memarg }
+        | Operator::I32AtomicRmw8AndU { memarg }
+        | Operator::I32AtomicRmw8OrU { memarg }
+        | Operator::I32AtomicRmw8XorU { memarg }
+        | Operator::I32AtomicRmw8XchgU { memarg } => (WASMType::I32, 8, memarg),
+        Operator::I32AtomicLoad16U { memarg }
+        | Operator::I32AtomicStore16 { memarg }
+        | Operator::I32AtomicRmw16CmpxchgU { memarg }
+        | Operator::I32AtomicRmw16AddU { memarg }
+        | Operator::I32AtomicRmw16SubU { memarg }
+        | Operator::I32AtomicRmw16AndU { memarg }
+        | Operator::I32AtomicRmw16OrU { memarg }
+        | Operator::I32AtomicRmw16XorU { memarg }
+        | Operator::I32AtomicRmw16XchgU { memarg } => (WASMType::I32, 16, memarg),
+        Operator::I64AtomicLoad8U { memarg }
+        | Operator::I64AtomicStore8 { memarg }
+        | Operator::I64AtomicRmw8CmpxchgU { memarg }
+        | Operator::I64AtomicRmw8AddU { memarg }
+        | Operator::I64AtomicRmw8SubU { memarg }
+        | Operator::I64AtomicRmw8AndU { memarg }
+        | Operator::I64AtomicRmw8OrU { memarg }
+        | Operator::I64AtomicRmw8XorU { memarg }
+        | Operator::I64AtomicRmw8XchgU { memarg } => (WASMType::I64, 8, memarg),
+        Operator::I64AtomicLoad16U { memarg }
+        | Operator::I64AtomicStore16 { memarg }
+        | Operator::I64AtomicRmw16CmpxchgU { memarg }
+        | Operator::I64AtomicRmw16AddU { memarg }
+        | Operator::I64AtomicRmw16SubU { memarg }
+        | Operator::I64AtomicRmw16AndU { memarg }
+        | Operator::I64AtomicRmw16OrU { memarg }
+        | Operator::I64AtomicRmw16XorU { memarg }
+        | Operator::I64AtomicRmw16XchgU { memarg } => (WASMType::I64, 16, memarg),
+        Operator::I64AtomicLoad32U { memarg }
+        | Operator::I64AtomicStore32 { memarg }
+        | Operator::I64AtomicRmw32CmpxchgU { memarg }
+        | Operator::I64AtomicRmw32AddU { memarg }
+        | Operator::I64AtomicRmw32SubU { memarg }
+        | Operator::I64AtomicRmw32AndU { memarg }
+        | Operator::I64AtomicRmw32OrU { memarg }
+        | Operator::I64AtomicRmw32XorU { memarg }
+        | Operator::I64AtomicRmw32XchgU { memarg } => (WASMType::I64, 32, memarg),
+        _ => return None,
+    };
+    Some((AtomicAccess { ty, bits }, memarg))
 }
 
-pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>, functions: &HashMap<u32, FunctionKind>, parsing_context: Option<ParsingContext>) -> Result<Vec<Statement>, ParserError<'a>> {
-    let mut exprs: Vec<Expression> = vec![];
+/// Method of the `std::sync::atomic` types implementing an atomic
+/// read-modify-write operator.
+fn atomic_rmw_method(op: &Operator) -> Option<&'static str> {
+    Some(match op {
+        Operator::I32AtomicRmwAdd { .. }
+        | Operator::I64AtomicRmwAdd { .. }
+        | Operator::I32AtomicRmw8AddU { .. }
+        | Operator::I32AtomicRmw16AddU { .. }
+        | Operator::I64AtomicRmw8AddU { .. }
+        | Operator::I64AtomicRmw16AddU { .. }
+        | Operator::I64AtomicRmw32AddU { .. } => "fetch_add",
+        Operator::I32AtomicRmwSub { .. }
+        | Operator::I64AtomicRmwSub { .. }
+        | Operator::I32AtomicRmw8SubU { .. }
+        | Operator::I32AtomicRmw16SubU { .. }
+        | Operator::I64AtomicRmw8SubU { .. }
+        | Operator::I64AtomicRmw16SubU { .. }
+        | Operator::I64AtomicRmw32SubU { .. } => "fetch_sub",
+        Operator::I32AtomicRmwAnd { .. }
+        | Operator::I64AtomicRmwAnd { .. }
+        | Operator::I32AtomicRmw8AndU { .. }
+        | Operator::I32AtomicRmw16AndU { .. }
+        | Operator::I64AtomicRmw8AndU { .. }
+        | Operator::I64AtomicRmw16AndU { .. }
+        | Operator::I64AtomicRmw32AndU { .. } => "fetch_and",
+        Operator::I32AtomicRmwOr { .. }
+        | Operator::I64AtomicRmwOr { .. }
+        | Operator::I32AtomicRmw8OrU { .. }
+        | Operator::I32AtomicRmw16OrU { .. }
+        | Operator::I64AtomicRmw8OrU { .. }
+        | Operator::I64AtomicRmw16OrU { .. }
+        | Operator::I64AtomicRmw32OrU { .. } => "fetch_or",
+        Operator::I32AtomicRmwXor { .. }
+        | Operator::I64AtomicRmwXor { .. }
+        | Operator::I32AtomicRmw8XorU { .. }
+        | Operator::I32AtomicRmw16XorU { .. }
+        | Operator::I64AtomicRmw8XorU { .. }
+        | Operator::I64AtomicRmw16XorU { .. }
+        | Operator::I64AtomicRmw32XorU { .. } => "fetch_xor",
+        Operator::I32AtomicRmwXchg { .. }
+        | Operator::I64AtomicRmwXchg { .. }
+        | Operator::I32AtomicRmw8XchgU { .. }
+        | Operator::I32AtomicRmw16XchgU { .. }
+        | Operator::I64AtomicRmw8XchgU { .. }
+        | Operator::I64AtomicRmw16XchgU { .. }
+        | Operator::I64AtomicRmw32XchgU { .. } => "swap",
+        _ => return None,
+    })
+}
+
+/// Feature that `op` belongs to, of those that some emitted code cannot
+/// express.
+pub fn operator_feature(op: &Operator) -> Option<Feature> {
+    match op {
+        Operator::MemoryAtomicNotify { .. } | Operator::MemoryAtomicWait32 { .. } | Operator::MemoryAtomicWait64 { .. } => Some(Feature::Atomics),
+        Operator::Try { .. } | Operator::Catch { .. } | Operator::CatchAll | Operator::Delegate { .. } | Operator::Throw { .. } | Operator::Rethrow { .. } => Some(Feature::Exceptions),
+        _ if atomic_access(op).is_some() => Some(Feature::Atomics),
+        _ if simd_access(op).is_some() => Some(Feature::SimdMemory),
+        Operator::V128Const { .. } => Some(Feature::Simd),
+        _ if simd_intrinsic(op).is_some() => Some(Feature::Simd),
+        _ => None,
+    }
+}
+
+/// Intrinsic and memory operand of a SIMD load or store.
+fn simd_access(op: &Operator) -> Option<(SimdAccess, MemoryImmediate)> {
+    let (name, pointee, memarg, lane) = match *op {
+        Operator::V128Load { memarg } => ("v128_load", "::std::arch::wasm32::v128", memarg, None),
+        Operator::V128Load8x8S { memarg } => ("i16x8_load_extend_i8x8", "i8", memarg, None),
+        Operator::V128Load8x8U { memarg } => ("i16x8_load_extend_u8x8", "u8", memarg, None),
+        Operator::V128Load16x4S { memarg } => ("i32x4_load_extend_i16x4", "i16", memarg, None),
+        Operator::V128Load16x4U { memarg } => ("i32x4_load_extend_u16x4", "u16", memarg, None),
+        Operator::V128Load32x2S { memarg } => ("i64x2_load_extend_i32x2", "i32", memarg, None),
+        Operator::V128Load32x2U { memarg } => ("i64x2_load_extend_u32x2", "u32", memarg, None),
+        Operator::V128Load8Splat { memarg } => ("v128_load8_splat", "u8", memarg, None),
+        Operator::V128Load16Splat { memarg } => ("v128_load16_splat", "u16", memarg, None),
+        Operator::V128Load32Splat { memarg } => ("v128_load32_splat", "u32", memarg, None),
+        Operator::V128Load64Splat { memarg } => ("v128_load64_splat", "u64", memarg, None),
+        Operator::V128Load32Zero { memarg } => ("v128_load32_zero", "u32", memarg, None),
+        Operator::V128Load64Zero { memarg } => ("v128_load64_zero", "u64", memarg, None),
+        Operator::V128Load8Lane { memarg, lane } => ("v128_load8_lane", "u8", memarg, Some(lane)),
+        Operator::V128Load16Lane { memarg, lane } => ("v128_load16_lane", "u16", memarg, Some(lane)),
+        Operator::V128Load32Lane { memarg, lane } => ("v128_load32_lane", "u32", memarg, Some(lane)),
+        Operator::V128Load64Lane { memarg, lane } => ("v128_load64_lane", "u64", memarg, Some(lane)),
+        Operator::V128Store { memarg } => ("v128_store", "::std::arch::wasm32::v128", memarg, None),
+        Operator::V128Store8Lane { memarg, lane } => ("v128_store8_lane", "u8", memarg, Some(lane)),
+        Operator::V128Store16Lane { memarg, lane } => ("v128_store16_lane", "u16", memarg, Some(lane)),
+        Operator::V128Store32Lane { memarg, lane } => ("v128_store32_lane", "u32", memarg, Some(lane)),
+        Operator::V128Store64Lane { memarg, lane } => ("v128_store64_lane", "u64", memarg, Some(lane)),
+        _ => return None,
+    };
+
+    Some((SimdAccess { name, pointee, lane }, memarg))
+}
+
+/// Intrinsic and const lane arguments of a SIMD operator that does not
+/// access memory. The relaxed SIMD operators have no stable intrinsics.
+fn simd_intrinsic(op: &Operator) -> Option<(SimdIntrinsic, Vec<u8>)> {
+    let unary = |name| SimdIntrinsic::new(name, 1);
+    let binary = |name| SimdIntrinsic::new(name, 2);
+    let shift = |name| SimdIntrinsic::new(name, 2).scalar("u32");
+    let test = |name| SimdIntrinsic::new(name, 1).result("i32");
+
+    let lanes = match *op {
+        Operator::I8x16Shuffle { lanes } => lanes.to_vec(),
+        Operator::I8x16ExtractLaneS { lane }
+        | Operator::I8x16ExtractLaneU { lane }
+        | Operator::I8x16ReplaceLane { lane }
+        | Operator::I16x8ExtractLaneS { lane }
+        | Operator::I16x8ExtractLaneU { lane }
+        | Operator::I16x8ReplaceLane { lane }
+        | Operator::I32x4ExtractLane { lane }
+        | Operator::I32x4ReplaceLane { lane }
+        | Operator::I64x2ExtractLane { lane }
+        | Operator::I64x2ReplaceLane { lane }
+        | Operator::F32x4ExtractLane { lane }
+        | Operator::F32x4ReplaceLane { lane }
+        | Operator::F64x2ExtractLane { lane }
+        | Operator::F64x2ReplaceLane { lane } => vec![lane],
+        _ => vec![],
+    };
+
+    let intrinsic = match *op {
+        Operator::I8x16Shuffle { .. } => binary("i8x16_shuffle"),
+        Operator::I8x16ExtractLaneS { .. } => unary("i8x16_extract_lane").result("i32"),
+        Operator::I8x16ExtractLaneU { .. } => unary("u8x16_extract_lane").result("i32"),
+        Operator::I8x16ReplaceLane { .. } => binary("i8x16_replace_lane").scalar("i8"),
+        Operator::I16x8ExtractLaneS { .. } => unary("i16x8_extract_lane").result("i32"),
+        Operator::I16x8ExtractLaneU { .. } => unary("u16x8_extract_lane").result("i32"),
+        Operator::I16x8ReplaceLane { .. } => binary("i16x8_replace_lane").scalar("i16"),
+        Operator::I32x4ExtractLane { .. } => unary("i32x4_extract_lane"),
+        Operator::I32x4ReplaceLane { .. } => binary("i32x4_replace_lane"),
+        Operator::I64x2ExtractLane { .. } => unary("i64x2_extract_lane"),
+        Operator::I64x2ReplaceLane { .. } => binary("i64x2_replace_lane"),
+        Operator::F32x4ExtractLane { .. } => unary("f32x4_extract_lane"),
+        Operator::F32x4ReplaceLane { .. } => binary("f32x4_replace_lane"),
+        Operator::F64x2ExtractLane { .. } => unary("f64x2_extract_lane"),
+        Operator::F64x2ReplaceLane { .. } => binary("f64x2_replace_lane"),
+        Operator::I8x16Swizzle => binary("i8x16_swizzle"),
+        Operator::I8x16Splat => unary("i8x16_splat").scalar("i8"),
+        Operator::I16x8Splat => unary("i16x8_splat").scalar("i16"),
+        Operator::I32x4Splat => unary("i32x4_splat"),
+        Operator::I64x2Splat => unary("i64x2_splat"),
+        Operator::F32x4Splat => unary("f32x4_splat"),
+        Operator::F64x2Splat => unary("f64x2_splat"),
+        Operator::I8x16Eq => binary("i8x16_eq"),
+        Operator::I8x16Ne => binary("i8x16_ne"),
+        Operator::I8x16LtS => binary("i8x16_lt"),
+        Operator::I8x16LtU => binary("u8x16_lt"),
+        Operator::I8x16GtS => binary("i8x16_gt"),
+        Operator::I8x16GtU => binary("u8x16_gt"),
+        Operator::I8x16LeS => binary("i8x16_le"),
+        Operator::I8x16LeU => binary("u8x16_le"),
+        Operator::I8x16GeS => binary("i8x16_ge"),
+        Operator::I8x16GeU => binary("u8x16_ge"),
+        Operator::I16x8Eq => binary("i16x8_eq"),
+        Operator::I16x8Ne => binary("i16x8_ne"),
+        Operator::I16x8LtS => binary("i16x8_lt"),
+        Operator::I16x8LtU => binary("u16x8_lt"),
+        Operator::I16x8GtS => binary("i16x8_gt"),
+        Operator::I16x8GtU => binary("u16x8_gt"),
+        Operator::I16x8LeS => binary("i16x8_le"),
+        Operator::I16x8LeU => binary("u16x8_le"),
+        Operator::I16x8GeS => binary("i16x8_ge"),
+        Operator::I16x8GeU => binary("u16x8_ge"),
+        Operator::I32x4Eq => binary("i32x4_eq"),
+        Operator::I32x4Ne => binary("i32x4_ne"),
+        Operator::I32x4LtS => binary("i32x4_lt"),
+        Operator::I32x4LtU => binary("u32x4_lt"),
+        Operator::I32x4GtS => binary("i32x4_gt"),
+        Operator::I32x4GtU => binary("u32x4_gt"),
+        Operator::I32x4LeS => binary("i32x4_le"),
+        Operator::I32x4LeU => binary("u32x4_le"),
+        Operator::I32x4GeS => binary("i32x4_ge"),
+        Operator::I32x4GeU => binary("u32x4_ge"),
+        Operator::I64x2Eq => binary("i64x2_eq"),
+        Operator::I64x2Ne => binary("i64x2_ne"),
+        Operator::I64x2LtS => binary("i64x2_lt"),
+        Operator::I64x2GtS => binary("i64x2_gt"),
+        Operator::I64x2LeS => binary("i64x2_le"),
+        Operator::I64x2GeS => binary("i64x2_ge"),
+        Operator::F32x4Eq => binary("f32x4_eq"),
+        Operator::F32x4Ne => binary("f32x4_ne"),
+        Operator::F32x4Lt => binary("f32x4_lt"),
+        Operator::F32x4Gt => binary("f32x4_gt"),
+        Operator::F32x4Le => binary("f32x4_le"),
+        Operator::F32x4Ge => binary("f32x4_ge"),
+        Operator::F64x2Eq => binary("f64x2_eq"),
+        Operator::F64x2Ne => binary("f64x2_ne"),
+        Operator::F64x2Lt => binary("f64x2_lt"),
+        Operator::F64x2Gt => binary("f64x2_gt"),
+        Operator::F64x2Le => binary("f64x2_le"),
+        Operator::F64x2Ge => binary("f64x2_ge"),
+        Operator::V128Not => unary("v128_not"),
+        Operator::V128And => binary("v128_and"),
+        Operator::V128AndNot => binary("v128_andnot"),
+        Operator::V128Or => binary("v128_or"),
+        Operator::V128Xor => binary("v128_xor"),
+        Operator::V128Bitselect => SimdIntrinsic::new("v128_bitselect", 3),
+        Operator::V128AnyTrue => test("v128_any_true"),
+        Operator::I8x16Abs => unary("i8x16_abs"),
+        Operator::I8x16Neg => unary("i8x16_neg"),
+        Operator::I8x16Popcnt => unary("i8x16_popcnt"),
+        Operator::I8x16AllTrue => test("i8x16_all_true"),
+        Operator::I8x16Bitmask => test("i8x16_bitmask"),
+        Operator::I8x16NarrowI16x8S => binary("i8x16_narrow_i16x8"),
+        Operator::I8x16NarrowI16x8U => binary("u8x16_narrow_i16x8"),
+        Operator::I8x16Shl => shift("i8x16_shl"),
+        Operator::I8x16ShrS => shift("i8x16_shr"),
+        Operator::I8x16ShrU => shift("u8x16_shr"),
+        Operator::I8x16Add => binary("i8x16_add"),
+        Operator::I8x16AddSatS => binary("i8x16_add_sat"),
+        Operator::I8x16AddSatU => binary("u8x16_add_sat"),
+        Operator::I8x16Sub => binary("i8x16_sub"),
+        Operator::I8x16SubSatS => binary("i8x16_sub_sat"),
+        Operator::I8x16SubSatU => binary("u8x16_sub_sat"),
+        Operator::I8x16MinS => binary("i8x16_min"),
+        Operator::I8x16MinU => binary("u8x16_min"),
+        Operator::I8x16MaxS => binary("i8x16_max"),
+        Operator::I8x16MaxU => binary("u8x16_max"),
+        Operator::I8x16RoundingAverageU => binary("u8x16_avgr"),
+        Operator::I16x8ExtAddPairwiseI8x16S => unary("i16x8_extadd_pairwise_i8x16"),
+        Operator::I16x8ExtAddPairwiseI8x16U => unary("i16x8_extadd_pairwise_u8x16"),
+        Operator::I16x8Abs => unary("i16x8_abs"),
+        Operator::I16x8Neg => unary("i16x8_neg"),
+        Operator::I16x8Q15MulrSatS => binary("i16x8_q15mulr_sat"),
+        Operator::I16x8AllTrue => test("i16x8_all_true"),
+        Operator::I16x8Bitmask => test("i16x8_bitmask"),
+        Operator::I16x8NarrowI32x4S => binary("i16x8_narrow_i32x4"),
+        Operator::I16x8NarrowI32x4U => binary("u16x8_narrow_i32x4"),
+        Operator::I16x8ExtendLowI8x16S => unary("i16x8_extend_low_i8x16"),
+        Operator::I16x8ExtendHighI8x16S => unary("i16x8_extend_high_i8x16"),
+        Operator::I16x8ExtendLowI8x16U => unary("i16x8_extend_low_u8x16"),
+        Operator::I16x8ExtendHighI8x16U => unary("i16x8_extend_high_u8x16"),
+        Operator::I16x8Shl => shift("i16x8_shl"),
+        Operator::I16x8ShrS => shift("i16x8_shr"),
+        Operator::I16x8ShrU => shift("u16x8_shr"),
+        Operator::I16x8Add => binary("i16x8_add"),
+        Operator::I16x8AddSatS => binary("i16x8_add_sat"),
+        Operator::I16x8AddSatU => binary("u16x8_add_sat"),
+        Operator::I16x8Sub => binary("i16x8_sub"),
+        Operator::I16x8SubSatS => binary("i16x8_sub_sat"),
+        Operator::I16x8SubSatU => binary("u16x8_sub_sat"),
+        Operator::I16x8Mul => binary("i16x8_mul"),
+        Operator::I16x8MinS => binary("i16x8_min"),
+        Operator::I16x8MinU => binary("u16x8_min"),
+        Operator::I16x8MaxS => binary("i16x8_max"),
+        Operator::I16x8MaxU => binary("u16x8_max"),
+        Operator::I16x8RoundingAverageU => binary("u16x8_avgr"),
+        Operator::I16x8ExtMulLowI8x16S => binary("i16x8_extmul_low_i8x16"),
+        Operator::I16x8ExtMulHighI8x16S => binary("i16x8_extmul_high_i8x16"),
+        Operator::I16x8ExtMulLowI8x16U => binary("i16x8_extmul_low_u8x16"),
+        Operator::I16x8ExtMulHighI8x16U => binary("i16x8_extmul_high_u8x16"),
+        Operator::I32x4ExtAddPairwiseI16x8S => unary("i32x4_extadd_pairwise_i16x8"),
+        Operator::I32x4ExtAddPairwiseI16x8U => unary("i32x4_extadd_pairwise_u16x8"),
+        Operator::I32x4Abs => unary("i32x4_abs"),
+        Operator::I32x4Neg => unary("i32x4_neg"),
+        Operator::I32x4AllTrue => test("i32x4_all_true"),
+        Operator::I32x4Bitmask => test("i32x4_bitmask"),
+        Operator::I32x4ExtendLowI16x8S => unary("i32x4_extend_low_i16x8"),
+        Operator::I32x4ExtendHighI16x8S => unary("i32x4_extend_high_i16x8"),
+        Operator::I32x4ExtendLowI16x8U => unary("i32x4_extend_low_u16x8"),
+        Operator::I32x4ExtendHighI16x8U => unary("i32x4_extend_high_u16x8"),
+        Operator::I32x4Shl => shift("i32x4_shl"),
+        Operator::I32x4ShrS => shift("i32x4_shr"),
+        Operator::I32x4ShrU => shift("u32x4_shr"),
+        Operator::I32x4Add => binary("i32x4_add"),
+        Operator::I32x4Sub => binary("i32x4_sub"),
+        Operator::I32x4Mul => binary("i32x4_mul"),
+        Operator::I32x4MinS => binary("i32x4_min"),
+        Operator::I32x4MinU => binary("u32x4_min"),
+        Operator::I32x4MaxS => binary("i32x4_max"),
+        Operator::I32x4MaxU => binary("u32x4_max"),
+        Operator::I32x4DotI16x8S => binary("i32x4_dot_i16x8"),
+        Operator::I32x4ExtMulLowI16x8S => binary("i32x4_extmul_low_i16x8"),
+        Operator::I32x4ExtMulHighI16x8S => binary("i32x4_extmul_high_i16x8"),
+        Operator::I32x4ExtMulLowI16x8U => binary("i32x4_extmul_low_u16x8"),
+        Operator::I32x4ExtMulHighI16x8U => binary("i32x4_extmul_high_u16x8"),
+        Operator::I64x2Abs => unary("i64x2_abs"),
+        Operator::I64x2Neg => unary("i64x2_neg"),
+        Operator::I64x2AllTrue => test("i64x2_all_true"),
+        Operator::I64x2Bitmask => test("i64x2_bitmask"),
+        Operator::I64x2ExtendLowI32x4S => unary("i64x2_extend_low_i32x4"),
+        Operator::I64x2ExtendHighI32x4S => unary("i64x2_extend_high_i32x4"),
+        Operator::I64x2ExtendLowI32x4U => unary("i64x2_extend_low_u32x4"),
+        Operator::I64x2ExtendHighI32x4U => unary("i64x2_extend_high_u32x4"),
+        Operator::I64x2Shl => shift("i64x2_shl"),
+        Operator::I64x2ShrS => shift("i64x2_shr"),
+        Operator::I64x2ShrU => shift("u64x2_shr"),
+        Operator::I64x2Add => binary("i64x2_add"),
+        Operator::I64x2Sub => binary("i64x2_sub"),
+        Operator::I64x2Mul => binary("i64x2_mul"),
+        Operator::I64x2ExtMulLowI32x4S => binary("i64x2_extmul_low_i32x4"),
+        Operator::I64x2ExtMulHighI32x4S => binary("i64x2_extmul_high_i32x4"),
+        Operator::I64x2ExtMulLowI32x4U => binary("i64x2_extmul_low_u32x4"),
+        Operator::I64x2ExtMulHighI32x4U => binary("i64x2_extmul_high_u32x4"),
+        Operator::F32x4Ceil => unary("f32x4_ceil"),
+        Operator::F32x4Floor => unary("f32x4_floor"),
+        Operator::F32x4Trunc => unary("f32x4_trunc"),
+        Operator::F32x4Nearest => unary("f32x4_nearest"),
+        Operator::F32x4Abs => unary("f32x4_abs"),
+        Operator::F32x4Neg => unary("f32x4_neg"),
+        Operator::F32x4Sqrt => unary("f32x4_sqrt"),
+        Operator::F32x4Add => binary("f32x4_add"),
+        Operator::F32x4Sub => binary("f32x4_sub"),
+        Operator::F32x4Mul => binary("f32x4_mul"),
+        Operator::F32x4Div => binary("f32x4_div"),
+        Operator::F32x4Min => binary("f32x4_min"),
+        Operator::F32x4Max => binary("f32x4_max"),
+        Operator::F32x4PMin => binary("f32x4_pmin"),
+        Operator::F32x4PMax => binary("f32x4_pmax"),
+        Operator::F64x2Ceil => unary("f64x2_ceil"),
+        Operator::F64x2Floor => unary("f64x2_floor"),
+        Operator::F64x2Trunc => unary("f64x2_trunc"),
+        Operator::F64x2Nearest => unary("f64x2_nearest"),
+        Operator::F64x2Abs => unary("f64x2_abs"),
+        Operator::F64x2Neg => unary("f64x2_neg"),
+        Operator::F64x2Sqrt => unary("f64x2_sqrt"),
+        Operator::F64x2Add => binary("f64x2_add"),
+        Operator::F64x2Sub => binary("f64x2_sub"),
+        Operator::F64x2Mul => binary("f64x2_mul"),
+        Operator::F64x2Div => binary("f64x2_div"),
+        Operator::F64x2Min => binary("f64x2_min"),
+        Operator::F64x2Max => binary("f64x2_max"),
+        Operator::F64x2PMin => binary("f64x2_pmin"),
+        Operator::F64x2PMax => binary("f64x2_pmax"),
+        Operator::I32x4TruncSatF32x4S => unary("i32x4_trunc_sat_f32x4"),
+        Operator::I32x4TruncSatF32x4U => unary("u32x4_trunc_sat_f32x4"),
+        Operator::F32x4ConvertI32x4S => unary("f32x4_convert_i32x4"),
+        Operator::F32x4ConvertI32x4U => unary("f32x4_convert_u32x4"),
+        Operator::I32x4TruncSatF64x2SZero => unary("i32x4_trunc_sat_f64x2_zero"),
+        Operator::I32x4TruncSatF64x2UZero => unary("u32x4_trunc_sat_f64x2_zero"),
+        Operator::F64x2ConvertLowI32x4S => unary("f64x2_convert_low_i32x4"),
+        Operator::F64x2ConvertLowI32x4U => unary("f64x2_convert_low_u32x4"),
+        Operator::F32x4DemoteF64x2Zero => unary("f32x4_demote_f64x2_zero"),
+        Operator::F64x2PromoteLowF32x4 => unary("f64x2_promote_low_f32x4"),
+        _ => return None,
+    };
+
+    Some((intrinsic, lanes))
+}
+
+/// Variable holding local `index` of the function at the bottom of `stack`.
+fn local(context: &Context, stack: &[Level], index: u32) -> String {
+    match stack[0].kind {
+        LevelKind::Function(function) => context.local_name(function, index),
+        _ => format!("p{index}"),
+    }
+}
+
+/// Label of the next block nested in `stack`: its name from the `name`
+/// section, or `B{depth}`, made distinct from the labels around it.
+fn block_label(context: &Context, stack: &[Level], labels: &mut u32) -> String {
+    let name = match stack[0].kind {
+        LevelKind::Function(function) => context.label_name(function, *labels),
+        _ => None,
+    };
+    *labels += 1;
+    let mut used = stack.iter().map(|level| level.label.clone()).collect();
+    names::assign(name.unwrap_or(&format!("B{}", stack.len())), &mut used)
+}
+
+/// Label targeted by a branch `relative_depth` levels up.
+fn branch_target(stack: &[Level], relative_depth: u32) -> Option<&Level> {
+    stack.get(((stack.len() - 1) as u32).checked_sub(relative_depth)? as usize)
+}
+
+pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>, context: &Context, index: u32, ty: &FuncType) -> Result<Vec<Statement>, ParserError<'a>> {
+    let stack = [Level {
+        kind: LevelKind::Function(index),
+        arity: ty.returns.len(),
+        params: (0..ty.params.len() as u32).map(|i| context.local_name(index, i)).collect(),
+        label: "B0".to_string(),
+    }];
+    let mut sequence = parse_sequence(iter, context, &stack, vec![], &mut 0)?;
+
+    if !ty.returns.is_empty() {
+        if let Some(expr) = sequence.result(ty.returns.len()) {
+            sequence.statements.push(Statement::Unassigned(expr))
+        }
+    }
+
+    Ok(sequence.statements)
+}
+
+fn parse_sequence<'a>(iter: &mut impl Iterator<Item=Operator<'a>>, context: &Context, stack: &[Level], params: Vec<Expression>, labels: &mut u32) -> Result<Sequence, ParserError<'a>> {
+    let mut exprs: Vec<Expression> = params;
     let mut stmts: Vec<Statement> = vec![];
+    let mut targets = BTreeSet::new();
+    let mut delegates = BTreeSet::new();
+    let mut terminator = Terminator::End;
+    let mut diverges = false;
+    let depth = stack.len();
+    let mut spilled = 0;
 
+    #[allow(clippy::while_let_loop)]
     loop {
         if let Some(op) = iter.next() {
             match op {
-                Operator::Unreachable => stmts.push(Statement::Unreachable),
+                Operator::Unreachable => {
+                    stmts.push(Statement::Unreachable);
+                    terminator = skip_unreachable(iter);
+                    diverges = true;
+                    break
+                },
                 Operator::Nop => stmts.push(Statement::Nop),
                 Operator::Block {
                     ty,
                 } => {
-                    match ty {
-                        BlockType::Empty => {
-                            let mut items = if let Some(ParsingContext::Nested { stack }) = parsing_context.clone() {
-                                stack
-                            } else {
-                                vec![]
-                            };
-                            items.push(LevelKind::Block);
-                            stmts.push(build_block_statement_empty_type(
-                                iter,
-                                &functions,
-                                &items,
-                            )?);
-                        },
-                        BlockType::Type(_typ) => {
-                            return Err(ParserError::Unimplemented(op))
-                        },
-                        BlockType::FuncType(_index) => {
-                            return Err(ParserError::Unimplemented(op))
-                        },
-                    }
+                    let signature = match block_signature(context, ty) {
+                        Some(signature) if signature.params.len() <= exprs.len() => signature,
+                        _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                    };
+                    spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                    let params = exprs.split_off(exprs.len() - signature.params.len());
+                    let mut items = stack.to_vec();
+                    let label = block_label(context, stack, labels);
+                    items.push(Level::new(LevelKind::Block, signature.returns.len(), label.clone()));
+                    let results = temporaries(depth, &mut spilled, signature.returns.len());
+                    stmts.push(Statement::Block(
+                        build_block_statement(iter, context, &items, params, &results, &mut targets, &mut delegates, labels)?,
+                        label,
+                        results.clone(),
+                    ));
+                    exprs.extend(results.into_iter().map(Expression::LocalGet));
                 },
                 Operator::Loop {
                     ty
                 } => {
-                    match ty {
-                        BlockType::Empty => {
-                            let mut items = if let Some(ParsingContext::Nested { stack }) = parsing_context.clone() {
-                                stack
-                            } else {
-                                vec![]
-                            };
-                            items.push(LevelKind::Loop);
-                            stmts.push(build_loop_statement_empty_type(
-                                iter,
-                                &functions,
-                                &items,
-                            )?);
-                        },
-                        BlockType::Type(_typ) => {
-                            return Err(ParserError::Unimplemented(op))
-                        },
-                        BlockType::FuncType(_index) => {
-                            return Err(ParserError::Unimplemented(op))
-                        },
-                    }
-                },
-                // Operator::If {
-                //     ty,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::Else => return Err(ParserError::Unimplemented),
-                // Operator::Try {
-                //     ty,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::Catch {
-                //     index,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::Throw {
-                //     index,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::Rethrow {
-                //     relative_depth,
-                // } => return Err(ParserError::Unimplemented),
-                Operator::End => {
-                    match &parsing_context {
-                        Some(ParsingContext::Nested { .. }) | None => break,
-                        // _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    let signature = match block_signature(context, ty) {
+                        Some(signature) if signature.params.len() <= exprs.len() => signature,
+                        _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                    };
+                    spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                    let params = exprs.split_off(exprs.len() - signature.params.len());
+                    // loop parameters are rebound by every branch back to the loop
+                    let names = (0..params.len()).map(|i| format!("l{depth}_{i}")).collect::<Vec<_>>();
+                    if let Some(value) = branch_value(params) {
+                        stmts.push(Statement::Let { names: names.clone(), value, mutable: true });
+                    }
+                    let label = block_label(context, stack, labels);
+                    let mut items = stack.to_vec();
+                    items.push(Level {
+                        kind: LevelKind::Loop,
+                        arity: names.len(),
+                        params: names.clone(),
+                        label: label.clone(),
+                    });
+                    let results = temporaries(depth, &mut spilled, signature.returns.len());
+                    stmts.push(Statement::Loop(
+                        build_block_statement(iter, context, &items, names.into_iter().map(Expression::LocalGet).collect(), &results, &mut targets, &mut delegates, labels)?,
+                        label,
+                        results.clone(),
+                    ));
+                    exprs.extend(results.into_iter().map(Expression::LocalGet));
+                },
+                Operator::If {
+                    ty,
+                } => {
+                    let cond = exprs.pop();
+                    let (cond, signature) = match (cond, block_signature(context, ty)) {
+                        (Some(cond), Some(signature)) if signature.params.len() <= exprs.len() => (cond, signature),
+                        _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                    };
+                    spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                    let params = exprs.split_off(exprs.len() - signature.params.len());
+                    let mut items = stack.to_vec();
+                    let label = block_label(context, stack, labels);
+                    items.push(Level::new(LevelKind::If, signature.returns.len(), label.clone()));
+                    let if_depth = depth as u32;
+                    let arity = signature.returns.len();
+
+                    let mut then_seq = parse_nested(iter, context, &items, params.clone(), &mut targets, &mut delegates, labels)?;
+                    let mut else_seq = if then_seq.terminator == Terminator::Else {
+                        Some(parse_nested(iter, context, &items, params.clone(), &mut targets, &mut delegates, labels)?)
+                    } else {
+                        None
+                    };
+                    let labeled = then_seq.targets.contains(&if_depth) || else_seq.as_ref().is_some_and(|seq| seq.targets.contains(&if_depth));
+                    let then_value = then_seq.result(arity);
+                    // a missing `else` passes the parameters through as results
+                    let else_value = match else_seq.as_mut() {
+                        Some(seq) => seq.result(arity),
+                        None => branch_value(params),
+                    };
+                    let else_stmts = else_seq.map_or(vec![], |seq| seq.statements);
+                    let then_stmts = then_seq.statements;
+
+                    if arity == 0 {
+                        stmts.push(Statement::If { cond, then_stmts, else_stmts, label: labeled.then_some(label) })
+                    } else {
+                        let if_else = Expression::IfElse {
+                            cond: Box::new(cond),
+                            then_stmts,
+                            then_value: then_value.map(Box::new),
+                            else_stmts,
+                            else_value: else_value.map(Box::new),
+                            label: labeled.then_some(label),
+                        };
+                        if arity == 1 {
+                            exprs.push(if_else)
+                        } else {
+                            let results = temporaries(depth, &mut spilled, arity);
+                            stmts.push(Statement::Let { names: results.clone(), value: if_else, mutable: false });
+                            exprs.extend(results.into_iter().map(Expression::LocalGet));
+                        }
+                    }
+                },
+                Operator::Else => {
+                    terminator = Terminator::Else;
+                    break
+                },
+                Operator::Try {
+                    ty,
+                } => {
+                    let signature = match block_signature(context, ty) {
+                        Some(signature) if signature.params.len() <= exprs.len() => signature,
+                        _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                    };
+                    spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                    let params = exprs.split_off(exprs.len() - signature.params.len());
+                    let try_depth = depth as u32;
+                    let mut items = stack.to_vec();
+                    let label = block_label(context, stack, labels);
+                    items.push(Level::new(LevelKind::Try, signature.returns.len(), label.clone()));
+                    let results = temporaries(depth, &mut spilled, signature.returns.len());
+
+                    let mut sequence = parse_nested(iter, context, &items, params, &mut targets, &mut delegates, labels)?;
+                    let escapes = try_escapes(stack, &sequence);
+                    if !results.is_empty() {
+                        if let Some(value) = sequence.result(results.len()) {
+                            sequence.statements.push(Statement::Br { label: label.clone(), value: Some(value) });
+                        }
+                    }
+                    let body = sequence.statements;
+                    let mut clause = sequence.terminator;
+
+                    // handlers run outside of the closure, at the same depth as the body
+                    items.last_mut().unwrap().kind = LevelKind::Catch;
+                    let mut catches = vec![];
+                    let mut catch_all = None;
+                    let mut delegated = None;
+                    loop {
+                        match clause {
+                            Terminator::Catch(tag) => {
+                                let ty = match context.get_tag_by_index(tag) {
+                                    Some(ty) => ty,
+                                    None => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                                };
+                                let names = (0..ty.params.len()).map(|i| format!("c{depth}_{i}")).collect::<Vec<_>>();
+                                let values = names.iter().cloned().map(Expression::LocalGet).collect();
+                                let (statements, next) = build_clause(iter, context, &items, values, &results, &mut targets, &mut delegates, labels)?;
+                                catches.push(Catch { tag, types: ty.params.clone(), names, statements });
+                                clause = next;
+                            },
+                            Terminator::CatchAll => {
+                                let (statements, next) = build_clause(iter, context, &items, vec![], &results, &mut targets, &mut delegates, labels)?;
+                                catch_all = Some(statements);
+                                clause = next;
+                            },
+                            Terminator::Delegate(relative_depth) => {
+                                // the label of the `try` itself is not counted
+                                let target = match try_depth.checked_sub(relative_depth + 1) {
+                                    Some(target) => target,
+                                    None => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                                };
+                                delegated = Some(Box::new(delegate(stack, target, Expression::LocalGet("payload".to_string()))));
+                                delegates.insert(target);
+                                break
+                            },
+                            Terminator::End | Terminator::Else => break,
+                        }
+                    }
+
+                    stmts.push(Statement::Try {
+                        body,
+                        depth: try_depth,
+                        label,
+                        results: results.clone(),
+                        escapes,
+                        catches,
+                        catch_all,
+                        delegate: delegated,
+                    });
+                    exprs.extend(results.into_iter().map(Expression::LocalGet));
+                },
+                Operator::Catch {
+                    index,
+                } => {
+                    terminator = Terminator::Catch(index);
+                    break
+                },
+                Operator::CatchAll => {
+                    terminator = Terminator::CatchAll;
+                    break
+                },
+                Operator::Delegate {
+                    relative_depth,
+                } => {
+                    terminator = Terminator::Delegate(relative_depth);
+                    break
+                },
+                Operator::Throw {
+                    index,
+                } => {
+                    let ty = match context.get_tag_by_index(index) {
+                        Some(ty) if ty.params.len() <= exprs.len() => ty,
+                        _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                    };
+                    let values = exprs.split_off(exprs.len() - ty.params.len());
+                    stmts.push(Statement::Throw { tag: index, types: ty.params.clone(), values });
+                    terminator = skip_unreachable(iter);
+                    diverges = true;
+                    break
+                },
+                Operator::Rethrow {
+                    relative_depth,
+                } => {
+                    match branch_target(stack, relative_depth) {
+                        Some(Level { kind: LevelKind::Catch, .. }) => {
+                            stmts.push(Statement::Rethrow(exception_variable(depth as u32 - 1 - relative_depth)))
+                        },
+                        _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
                     }
+                    terminator = skip_unreachable(iter);
+                    diverges = true;
+                    break
                 },
+                Operator::End => break,
                 Operator::Br {
                     relative_depth,
                 } => {
-                    if let Some(ParsingContext::Nested { stack }) = parsing_context.clone() {
-                        let block_depth = (stack.len() - 1) as u32;
-                        stmts.push(
-                            match stack.get((block_depth - relative_depth) as usize) {
-                                Some(LevelKind::Block) => {
-                                    Statement::Br { block_depth, relative_depth }
-                                }
-                                Some(LevelKind::Loop) => {
-                                    Statement::Continue { block_depth, relative_depth }
-                                },
-                                None => {
-                                    return Err(ParserError::Invalid { statements: stmts.clone(), expressions:exprs.clone(), operator: op })
-                                }
-                            }
-                        )
+                    let arity = branch_target(stack, relative_depth).map_or(usize::MAX, |level| level.arity);
+                    if arity > exprs.len() {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                    let value = branch_value(exprs.split_off(exprs.len() - arity));
+                    if let Some(stmt) = branch(stack, relative_depth, value) {
+                        stmts.push(stmt);
+                        targets.insert(depth as u32 - 1 - relative_depth);
+                        terminator = skip_unreachable(iter);
+                        diverges = true;
+                        break
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1080,25 +2577,34 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                 Operator::BrIf {
                     relative_depth,
                 } => {
+                    let arity = branch_target(stack, relative_depth).map_or(usize::MAX, |level| level.arity);
+                    if arity >= exprs.len() {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
                     if let Some(cond) = exprs.pop() {
-                        if let Some(ParsingContext::Nested { stack }) = &parsing_context {
-                            let block_depth = (stack.len() - 1) as u32;
-                            stmts.push(
-                                match stack.get((block_depth - relative_depth) as usize) {
-                                    Some(LevelKind::Block) => {
-                                        Statement::BrIf { cond, block_depth, relative_depth }
-                                    }
-                                    Some(LevelKind::Loop) => {
-                                        Statement::ContinueIf { cond, block_depth, relative_depth }
-                                    },
-                                    None => {
-                                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions:exprs.clone(), operator: op })
-                                    }
-                                }
-                            )
-                        } else {
-                            return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                        // values stay on the stack when the branch is not taken
+                        if arity > 0 {
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
                         }
+                        let value = branch_value(exprs[exprs.len() - arity..].to_vec());
+                        let block_depth = depth as u32 - 1;
+                        stmts.push(
+                            match branch(stack, relative_depth, value) {
+                                Some(Statement::Br { label, value }) => {
+                                    Statement::BrIf { cond, label, value }
+                                }
+                                Some(Statement::Continue { label, params, value }) => {
+                                    Statement::ContinueIf { cond, label, params, value }
+                                },
+                                Some(ret) => {
+                                    Statement::If { cond, then_stmts: vec![ret], else_stmts: vec![], label: None }
+                                },
+                                None => {
+                                    return Err(ParserError::Invalid { statements: stmts.clone(), expressions:exprs.clone(), operator: op })
+                                }
+                            }
+                        );
+                        targets.insert(block_depth - relative_depth);
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1106,29 +2612,50 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                 Operator::BrTable {
                     ref table,
                 } => {
+                    let default = table.default();
+                    let arity = branch_target(stack, default).map_or(usize::MAX, |level| level.arity);
+                    if arity >= exprs.len() {
+                        return Err(ParserError::Invalid { statements: stmts, expressions: exprs, operator: op })
+                    }
                     if let Some(cond) = exprs.pop() {
-                        if let Some(ParsingContext::Nested { stack }) = parsing_context.clone() {
-                            stmts.push(Statement::BrTable {
-                                cond,
-                                stack,
-                                table: table.targets().collect::<Result<_, _>>().unwrap(),
-                                default: table.default()
-                            })
+                        // the values are shared between all of the targets
+                        if arity > 0 {
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
                         }
+                        let value = branch_value(exprs.split_off(exprs.len() - arity));
+                        let table: Vec<u32> = table.targets().collect::<Result<_, _>>().unwrap();
+                        let block_depth = depth as u32 - 1;
+                        targets.extend(table.iter().chain(std::iter::once(&default)).map(|relative_depth| block_depth - relative_depth));
+                        stmts.push(Statement::BrTable {
+                            cond,
+                            stack: stack.to_vec(),
+                            table,
+                            default,
+                            value,
+                        });
+                        terminator = skip_unreachable(iter);
+                        diverges = true;
+                        break
                     } else {
                         return Err(ParserError::Invalid { statements: stmts, expressions: exprs, operator: op })
                     }
                 },
                 Operator::Return => {
-                    stmts.push(Statement::Return(exprs.pop()))
+                    let arity = stack[0].arity;
+                    let value = branch_value(exprs.split_off(exprs.len().saturating_sub(arity)));
+                    stmts.extend(branch(stack, depth as u32 - 1, value));
+                    targets.insert(0);
+                    terminator = skip_unreachable(iter);
+                    diverges = true;
+                    break
                 },
                 Operator::Call {
                     function_index,
                 } => {
-                    if let Some(func) = functions.get(&function_index) {
-                        let num_params = match func {
-                            FunctionKind::Defined(f) => f.ty.params.len(),
-                            FunctionKind::Imported(f) => f.ty.params.len(),
+                    if let Some(func) = context.get_function_by_index(function_index) {
+                        let (num_params, num_returns) = match func {
+                            FunctionKind::Defined(f) => (f.ty.params.len(), f.ty.returns.len()),
+                            FunctionKind::Imported(f) => (f.ty.params.len(), f.ty.returns.len()),
                         };
                         let mut inputs = Vec::with_capacity(num_params);
                         for _ in 0..num_params {
@@ -1139,29 +2666,114 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                             }
                         }
                         inputs.reverse();
-                        exprs.push(Expression::Call(func.clone(), inputs))
-                    } else {
-                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
-                    }
-                },
-                // Operator::CallIndirect {
-                //     index,
-                //     table_index,
-                //     table_byte,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::ReturnCall {
-                //     function_index,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::ReturnCallIndirect {
-                //     index,
-                //     table_index,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::Delegate {
-                //     relative_depth,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::CatchAll => return Err(ParserError::Unimplemented),
+                        if num_returns == 0 {
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::Call(Expression::Call(func.clone(), inputs)))
+                        } else if num_returns == 1 {
+                            exprs.push(Expression::Call(func.clone(), inputs))
+                        } else {
+                            // bind the results so they can be consumed individually
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            let results = temporaries(depth, &mut spilled, num_returns);
+                            stmts.push(Statement::Let { names: results.clone(), value: Expression::Call(func.clone(), inputs), mutable: false });
+                            exprs.extend(results.into_iter().map(Expression::LocalGet));
+                        }
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::CallIndirect {
+                    index: type_index,
+                    table_index,
+                    ..
+                } => {
+                    let table = context.get_table_by_index(table_index);
+                    let ty = context.types.get(&type_index);
+                    if let (Some(table), Some(ty), Some(index)) = (table, ty, exprs.pop()) {
+                        if exprs.len() < ty.params.len() {
+                            return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                        }
+                        let args = exprs.split_off(exprs.len() - ty.params.len());
+                        let call = Expression::CallIndirect {
+                            table: table.to_string(),
+                            type_index: context.canonical_type_index(ty).unwrap_or(type_index),
+                            ty: ty.clone(),
+                            args,
+                            index: Box::new(index),
+                        };
+                        if ty.returns.is_empty() {
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::Call(call))
+                        } else if ty.returns.len() == 1 {
+                            exprs.push(call)
+                        } else {
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            let results = temporaries(depth, &mut spilled, ty.returns.len());
+                            stmts.push(Statement::Let { names: results.clone(), value: call, mutable: false });
+                            exprs.extend(results.into_iter().map(Expression::LocalGet));
+                        }
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::ReturnCall {
+                    function_index,
+                } => {
+                    let func = match context.get_function_by_index(function_index) {
+                        Some(func) if func.ty().params.len() <= exprs.len() => func,
+                        _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                    };
+                    let args = exprs.split_off(exprs.len() - func.ty().params.len());
+                    let block_depth = depth as u32 - 1;
+                    match &stack[0] {
+                        // calling itself, the function rebinds its parameters and starts over
+                        Level { kind: LevelKind::Function(index), params, label, .. } if *index == function_index && escaping_try(stack, 0).is_none() => {
+                            stmts.push(Statement::Continue { label: label.clone(), params: params.clone(), value: branch_value(args) })
+                        },
+                        _ if func.ty().returns.is_empty() => {
+                            stmts.push(Statement::Call(Expression::Call(func.clone(), args)));
+                            stmts.extend(branch(stack, block_depth, None));
+                        },
+                        _ => stmts.extend(branch(stack, block_depth, Some(Expression::Call(func.clone(), args)))),
+                    }
+                    targets.insert(0);
+                    terminator = skip_unreachable(iter);
+                    diverges = true;
+                    break
+                },
+                Operator::ReturnCallIndirect {
+                    index: type_index,
+                    table_index,
+                } => {
+                    let table = context.get_table_by_index(table_index);
+                    let ty = context.types.get(&type_index);
+                    let (table, ty, index) = match (table, ty, exprs.pop()) {
+                        (Some(table), Some(ty), Some(index)) if ty.params.len() <= exprs.len() => (table, ty, index),
+                        _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                    };
+                    let args = exprs.split_off(exprs.len() - ty.params.len());
+                    let call = Expression::CallIndirect {
+                        table: table.to_string(),
+                        type_index: context.canonical_type_index(ty).unwrap_or(type_index),
+                        ty: ty.clone(),
+                        args,
+                        index: Box::new(index),
+                    };
+                    let block_depth = depth as u32 - 1;
+                    if ty.returns.is_empty() {
+                        stmts.push(Statement::Call(call));
+                        stmts.extend(branch(stack, block_depth, None));
+                    } else {
+                        stmts.extend(branch(stack, block_depth, Some(call)));
+                    }
+                    targets.insert(0);
+                    terminator = skip_unreachable(iter);
+                    diverges = true;
+                    break
+                },
                 Operator::Drop => {
                     if let Some(expr) = exprs.pop() {
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
                         stmts.push(Statement::Drop(expr))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
@@ -1178,19 +2790,30 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                         _ => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
                 },
-                // Operator::TypedSelect {
-                //     ty: Type,
-                // } => return Err(ParserError::Unimplemented),
+                Operator::TypedSelect {
+                    ..
+                } => {
+                    let cond = exprs.pop();
+                    let expr2 = exprs.pop();
+                    let expr1 = exprs.pop();
+                    if let (Some(expr1), Some(expr2), Some(cond)) = (expr1, expr2, cond) {
+                        exprs.push(Expression::Select(Box::new(expr1), Box::new(expr2), Box::new(cond)))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
                 Operator::LocalGet {
                     local_index,
                 } => {
-                    exprs.push(Expression::LocalGet(format!("p{}", local_index)))
+                    exprs.push(Expression::LocalGet(local(context, stack, local_index)))
                 },
                 Operator::LocalSet {
                     local_index,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        stmts.push(Statement::LocalSet(format!("p{}", local_index), expr))
+                        let name = local(context, stack, local_index);
+                        spill_reads(&mut exprs, &mut stmts, depth, &mut spilled, &name);
+                        stmts.push(Statement::LocalSet(name, expr))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1199,26 +2822,37 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     local_index,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::LocalTee(format!("p{}", local_index), Box::new(expr)))
+                        let name = local(context, stack, local_index);
+                        spill_reads(&mut exprs, &mut stmts, depth, &mut spilled, &name);
+                        exprs.push(Expression::LocalTee(name, Box::new(expr)))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::GlobalGet {
+                    global_index,
+                } => {
+                    if let Some(global) = context.get_global_by_index(global_index) {
+                        exprs.push(Expression::GlobalGet(global.to_string()))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::GlobalSet {
+                    global_index,
+                } => {
+                    if let (Some(global), Some(expr)) = (context.get_global_by_index(global_index), exprs.pop()) {
+                        spill_global_reads(&mut exprs, &mut stmts, depth, &mut spilled, &global.to_string());
+                        stmts.push(Statement::GlobalSet(global.to_string(), expr))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
                 },
-                // Operator::GlobalGet {
-                //     global_index,
-                // } => {
-                //     return Err(ParserError::Unimplemented)
-                // },
-                // Operator::GlobalSet {
-                //     global_index,
-                // } => {
-                //     return Err(ParserError::Unimplemented)
-                // },
                 Operator::I32Load {
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I32Load(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I32Load(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1227,7 +2861,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I64Load(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I64Load(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1236,7 +2870,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::F32Load(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::F32Load(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1245,7 +2879,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::F64Load(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::F64Load(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1254,7 +2888,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I32Load8S(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I32Load8S(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1263,7 +2897,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I32Load8U(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I32Load8U(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1272,7 +2906,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I32Load16S(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I32Load16S(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1281,7 +2915,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I32Load16U(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I32Load16U(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1290,7 +2924,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I64Load8S(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I64Load8S(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1299,7 +2933,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I64Load8U(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I64Load8U(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1308,7 +2942,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I64Load16S(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I64Load16S(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1317,7 +2951,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I64Load16U(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I64Load16U(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1326,7 +2960,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I64Load32S(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I64Load32S(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1335,7 +2969,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     memarg,
                 } => {
                     if let Some(expr) = exprs.pop() {
-                        exprs.push(Expression::I64Load32U(Box::new(expr), memarg.align, memarg.offset))
+                        exprs.push(Expression::I64Load32U(Box::new(context.address(memarg.memory, expr)), memarg.align, memarg.offset))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1347,7 +2981,8 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     let ptr_expr = exprs.pop();
                     match (ptr_expr, value_expr) {
                         (Some(pe), Some(ve)) => {
-                            stmts.push(Statement::I32Store(pe, ve, memarg.align, memarg.offset));
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::I32Store(context.address(memarg.memory, pe), ve, memarg.align, memarg.offset));
                         },
                         _ => return Err(ParserError::Invalid { statements: stmts. clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1359,7 +2994,8 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     let ptr_expr = exprs.pop();
                     match (ptr_expr, value_expr) {
                         (Some(pe), Some(ve)) => {
-                            stmts.push(Statement::I64Store(pe, ve, memarg.align, memarg.offset));
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::I64Store(context.address(memarg.memory, pe), ve, memarg.align, memarg.offset));
                         },
                         _ => return Err(ParserError::Invalid { statements: stmts. clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1371,7 +3007,8 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     let ptr_expr = exprs.pop();
                     match (ptr_expr, value_expr) {
                         (Some(pe), Some(ve)) => {
-                            stmts.push(Statement::F32Store(pe, ve, memarg.align, memarg.offset));
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::F32Store(context.address(memarg.memory, pe), ve, memarg.align, memarg.offset));
                         },
                         _ => return Err(ParserError::Invalid { statements: stmts. clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1383,7 +3020,8 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     let ptr_expr = exprs.pop();
                     match (ptr_expr, value_expr) {
                         (Some(pe), Some(ve)) => {
-                            stmts.push(Statement::F64Store(pe, ve, memarg.align, memarg.offset));
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::F64Store(context.address(memarg.memory, pe), ve, memarg.align, memarg.offset));
                         },
                         _ => return Err(ParserError::Invalid { statements: stmts. clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1395,7 +3033,8 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     let ptr_expr = exprs.pop();
                     match (ptr_expr, value_expr) {
                         (Some(pe), Some(ve)) => {
-                            stmts.push(Statement::I32Store8(pe, ve, memarg.align, memarg.offset));
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::I32Store8(context.address(memarg.memory, pe), ve, memarg.align, memarg.offset));
                         },
                         _ => return Err(ParserError::Invalid { statements: stmts. clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1407,7 +3046,8 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     let ptr_expr = exprs.pop();
                     match (ptr_expr, value_expr) {
                         (Some(pe), Some(ve)) => {
-                            stmts.push(Statement::I32Store16(pe, ve, memarg.align, memarg.offset));
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::I32Store16(context.address(memarg.memory, pe), ve, memarg.align, memarg.offset));
                         },
                         _ => return Err(ParserError::Invalid { statements: stmts. clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1419,7 +3059,8 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     let ptr_expr = exprs.pop();
                     match (ptr_expr, value_expr) {
                         (Some(pe), Some(ve)) => {
-                            stmts.push(Statement::I64Store8(pe, ve, memarg.align, memarg.offset));
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::I64Store8(context.address(memarg.memory, pe), ve, memarg.align, memarg.offset));
                         },
                         _ => return Err(ParserError::Invalid { statements: stmts. clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1431,7 +3072,8 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     let ptr_expr = exprs.pop();
                     match (ptr_expr, value_expr) {
                         (Some(pe), Some(ve)) => {
-                            stmts.push(Statement::I64Store16(pe, ve, memarg.align, memarg.offset));
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::I64Store16(context.address(memarg.memory, pe), ve, memarg.align, memarg.offset));
                         },
                         _ => return Err(ParserError::Invalid { statements: stmts. clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1443,21 +3085,27 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                     let ptr_expr = exprs.pop();
                     match (ptr_expr, value_expr) {
                         (Some(pe), Some(ve)) => {
-                            stmts.push(Statement::I64Store32(pe, ve, memarg.align, memarg.offset));
+                            spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                            stmts.push(Statement::I64Store32(context.address(memarg.memory, pe), ve, memarg.align, memarg.offset));
                         },
                         _ => return Err(ParserError::Invalid { statements: stmts. clone(), expressions: exprs.clone(), operator: op })
                     }
                 },
                 Operator::MemorySize {
+                    mem,
                     ..
                 } => {
-                    exprs.push(Expression::MemorySize)
+                    match context.get_memory_by_index(mem) {
+                        Some(memory) => exprs.push(Expression::MemorySize(memory.clone())),
+                        None => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
                 },
                 Operator::MemoryGrow {
+                    mem,
                     ..
                 } => {
-                    if let Some(delta) = exprs.pop() {
-                        exprs.push(Expression::MemoryGrow(Box::new(delta)))
+                    if let (Some(memory), Some(delta)) = (context.get_memory_by_index(mem), exprs.pop()) {
+                        exprs.push(Expression::MemoryGrow(memory.clone(), Box::new(delta)))
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
                     }
@@ -1482,13 +3130,25 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                 } => {
                     exprs.push(Expression::F64Const(value.bits()))
                 },
-                // Operator::RefNull {
-                //     ty,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::RefIsNull => return Err(ParserError::Unimplemented),
-                // Operator::RefFunc {
-                //     function_index,
-                // } => return Err(ParserError::Unimplemented),
+                Operator::RefNull {
+                    ..
+                } => exprs.push(Expression::RefNull),
+                Operator::RefIsNull => {
+                    if let Some(expr) = exprs.pop() {
+                        exprs.push(Expression::RefIsNull(Box::new(expr)))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::RefFunc {
+                    function_index,
+                } => {
+                    if let Some(func) = context.get_function_by_index(function_index) {
+                        exprs.push(context.ref_func(func))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
                 Operator::I32Eqz => {
                     if let Some(expr) = exprs.pop() {
                         exprs.push(
@@ -2245,7 +3905,7 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
                 Operator::I64Clz => {
                     if let Some(expr) = exprs.pop() {
                         exprs.push(
-                            Expression::I64Eqz(Box::new(expr))
+                            Expression::I64Clz(Box::new(expr))
                         )
                     } else {
                         return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
@@ -3109,617 +4769,451 @@ pub fn statements_from_operators<'a>(iter: &mut impl Iterator<Item=Operator<'a>>
             
                 // 0xFC operators
                 // Non-trapping Float-to-int Conversions
-                // Operator::I32TruncSatF32S => return Err(ParserError::Unimplemented),
-                // Operator::I32TruncSatF32U => return Err(ParserError::Unimplemented),
-                // Operator::I32TruncSatF64S => return Err(ParserError::Unimplemented),
-                // Operator::I32TruncSatF64U => return Err(ParserError::Unimplemented),
-                // Operator::I64TruncSatF32S => return Err(ParserError::Unimplemented),
-                // Operator::I64TruncSatF32U => return Err(ParserError::Unimplemented),
-                // Operator::I64TruncSatF64S => return Err(ParserError::Unimplemented),
-                // Operator::I64TruncSatF64U => return Err(ParserError::Unimplemented),
+                Operator::I32TruncSatF32S => {
+                    if let Some(expr) = exprs.pop() {
+                        exprs.push(
+                            Expression::I32TruncSatF32S(Box::new(expr))
+                        )
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I32TruncSatF32U => {
+                    if let Some(expr) = exprs.pop() {
+                        exprs.push(
+                            Expression::I32TruncSatF32U(Box::new(expr))
+                        )
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I32TruncSatF64S => {
+                    if let Some(expr) = exprs.pop() {
+                        exprs.push(
+                            Expression::I32TruncSatF64S(Box::new(expr))
+                        )
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I32TruncSatF64U => {
+                    if let Some(expr) = exprs.pop() {
+                        exprs.push(
+                            Expression::I32TruncSatF64U(Box::new(expr))
+                        )
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I64TruncSatF32S => {
+                    if let Some(expr) = exprs.pop() {
+                        exprs.push(
+                            Expression::I64TruncSatF32S(Box::new(expr))
+                        )
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I64TruncSatF32U => {
+                    if let Some(expr) = exprs.pop() {
+                        exprs.push(
+                            Expression::I64TruncSatF32U(Box::new(expr))
+                        )
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I64TruncSatF64S => {
+                    if let Some(expr) = exprs.pop() {
+                        exprs.push(
+                            Expression::I64TruncSatF64S(Box::new(expr))
+                        )
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I64TruncSatF64U => {
+                    if let Some(expr) = exprs.pop() {
+                        exprs.push(
+                            Expression::I64TruncSatF64U(Box::new(expr))
+                        )
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
             
                 // 0xFC operators
                 // bulk memory https://github.com/WebAssembly/bulk-memory-operations/blob/master/proposals/bulk-memory-operations/Overview.md
-                // Operator::MemoryInit {
-                //     segment,
-                //     mem,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::DataDrop {
-                //     segment,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::MemoryCopy {
-                //     src,
-                //     dst,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::MemoryFill {
-                //     mem,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::TableInit {
-                //     segment,
-                //     table,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::ElemDrop {
-                //     segment,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::TableCopy {
-                //     dst_table,
-                //     src_table,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::TableFill {
-                //     table,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::TableGet {
-                //     table,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::TableSet {
-                //     table,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::TableGrow {
-                //     table,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::TableSize {
-                //     table,
-                // } => return Err(ParserError::Unimplemented),
-            
+                Operator::MemoryInit {
+                    segment,
+                    mem,
+                } => {
+                    let len = exprs.pop();
+                    let src = exprs.pop();
+                    let dst = exprs.pop();
+                    if let (Some(dst), Some(src), Some(len)) = (dst, src, len) {
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                        stmts.push(Statement::MemoryInit { segment: context.get_passive_data_by_index(segment), dst: context.address(mem, dst), src, len })
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::DataDrop {
+                    segment,
+                } => {
+                    stmts.push(Statement::DataDrop(context.get_passive_data_by_index(segment)))
+                },
+                Operator::MemoryCopy {
+                    src: src_memory,
+                    dst: dst_memory,
+                } => {
+                    let len = exprs.pop();
+                    let src = exprs.pop();
+                    let dst = exprs.pop();
+                    if let (Some(dst), Some(src), Some(len)) = (dst, src, len) {
+                        // the length is only an `i64` between two 64-bit memories
+                        let memory64 = [src_memory, dst_memory].iter().all(|index| context.get_memory_by_index(*index).is_some_and(|memory| memory.memory64));
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                        stmts.push(Statement::MemoryCopy { dst: context.address(dst_memory, dst), src: context.address(src_memory, src), len, memory64 })
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::MemoryFill {
+                    mem,
+                } => {
+                    let len = exprs.pop();
+                    let value = exprs.pop();
+                    let dst = exprs.pop();
+                    if let (Some(dst), Some(value), Some(len)) = (dst, value, len) {
+                        let memory64 = context.get_memory_by_index(mem).is_some_and(|memory| memory.memory64);
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                        stmts.push(Statement::MemoryFill { dst: context.address(mem, dst), value, len, memory64 })
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::TableInit {
+                    segment,
+                    table,
+                } => {
+                    let len = exprs.pop();
+                    let src = exprs.pop();
+                    let dst = exprs.pop();
+                    if let (Some(table), Some(dst), Some(src), Some(len)) = (context.get_table_by_index(table), dst, src, len) {
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                        stmts.push(Statement::TableInit {
+                            table: table.to_string(),
+                            ty: table.ty,
+                            segment: context.get_passive_element_by_index(segment),
+                            dst,
+                            src,
+                            len,
+                        })
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::ElemDrop {
+                    segment,
+                } => {
+                    stmts.push(Statement::ElemDrop(context.get_passive_element_by_index(segment)))
+                },
+                Operator::TableCopy {
+                    dst_table,
+                    src_table,
+                } => {
+                    let len = exprs.pop();
+                    let src = exprs.pop();
+                    let dst = exprs.pop();
+                    let tables = (context.get_table_by_index(dst_table), context.get_table_by_index(src_table));
+                    if let ((Some(dst_table), Some(src_table)), Some(dst), Some(src), Some(len)) = (tables, dst, src, len) {
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                        stmts.push(Statement::TableCopy {
+                            dst_table: dst_table.to_string(),
+                            src_table: src_table.to_string(),
+                            dst,
+                            src,
+                            len,
+                        })
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::TableFill {
+                    table,
+                } => {
+                    let len = exprs.pop();
+                    let value = exprs.pop();
+                    let dst = exprs.pop();
+                    if let (Some(table), Some(dst), Some(value), Some(len)) = (context.get_table_by_index(table), dst, value, len) {
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                        stmts.push(Statement::TableFill(table.to_string(), dst, value, len))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::TableGet {
+                    table,
+                } => {
+                    if let (Some(table), Some(index)) = (context.get_table_by_index(table), exprs.pop()) {
+                        exprs.push(Expression::TableGet(table.to_string(), Box::new(index)))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::TableSet {
+                    table,
+                } => {
+                    let value = exprs.pop();
+                    let index = exprs.pop();
+                    if let (Some(table), Some(index), Some(value)) = (context.get_table_by_index(table), index, value) {
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                        stmts.push(Statement::TableSet(table.to_string(), index, value))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::TableGrow {
+                    table,
+                } => {
+                    let delta = exprs.pop();
+                    let value = exprs.pop();
+                    if let (Some(table), Some(value), Some(delta)) = (context.get_table_by_index(table), value, delta) {
+                        exprs.push(Expression::TableGrow(table.to_string(), Box::new(value), Box::new(delta), table.maximum.unwrap_or(u32::MAX)))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::TableSize {
+                    table,
+                } => {
+                    if let Some(table) = context.get_table_by_index(table) {
+                        exprs.push(Expression::TableSize(table.to_string()))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+
                 // 0xFE operators
                 // https://github.com/WebAssembly/threads/blob/master/proposals/threads/Overview.md
-                // Operator::MemoryAtomicNotify {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::MemoryAtomicWait32 {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::MemoryAtomicWait64 {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::AtomicFence {
-                //     flags: u8,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicLoad {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicLoad {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicLoad8U {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicLoad16U {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicLoad8U {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicLoad16U {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicLoad32U {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicStore {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicStore {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicStore8 {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicStore16 {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicStore8 {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicStore16 {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicStore32 {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmwAdd {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmwAdd {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw8AddU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw16AddU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw8AddU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw16AddU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw32AddU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmwSub {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmwSub {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw8SubU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw16SubU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw8SubU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw16SubU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw32SubU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmwAnd {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmwAnd {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw8AndU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw16AndU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw8AndU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw16AndU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw32AndU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmwOr {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmwOr {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw8OrU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw16OrU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw8OrU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw16OrU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw32OrU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmwXor {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmwXor {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw8XorU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw16XorU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw8XorU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw16XorU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw32XorU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmwXchg {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmwXchg {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw8XchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw16XchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw8XchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw16XchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw32XchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmwCmpxchg {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmwCmpxchg {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw8CmpxchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32AtomicRmw16CmpxchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw8CmpxchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw16CmpxchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64AtomicRmw32CmpxchgU {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-            
+                Operator::MemoryAtomicNotify {
+                    memarg,
+                } => {
+                    let count = exprs.pop();
+                    let addr = exprs.pop();
+                    if let (Some(addr), Some(count)) = (addr, count) {
+                        exprs.push(Expression::MemoryAtomicNotify(Box::new(context.address(memarg.memory, addr)), Box::new(count), memarg.offset))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::MemoryAtomicWait32 {
+                    memarg,
+                } => {
+                    let timeout = exprs.pop();
+                    let expected = exprs.pop();
+                    let addr = exprs.pop();
+                    if let (Some(addr), Some(expected), Some(timeout)) = (addr, expected, timeout) {
+                        exprs.push(Expression::MemoryAtomicWait32(Box::new(context.address(memarg.memory, addr)), Box::new(expected), Box::new(timeout), memarg.offset))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::MemoryAtomicWait64 {
+                    memarg,
+                } => {
+                    let timeout = exprs.pop();
+                    let expected = exprs.pop();
+                    let addr = exprs.pop();
+                    if let (Some(addr), Some(expected), Some(timeout)) = (addr, expected, timeout) {
+                        exprs.push(Expression::MemoryAtomicWait64(Box::new(context.address(memarg.memory, addr)), Box::new(expected), Box::new(timeout), memarg.offset))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::AtomicFence {
+                    ..
+                } => {
+                    stmts.push(Statement::AtomicFence)
+                },
+                Operator::I32AtomicLoad { .. }
+                | Operator::I64AtomicLoad { .. }
+                | Operator::I32AtomicLoad8U { .. }
+                | Operator::I32AtomicLoad16U { .. }
+                | Operator::I64AtomicLoad8U { .. }
+                | Operator::I64AtomicLoad16U { .. }
+                | Operator::I64AtomicLoad32U { .. } => {
+                    let (access, memarg) = atomic_access(&op).expect("atomic load");
+                    if let Some(addr) = exprs.pop() {
+                        exprs.push(Expression::AtomicLoad(access, Box::new(context.address(memarg.memory, addr)), memarg.offset))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I32AtomicStore { .. }
+                | Operator::I64AtomicStore { .. }
+                | Operator::I32AtomicStore8 { .. }
+                | Operator::I32AtomicStore16 { .. }
+                | Operator::I64AtomicStore8 { .. }
+                | Operator::I64AtomicStore16 { .. }
+                | Operator::I64AtomicStore32 { .. } => {
+                    let (access, memarg) = atomic_access(&op).expect("atomic store");
+                    let value = exprs.pop();
+                    let addr = exprs.pop();
+                    if let (Some(addr), Some(value)) = (addr, value) {
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                        stmts.push(Statement::AtomicStore(access, context.address(memarg.memory, addr), value, memarg.offset))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I32AtomicRmwAdd { .. }
+                | Operator::I64AtomicRmwAdd { .. }
+                | Operator::I32AtomicRmw8AddU { .. }
+                | Operator::I32AtomicRmw16AddU { .. }
+                | Operator::I64AtomicRmw8AddU { .. }
+                | Operator::I64AtomicRmw16AddU { .. }
+                | Operator::I64AtomicRmw32AddU { .. }
+                | Operator::I32AtomicRmwSub { .. }
+                | Operator::I64AtomicRmwSub { .. }
+                | Operator::I32AtomicRmw8SubU { .. }
+                | Operator::I32AtomicRmw16SubU { .. }
+                | Operator::I64AtomicRmw8SubU { .. }
+                | Operator::I64AtomicRmw16SubU { .. }
+                | Operator::I64AtomicRmw32SubU { .. }
+                | Operator::I32AtomicRmwAnd { .. }
+                | Operator::I64AtomicRmwAnd { .. }
+                | Operator::I32AtomicRmw8AndU { .. }
+                | Operator::I32AtomicRmw16AndU { .. }
+                | Operator::I64AtomicRmw8AndU { .. }
+                | Operator::I64AtomicRmw16AndU { .. }
+                | Operator::I64AtomicRmw32AndU { .. }
+                | Operator::I32AtomicRmwOr { .. }
+                | Operator::I64AtomicRmwOr { .. }
+                | Operator::I32AtomicRmw8OrU { .. }
+                | Operator::I32AtomicRmw16OrU { .. }
+                | Operator::I64AtomicRmw8OrU { .. }
+                | Operator::I64AtomicRmw16OrU { .. }
+                | Operator::I64AtomicRmw32OrU { .. }
+                | Operator::I32AtomicRmwXor { .. }
+                | Operator::I64AtomicRmwXor { .. }
+                | Operator::I32AtomicRmw8XorU { .. }
+                | Operator::I32AtomicRmw16XorU { .. }
+                | Operator::I64AtomicRmw8XorU { .. }
+                | Operator::I64AtomicRmw16XorU { .. }
+                | Operator::I64AtomicRmw32XorU { .. }
+                | Operator::I32AtomicRmwXchg { .. }
+                | Operator::I64AtomicRmwXchg { .. }
+                | Operator::I32AtomicRmw8XchgU { .. }
+                | Operator::I32AtomicRmw16XchgU { .. }
+                | Operator::I64AtomicRmw8XchgU { .. }
+                | Operator::I64AtomicRmw16XchgU { .. }
+                | Operator::I64AtomicRmw32XchgU { .. } => {
+                    let (access, memarg) = atomic_access(&op).expect("atomic read-modify-write");
+                    let method = atomic_rmw_method(&op).expect("atomic read-modify-write");
+                    let value = exprs.pop();
+                    let addr = exprs.pop();
+                    if let (Some(addr), Some(value)) = (addr, value) {
+                        exprs.push(Expression::AtomicRmw(access, method, Box::new(context.address(memarg.memory, addr)), Box::new(value), memarg.offset))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::I32AtomicRmwCmpxchg { .. }
+                | Operator::I64AtomicRmwCmpxchg { .. }
+                | Operator::I32AtomicRmw8CmpxchgU { .. }
+                | Operator::I32AtomicRmw16CmpxchgU { .. }
+                | Operator::I64AtomicRmw8CmpxchgU { .. }
+                | Operator::I64AtomicRmw16CmpxchgU { .. }
+                | Operator::I64AtomicRmw32CmpxchgU { .. } => {
+                    let (access, memarg) = atomic_access(&op).expect("atomic compare exchange");
+                    let replacement = exprs.pop();
+                    let expected = exprs.pop();
+                    let addr = exprs.pop();
+                    if let (Some(addr), Some(expected), Some(replacement)) = (addr, expected, replacement) {
+                        exprs.push(Expression::AtomicCmpxchg(access, Box::new(context.address(memarg.memory, addr)), Box::new(expected), Box::new(replacement), memarg.offset))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+
                 // 0xFD operators
                 // SIMD https://webassembly.github.io/simd/core/binary/instructions.html
-                // Operator::V128Load {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load8x8S {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load8x8U {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load16x4S {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load16x4U {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load32x2S {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load32x2U {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load8Splat {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load16Splat {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load32Splat {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load64Splat {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load32Zero {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load64Zero {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Store {
-                //     memarg,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load8Lane {
-                //     memarg,
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load16Lane {
-                //     memarg,
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load32Lane {
-                //     memarg,
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Load64Lane {
-                //     memarg,
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Store8Lane {
-                //     memarg,
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Store16Lane {
-                //     memarg,
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Store32Lane {
-                //     memarg,
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Store64Lane {
-                //     memarg,
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::V128Const {
-                //     value: V128,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Shuffle {
-                //     lanes,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I8x16ExtractLaneS {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I8x16ExtractLaneU {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I8x16ReplaceLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtractLaneS {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtractLaneU {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ReplaceLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtractLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ReplaceLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ExtractLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ReplaceLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::F32x4ExtractLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::F32x4ReplaceLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::F64x2ExtractLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::F64x2ReplaceLane {
-                //     lane,
-                // } => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Swizzle => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Splat => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Splat => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Splat => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Splat => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Splat => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Splat => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Eq => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Ne => return Err(ParserError::Unimplemented),
-                // Operator::I8x16LtS => return Err(ParserError::Unimplemented),
-                // Operator::I8x16LtU => return Err(ParserError::Unimplemented),
-                // Operator::I8x16GtS => return Err(ParserError::Unimplemented),
-                // Operator::I8x16GtU => return Err(ParserError::Unimplemented),
-                // Operator::I8x16LeS => return Err(ParserError::Unimplemented),
-                // Operator::I8x16LeU => return Err(ParserError::Unimplemented),
-                // Operator::I8x16GeS => return Err(ParserError::Unimplemented),
-                // Operator::I8x16GeU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Eq => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Ne => return Err(ParserError::Unimplemented),
-                // Operator::I16x8LtS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8LtU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8GtS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8GtU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8LeS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8LeU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8GeS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8GeU => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Eq => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Ne => return Err(ParserError::Unimplemented),
-                // Operator::I32x4LtS => return Err(ParserError::Unimplemented),
-                // Operator::I32x4LtU => return Err(ParserError::Unimplemented),
-                // Operator::I32x4GtS => return Err(ParserError::Unimplemented),
-                // Operator::I32x4GtU => return Err(ParserError::Unimplemented),
-                // Operator::I32x4LeS => return Err(ParserError::Unimplemented),
-                // Operator::I32x4LeU => return Err(ParserError::Unimplemented),
-                // Operator::I32x4GeS => return Err(ParserError::Unimplemented),
-                // Operator::I32x4GeU => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Eq => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Ne => return Err(ParserError::Unimplemented),
-                // Operator::I64x2LtS => return Err(ParserError::Unimplemented),
-                // Operator::I64x2GtS => return Err(ParserError::Unimplemented),
-                // Operator::I64x2LeS => return Err(ParserError::Unimplemented),
-                // Operator::I64x2GeS => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Eq => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Ne => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Lt => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Gt => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Le => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Ge => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Eq => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Ne => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Lt => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Gt => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Le => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Ge => return Err(ParserError::Unimplemented),
-                // Operator::V128Not => return Err(ParserError::Unimplemented),
-                // Operator::V128And => return Err(ParserError::Unimplemented),
-                // Operator::V128AndNot => return Err(ParserError::Unimplemented),
-                // Operator::V128Or => return Err(ParserError::Unimplemented),
-                // Operator::V128Xor => return Err(ParserError::Unimplemented),
-                // Operator::V128Bitselect => return Err(ParserError::Unimplemented),
-                // Operator::V128AnyTrue => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Abs => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Neg => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Popcnt => return Err(ParserError::Unimplemented),
-                // Operator::I8x16AllTrue => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Bitmask => return Err(ParserError::Unimplemented),
-                // Operator::I8x16NarrowI16x8S => return Err(ParserError::Unimplemented),
-                // Operator::I8x16NarrowI16x8U => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Shl => return Err(ParserError::Unimplemented),
-                // Operator::I8x16ShrS => return Err(ParserError::Unimplemented),
-                // Operator::I8x16ShrU => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Add => return Err(ParserError::Unimplemented),
-                // Operator::I8x16AddSatS => return Err(ParserError::Unimplemented),
-                // Operator::I8x16AddSatU => return Err(ParserError::Unimplemented),
-                // Operator::I8x16Sub => return Err(ParserError::Unimplemented),
-                // Operator::I8x16SubSatS => return Err(ParserError::Unimplemented),
-                // Operator::I8x16SubSatU => return Err(ParserError::Unimplemented),
-                // Operator::I8x16MinS => return Err(ParserError::Unimplemented),
-                // Operator::I8x16MinU => return Err(ParserError::Unimplemented),
-                // Operator::I8x16MaxS => return Err(ParserError::Unimplemented),
-                // Operator::I8x16MaxU => return Err(ParserError::Unimplemented),
-                // Operator::I8x16RoundingAverageU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtAddPairwiseI8x16S => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtAddPairwiseI8x16U => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Abs => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Neg => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Q15MulrSatS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8AllTrue => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Bitmask => return Err(ParserError::Unimplemented),
-                // Operator::I16x8NarrowI32x4S => return Err(ParserError::Unimplemented),
-                // Operator::I16x8NarrowI32x4U => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtendLowI8x16S => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtendHighI8x16S => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtendLowI8x16U => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtendHighI8x16U => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Shl => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ShrS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ShrU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Add => return Err(ParserError::Unimplemented),
-                // Operator::I16x8AddSatS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8AddSatU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Sub => return Err(ParserError::Unimplemented),
-                // Operator::I16x8SubSatS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8SubSatU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8Mul => return Err(ParserError::Unimplemented),
-                // Operator::I16x8MinS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8MinU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8MaxS => return Err(ParserError::Unimplemented),
-                // Operator::I16x8MaxU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8RoundingAverageU => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtMulLowI8x16S => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtMulHighI8x16S => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtMulLowI8x16U => return Err(ParserError::Unimplemented),
-                // Operator::I16x8ExtMulHighI8x16U => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtAddPairwiseI16x8S => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtAddPairwiseI16x8U => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Abs => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Neg => return Err(ParserError::Unimplemented),
-                // Operator::I32x4AllTrue => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Bitmask => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtendLowI16x8S => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtendHighI16x8S => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtendLowI16x8U => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtendHighI16x8U => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Shl => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ShrS => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ShrU => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Add => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Sub => return Err(ParserError::Unimplemented),
-                // Operator::I32x4Mul => return Err(ParserError::Unimplemented),
-                // Operator::I32x4MinS => return Err(ParserError::Unimplemented),
-                // Operator::I32x4MinU => return Err(ParserError::Unimplemented),
-                // Operator::I32x4MaxS => return Err(ParserError::Unimplemented),
-                // Operator::I32x4MaxU => return Err(ParserError::Unimplemented),
-                // Operator::I32x4DotI16x8S => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtMulLowI16x8S => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtMulHighI16x8S => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtMulLowI16x8U => return Err(ParserError::Unimplemented),
-                // Operator::I32x4ExtMulHighI16x8U => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Abs => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Neg => return Err(ParserError::Unimplemented),
-                // Operator::I64x2AllTrue => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Bitmask => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ExtendLowI32x4S => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ExtendHighI32x4S => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ExtendLowI32x4U => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ExtendHighI32x4U => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Shl => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ShrS => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ShrU => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Add => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Sub => return Err(ParserError::Unimplemented),
-                // Operator::I64x2Mul => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ExtMulLowI32x4S => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ExtMulHighI32x4S => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ExtMulLowI32x4U => return Err(ParserError::Unimplemented),
-                // Operator::I64x2ExtMulHighI32x4U => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Ceil => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Floor => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Trunc => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Nearest => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Abs => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Neg => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Sqrt => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Add => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Sub => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Mul => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Div => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Min => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Max => return Err(ParserError::Unimplemented),
-                // Operator::F32x4PMin => return Err(ParserError::Unimplemented),
-                // Operator::F32x4PMax => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Ceil => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Floor => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Trunc => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Nearest => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Abs => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Neg => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Sqrt => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Add => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Sub => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Mul => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Div => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Min => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Max => return Err(ParserError::Unimplemented),
-                // Operator::F64x2PMin => return Err(ParserError::Unimplemented),
-                // Operator::F64x2PMax => return Err(ParserError::Unimplemented),
-                // Operator::I32x4TruncSatF32x4S => return Err(ParserError::Unimplemented),
-                // Operator::I32x4TruncSatF32x4U => return Err(ParserError::Unimplemented),
-                // Operator::F32x4ConvertI32x4S => return Err(ParserError::Unimplemented),
-                // Operator::F32x4ConvertI32x4U => return Err(ParserError::Unimplemented),
-                // Operator::I32x4TruncSatF64x2SZero => return Err(ParserError::Unimplemented),
-                // Operator::I32x4TruncSatF64x2UZero => return Err(ParserError::Unimplemented),
-                // Operator::F64x2ConvertLowI32x4S => return Err(ParserError::Unimplemented),
-                // Operator::F64x2ConvertLowI32x4U => return Err(ParserError::Unimplemented),
-                // Operator::F32x4DemoteF64x2Zero => return Err(ParserError::Unimplemented),
-                // Operator::F64x2PromoteLowF32x4 => return Err(ParserError::Unimplemented),
-                // Operator::I8x16RelaxedSwizzle => return Err(ParserError::Unimplemented),
-                // Operator::I32x4RelaxedTruncSatF32x4S => return Err(ParserError::Unimplemented),
-                // Operator::I32x4RelaxedTruncSatF32x4U => return Err(ParserError::Unimplemented),
-                // Operator::I32x4RelaxedTruncSatF64x2SZero => return Err(ParserError::Unimplemented),
-                // Operator::I32x4RelaxedTruncSatF64x2UZero => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Fma => return Err(ParserError::Unimplemented),
-                // Operator::F32x4Fms => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Fma => return Err(ParserError::Unimplemented),
-                // Operator::F64x2Fms => return Err(ParserError::Unimplemented),
-                // Operator::I8x16LaneSelect => return Err(ParserError::Unimplemented),
-                // Operator::I16x8LaneSelect => return Err(ParserError::Unimplemented),
-                // Operator::I32x4LaneSelect => return Err(ParserError::Unimplemented),
-                // Operator::I64x2LaneSelect => return Err(ParserError::Unimplemented),
-                // Operator::F32x4RelaxedMin => return Err(ParserError::Unimplemented),
-                // Operator::F32x4RelaxedMax => return Err(ParserError::Unimplemented),
-                // Operator::F64x2RelaxedMin => return Err(ParserError::Unimplemented),
-                // Operator::F64x2RelaxedMax => return Err(ParserError::Unimplemented),
-                o => return Err(ParserError::<'a>::Unimplemented(o))
+                Operator::V128Const {
+                    value,
+                } => exprs.push(Expression::V128Const(u128::from_le_bytes(*value.bytes()))),
+                Operator::V128Load { .. }
+                | Operator::V128Load8x8S { .. }
+                | Operator::V128Load8x8U { .. }
+                | Operator::V128Load16x4S { .. }
+                | Operator::V128Load16x4U { .. }
+                | Operator::V128Load32x2S { .. }
+                | Operator::V128Load32x2U { .. }
+                | Operator::V128Load8Splat { .. }
+                | Operator::V128Load16Splat { .. }
+                | Operator::V128Load32Splat { .. }
+                | Operator::V128Load64Splat { .. }
+                | Operator::V128Load32Zero { .. }
+                | Operator::V128Load64Zero { .. }
+                | Operator::V128Load8Lane { .. }
+                | Operator::V128Load16Lane { .. }
+                | Operator::V128Load32Lane { .. }
+                | Operator::V128Load64Lane { .. } => {
+                    let (access, memarg) = simd_access(&op).expect("SIMD load");
+                    let vector = match access.lane {
+                        Some(_) => match exprs.pop() {
+                            Some(vector) => Some(Box::new(vector)),
+                            None => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op }),
+                        },
+                        None => None,
+                    };
+                    if let Some(addr) = exprs.pop() {
+                        exprs.push(Expression::SimdLoad(access, Box::new(context.address(memarg.memory, addr)), vector, memarg.offset))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                Operator::V128Store { .. }
+                | Operator::V128Store8Lane { .. }
+                | Operator::V128Store16Lane { .. }
+                | Operator::V128Store32Lane { .. }
+                | Operator::V128Store64Lane { .. } => {
+                    let (access, memarg) = simd_access(&op).expect("SIMD store");
+                    let value = exprs.pop();
+                    let addr = exprs.pop();
+                    if let (Some(addr), Some(value)) = (addr, value) {
+                        spill(&mut exprs, &mut stmts, depth, &mut spilled);
+                        stmts.push(Statement::SimdStore(access, context.address(memarg.memory, addr), value, memarg.offset))
+                    } else {
+                        return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: op })
+                    }
+                },
+                o => match simd_intrinsic(&o) {
+                    Some((intrinsic, lanes)) if exprs.len() >= intrinsic.arity => {
+                        let args = exprs.split_off(exprs.len() - intrinsic.arity);
+                        exprs.push(Expression::Simd(intrinsic, lanes, args))
+                    },
+                    Some(_) => return Err(ParserError::Invalid { statements: stmts.clone(), expressions: exprs.clone(), operator: o }),
+                    None => return Err(ParserError::<'a>::Unimplemented(o)),
+                },
             }
         } else {
             break
         }
     }
 
-    if exprs.len() == 1 {
-        if let Some(expr) = exprs.pop() {
-            stmts.push(Statement::Unassigned(expr))
-        }
-    }
-
-    Ok(stmts)
+    Ok(Sequence {
+        statements: stmts,
+        values: exprs,
+        targets,
+        delegates,
+        terminator,
+        diverges,
+    })
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum ParserError<'a> {
     Invalid {
@@ -3729,3 +5223,12 @@ pub enum ParserError<'a> {
     },
     Unimplemented(Operator<'a>)
 }
+
+impl Display for ParserError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Invalid { operator, .. } => write!(f, "{operator:?} is missing its operands"),
+            Self::Unimplemented(operator) => write!(f, "{operator:?} is not supported"),
+        }
+    }
+}
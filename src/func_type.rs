@@ -1,8 +1,9 @@
+use itertools::Itertools;
+use crate::options::EmitOptions;
 use crate::wasm_type::WASMType;
-use wasmparser;
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FuncType {
     pub params: Vec<WASMType>,
     pub returns: Vec<WASMType>,
@@ -11,8 +12,39 @@ pub struct FuncType {
 impl From<wasmparser::FuncType> for FuncType {
     fn from(obj: wasmparser::FuncType) -> Self {
         Self {
-            params: obj.params.into_iter().map(|p| p.into()).collect(),
-            returns: obj.returns.into_iter().map(|p| p.into()).collect(),
+            params: obj.params.iter().map(|p| p.into()).collect(),
+            returns: obj.returns.iter().map(|p| p.into()).collect(),
+        }
+    }
+}
+
+impl FuncType {
+    /// Rust type of a function pointer with this signature; a method of the
+    /// instance takes it as its first parameter.
+    pub fn emit_pointer_type(&self, options: &EmitOptions) -> String {
+        let receiver = options.instance.then(|| "&mut Self".to_string());
+        format!("unsafe fn({}){}", receiver.into_iter().chain(self.params.iter().map(|param| param.to_string())).join(", "), self.emit_return_sig())
+    }
+
+    /// Return part of the signature of a function emitted with `options`:
+    /// safe code returns its results or a trap.
+    pub fn emit_result_sig(&self, options: &EmitOptions) -> String {
+        if !options.safe {
+            return self.emit_return_sig()
+        }
+        let results = match self.returns.len() {
+            1 => self.returns[0].to_string(),
+            _ => format!("({})", self.returns.iter().join(", ")),
+        };
+        format!(" -> Result<{results}, __W2RTrap>")
+    }
+
+    /// Return part of a signature; multiple results are returned as a tuple.
+    pub fn emit_return_sig(&self) -> String {
+        match self.returns.len() {
+            0 => "".to_string(),
+            1 => format!(" -> {}", self.returns[0]),
+            _ => format!(" -> ({})", self.returns.iter().join(", ")),
         }
     }
 }
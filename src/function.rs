@@ -1,7 +1,9 @@
 use itertools::Itertools;
-use crate::statement::Statement;
+use std::collections::BTreeMap;
+use crate::statement::{INDENTATION, Statement};
 use crate::wasm_type::WASMType;
 use crate::func_type::FuncType;
+use crate::options::EmitOptions;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
 
@@ -14,12 +16,21 @@ pub struct Function {
     pub exported: bool,
     pub export_name: Option<String>,
     pub debug_name: Option<String>,
+    /// Identifier derived from the export name or else the `name` section.
+    pub identifier: Option<String>,
+    /// Identifier of the method an instance exports this function as.
+    pub method: Option<String>,
+    /// Identifiers of the named parameters and locals.
+    pub local_names: BTreeMap<u32, String>,
+    /// Whether the function tail calls itself. Its body then runs in a loop
+    /// that such calls continue with new parameters.
+    pub tail_recursive: bool,
 }
 
 impl Display for Function {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        if self.exported { 
-            write!(f, "{}", self.export_name.as_ref().expect("Export name missing for exported function."))
+        if let Some(identifier) = &self.identifier {
+            write!(f, "{identifier}")
         } else { 
             write!(f, "__w2r_f{}", self.index)
         }
@@ -27,14 +38,24 @@ impl Display for Function {
 }
 
 impl Function {
-    pub fn emit_code(&self, indentation: usize) -> Vec<String> {
+    /// Name of this function as a method of an instance.
+    pub fn method(&self) -> String {
+        self.method.clone().unwrap_or_else(|| self.to_string())
+    }
+
+    /// Variable holding parameter or local `index`.
+    pub fn local(&self, index: u32) -> String {
+        self.local_names.get(&index).cloned().unwrap_or_else(|| format!("p{index}"))
+    }
+
+    pub fn emit_code(&self, indentation: usize, options: &EmitOptions) -> Vec<String> {
         let mut lines = vec![];
 
         lines.extend(self.emit_help_text());
 
-        lines.extend(self.emit_signature());
+        lines.extend(self.emit_signature(options));
 
-        lines.extend(self.emit_body(indentation));
+        lines.extend(self.emit_body(indentation, options));
 
         lines.push("}".to_string());
 
@@ -42,54 +63,98 @@ impl Function {
     }
 
     fn emit_help_text(&self) -> Vec<String> {
+        let mut lines = vec!["".to_string()];
         if let Some(ref dnm) = self.debug_name {
-            vec![format!("/// Debug name: {}", dnm)]
-        } else {
-            vec!["".to_string()]
+            // a name may hold any character, but one line of the comment
+            let dnm = dnm.replace(char::is_control, " ");
+            match crate::names::demangle(&dnm) {
+                Some(path) => {
+                    lines.push(format!("/// Debug name: `{path}`"));
+                    lines.push("///".to_string());
+                    lines.push(format!("/// Symbol: `{dnm}`"));
+                },
+                None => lines.push(format!("/// Debug name: {}", dnm)),
+            }
         }
+        lines
     }
 
-    fn emit_signature(&self) -> Vec<String> {
+    fn emit_signature(&self, options: &EmitOptions) -> Vec<String> {
         let mut lines = Vec::new();
 
-        let return_sig = match self.ty.returns.len() {
-            0 => "".to_string(),
-            1 => {
-                format!(" -> {}", &self.ty.returns[0])
-            },
-            _ => unimplemented!()
-        };
+        // the exports of an instance are its public methods
+        if options.instance {
+            let params = std::iter::once("&mut self".to_string()).chain(self.emit_param_types());
+            lines.push(format!(
+                "{}{}fn {}({}){} {{",
+                if self.exported { "pub " } else { options.visibility() },
+                if options.safe { "" } else { "unsafe " },
+                self.method(),
+                params.format(", "),
+                self.ty.emit_result_sig(options)
+            ));
+            return lines
+        }
 
-        if self.exported {
-            lines.push("#[no_mangle]".to_string());
+        if let Some(name) = self.export_name.as_ref().filter(|_| self.exported) {
+            lines.push(crate::names::export_attribute(&self.to_string(), name));
         }
 
         lines.push(format!(
-            "unsafe fn {}({}){} {{",
+            "{}unsafe fn {}({}){} {{",
+            options.visibility(),
             self,
-            self.emit_param_types(),
-            return_sig
+            self.emit_param_types().format(", "),
+            self.ty.emit_return_sig()
         ));
 
         lines
     }
 
-    fn emit_body(&self, indentation: usize) -> Vec<String> {
+    fn emit_body(&self, indentation: usize, options: &EmitOptions) -> Vec<String> {
         let mut lines = vec![];
+
+        if self.tail_recursive {
+            // locals are declared in the loop so each call starts from zero
+            lines.push(format!("{:indentation$}'B0: loop {{", " "));
+            let inner = indentation + INDENTATION;
+            if !self.locals.is_empty() {
+                lines.push(self.emit_locals(inner));
+            }
+            for stmt in self.statements.iter() {
+                match stmt {
+                    Statement::Unassigned(expr) => lines.extend(Statement::Return(Some(expr.clone())).emit_code(inner, options)),
+                    stmt => lines.extend(stmt.emit_code(inner, options)),
+                }
+            }
+            if self.ty.returns.is_empty() {
+                lines.push(format!("{:inner$}break;", " "));
+            }
+            lines.push(format!("{:indentation$}}}", " "));
+            if options.safe && self.ty.returns.is_empty() {
+                lines.push(format!("{:indentation$}Ok(())", " "));
+            }
+            return lines
+        }
         
-        if self.locals.len() > 0 {
+        if !self.locals.is_empty() {
             lines.push(self.emit_locals(indentation));
         }
 
-        lines.extend(self.emit_statements(indentation));
+        lines.extend(self.emit_statements(indentation, options));
+
+        // the results of safe code are wrapped by `Statement::Unassigned`
+        if options.safe && self.ty.returns.is_empty() {
+            lines.push(format!("{:indentation$}Ok(())", " "));
+        }
 
         lines
     }
 
-    fn emit_param_types(&self) -> String {
+    fn emit_param_types(&self) -> impl Iterator<Item = String> + '_ {
         self.ty.params.iter().enumerate().map(|(i, param)| {
-            format!("mut p{}: {}", i, param)
-        }).join(", ")
+            format!("mut {}: {}", self.local(i as u32), param)
+        })
     }
 
     fn emit_locals(&self, indentation: usize) -> String {
@@ -97,12 +162,11 @@ impl Function {
         let param_len = self.ty.params.len();
 
         code.push_str(
-            &self.locals.iter().map(|(count, _)| {
-                (0..*count).into_iter()
+            &self.locals.iter().flat_map(|(count, _)| {
+                0..*count
             })
-            .flatten()
             .enumerate()
-            .map(|(i, _)| format!("mut p{}", i + param_len))
+            .map(|(i, _)| format!("mut {}", self.local((i + param_len) as u32)))
             .join(", ")
         );
 
@@ -116,14 +180,23 @@ impl Function {
             }).join(", ")
         );
 
+        code.push_str(") = (");
+
+        // WASM locals are zero-initialized
+        code.push_str(
+            &self.locals.iter().map(|(count, ty)| {
+                (0..*count).map(|_| ty.zero()).join(", ")
+            }).join(", ")
+        );
+
         code.push_str(");");
 
         code
     }
 
-    fn emit_statements(&self, indentation: usize) -> Vec<String> {
-        self.statements.iter().map(|stmt| {
-            stmt.emit_code(indentation)
-        }).flatten().collect()
+    fn emit_statements(&self, indentation: usize, options: &EmitOptions) -> Vec<String> {
+        self.statements.iter().flat_map(|stmt| {
+            stmt.emit_code(indentation, options)
+        }).collect()
     }
 }
\ No newline at end of file
@@ -0,0 +1,138 @@
+use crate::expression::Expression;
+use crate::options::EmitOptions;
+use crate::wasm_type::WASMType;
+use crate::statement::INDENTATION;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub index: u32,
+    pub ty: WASMType,
+    pub mutable: bool,
+    pub init: Expression,
+    pub exported: bool,
+    pub export_name: Option<String>,
+    /// Identifier derived from the export name or else the `name` section.
+    pub identifier: Option<String>,
+}
+
+impl Display for Global {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if let Some(identifier) = &self.identifier {
+            write!(f, "{identifier}")
+        } else {
+            write!(f, "__w2r_g{}", self.index)
+        }
+    }
+}
+
+impl Global {
+    pub fn emit_code(&self, options: &EmitOptions) -> Vec<String> {
+        let mut lines = vec!["".to_string()];
+
+        if let Some(name) = self.export_name.as_ref().filter(|_| self.exported) {
+            lines.push(crate::names::export_attribute(&self.to_string(), name));
+        }
+
+        let init = self.emit_init(options);
+
+        // only constant, unexported globals can become `const` items
+        let keyword = if self.mutable || self.initialized_in_setup() {
+            "static mut"
+        } else if self.exported {
+            "static"
+        } else {
+            "const"
+        };
+
+        lines.push(format!("{keyword} {self}: {} = {init};", self.ty));
+
+        lines
+    }
+
+    /// Field of an instance holding this global.
+    pub fn emit_field(&self) -> String {
+        format!("{}{self}: {}", if self.exported { "pub " } else { "" }, self.ty)
+    }
+
+    /// Value the global starts out with.
+    pub fn emit_init(&self, options: &EmitOptions) -> String {
+        if self.initialized_in_setup() {
+            self.ty.zero().to_string()
+        } else {
+            self.init.emit_code(options)
+        }
+    }
+
+    /// Globals initialized from another (imported) global cannot be evaluated
+    /// at compile time; their value is assigned by the `setup` function.
+    pub fn initialized_in_setup(&self) -> bool {
+        matches!(self.init, Expression::GlobalGet(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedGlobal {
+    pub index: u32,
+    pub ty: WASMType,
+    pub mutable: bool,
+    pub module: String,
+    pub name: String,
+    /// Identifier derived from the `name` section.
+    pub identifier: Option<String>,
+}
+
+impl ImportedGlobal {
+    pub fn emit_code(&self, options: &EmitOptions) -> Vec<String> {
+        vec![
+            format!("{:>INDENTATION$}#[link_name=\"{}\"]", " ", self.name),
+            format!(
+                "{:>INDENTATION$}{}static{} {}: {};",
+                " ",
+                options.visibility(),
+                if self.mutable { " mut" } else { "" },
+                self,
+                self.ty
+            ),
+        ]
+    }
+}
+
+impl ImportedGlobal {
+    /// Declaration of this import in the trait of its module; an instance
+    /// reads the value once, when it is created.
+    pub fn emit_host_method(&self, method: &str) -> Vec<String> {
+        let mut lines = vec![];
+        if self.mutable {
+            lines.push(format!("{:>INDENTATION$}/// Initial value of the mutable global {:?}; the instance keeps its own copy.", " ", self.name));
+        }
+        lines.push(format!("{:>INDENTATION$}fn {method}(&self) -> {};", " ", self.ty));
+        lines
+    }
+}
+
+impl Display for ImportedGlobal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match &self.identifier {
+            Some(identifier) => write!(f, "{identifier}"),
+            None => write!(f, "__w2r_g{}", self.index),
+        }
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum GlobalKind {
+    Imported(ImportedGlobal),
+    Defined(Global),
+}
+
+impl Display for GlobalKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Defined(global) => write!(f, "{}", global),
+            Self::Imported(global) => write!(f, "{}", global),
+        }
+    }
+}
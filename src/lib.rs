@@ -1,7 +1,13 @@
 mod context;
 mod expression;
 mod function;
+mod global;
+mod memory;
+mod names;
+pub mod options;
 pub mod parser;
+mod runtime;
 mod statement;
+mod table;
 mod wasm_type;
 mod func_type;
@@ -0,0 +1,220 @@
+use crate::options::EmitOptions;
+use crate::wasm_type::WASMType;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+
+const PAGE_SIZE: u64 = 1 << 16;
+
+/// A linear memory. Memory 0 is the native memory of the recompiled module,
+/// unless it is a memory64; every other memory is emulated with a
+/// bounds-checked `__W2RMemory` sized by `setup`. The sandboxed backend backs every memory with one.
+#[derive(Debug, Clone)]
+pub struct Memory {
+    pub index: u32,
+    pub initial: u64,
+    pub maximum: Option<u64>,
+    pub shared: bool,
+    /// Whether addresses and sizes are `i64` rather than `i32`.
+    pub memory64: bool,
+}
+
+impl Display for Memory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "__w2r_memory{}", self.index)
+    }
+}
+
+impl Memory {
+    pub fn new(index: u32, ty: wasmparser::MemoryType) -> Self {
+        Self {
+            index,
+            initial: ty.initial,
+            maximum: ty.maximum,
+            shared: ty.shared,
+            memory64: ty.memory64,
+        }
+    }
+
+    /// Whether this is the native memory of wasm32, whose addresses are
+    /// pointers; the 64-bit addresses of memory64 do not fit them.
+    pub fn is_native(&self) -> bool {
+        self.index == 0 && !self.memory64
+    }
+
+    /// Whether this memory is a `__W2RMemory`, accessed through its
+    /// bounds-checked accessors.
+    pub fn is_sandboxed(&self, options: &EmitOptions) -> bool {
+        options.sandboxed || !self.is_native()
+    }
+
+    /// Type of the addresses, sizes and page counts of this memory.
+    pub fn address_type(&self) -> WASMType {
+        if self.memory64 { WASMType::I64 } else { WASMType::I32 }
+    }
+
+    /// Cast of an address or length of this memory to `usize`, without
+    /// sign-extending 32-bit values.
+    pub fn usize_cast(&self) -> &'static str {
+        if self.memory64 { "as u64 as usize" } else { "as u32 as usize" }
+    }
+
+    /// Cast of an address or length of this memory to the `u64` the
+    /// accessors of `__W2RMemory` take.
+    fn u64_cast(&self) -> &'static str {
+        if self.memory64 { "as u64" } else { "as u32 as u64" }
+    }
+
+    /// Maximum size in pages; the whole address space if unbounded.
+    fn maximum_pages(&self) -> u64 {
+        self.maximum.unwrap_or(if self.memory64 { u64::MAX / PAGE_SIZE } else { 1 << 16 })
+    }
+
+    /// Pointer to the byte at `addr`. Addresses into the native memory
+    /// already are pointers.
+    pub fn emit_pointer(&self, addr: &str, options: &EmitOptions) -> String {
+        if self.is_sandboxed(options) {
+            self.emit_access(addr, 0, "u8", options)
+        } else {
+            addr.to_string()
+        }
+    }
+
+    /// Bounds-checked pointer to the `ty` at `addr + offset` in a sandboxed
+    /// memory.
+    pub fn emit_access(&self, addr: &str, offset: u64, ty: &str, options: &EmitOptions) -> String {
+        format!("__W2RMemory::pointer::<{ty}>(::std::ptr::addr_of_mut!({}), {addr} {}, {offset})", options.state(self), self.u64_cast())
+    }
+
+    /// Bounds-checked pointer to the `ty` at `addr + offset` in a sandboxed
+    /// memory, for an atomic access: it traps unless the address is a
+    /// multiple of the size of `ty`.
+    pub fn emit_atomic_access(&self, addr: &str, offset: u64, ty: &str, options: &EmitOptions) -> String {
+        format!("__W2RMemory::atomic::<{ty}>(::std::ptr::addr_of_mut!({}), {addr} {}, {offset})", options.state(self), self.u64_cast())
+    }
+
+    /// Bytes of the `size`-byte atomic access at `addr + offset` in safe
+    /// code, as a `&mut [u8; size]`: the memory is exclusively borrowed, so
+    /// atomics are ordinary loads and stores. It traps unless the address is
+    /// a multiple of `size`.
+    pub fn emit_atomic_bytes(&self, addr: &str, offset: u64, size: u32) -> String {
+        format!("self.{self}.atomic::<{size}>({addr} {}, {offset})?", self.u64_cast())
+    }
+
+    /// Sandboxed load of the `ty` at `addr + offset`.
+    pub fn emit_load(&self, addr: &str, offset: u64, ty: &str, options: &EmitOptions) -> String {
+        if options.safe {
+            return bind(&[("a", addr)], |operands| {
+                format!("{ty}::from_le_bytes(self.{self}.load({} {}, {offset})?)", operands[0], self.u64_cast())
+            })
+        }
+        format!("{ty}::from_le_bytes(__W2RMemory::load(::std::ptr::addr_of!({}), {addr} {}, {offset}))", options.state(self), self.u64_cast())
+    }
+
+    /// Sandboxed store of `value`, a `ty`, at `addr + offset`.
+    pub fn emit_store(&self, addr: &str, offset: u64, value: &str, ty: &str, options: &EmitOptions) -> String {
+        if options.safe {
+            return bind(&[("a", addr), ("v", value)], |operands| {
+                format!("self.{self}.store({} {}, {offset}, ({} as {ty}).to_le_bytes())?", operands[0], self.u64_cast(), operands[1])
+            })
+        }
+        format!("__W2RMemory::store(::std::ptr::addr_of_mut!({}), {addr} {}, {offset}, ({value} as {ty}).to_le_bytes())", options.state(self), self.u64_cast())
+    }
+
+    /// The `len` bytes at `addr` of a sandboxed memory, as a mutable slice.
+    pub fn emit_slice(&self, addr: &str, len: &str, options: &EmitOptions) -> String {
+        if options.safe {
+            return format!("self.{self}.slice({addr} {cast}, {len} {cast})?", cast = self.u64_cast())
+        }
+        format!("__W2RMemory::slice(::std::ptr::addr_of_mut!({}), {addr} {cast}, {len} {cast})", options.state(self), cast = self.u64_cast())
+    }
+
+    /// Sandboxed copy of `len` bytes from `src` in `source` to `dst`.
+    pub fn emit_copy(&self, dst: &str, source: &Memory, src: &str, len: &str, options: &EmitOptions) -> String {
+        // the length is an `i64` only if both memories are memory64
+        let len_cast = if self.memory64 && source.memory64 { "as u64" } else { "as u32 as u64" };
+        if options.safe && self.index == source.index {
+            return format!("self.{self}.copy_within({dst} {}, {src} {}, {len} {len_cast})?", self.u64_cast(), source.u64_cast())
+        }
+        if options.safe {
+            return format!("self.{self}.copy_from({dst} {}, &self.{source}, {src} {}, {len} {len_cast})?", self.u64_cast(), source.u64_cast())
+        }
+        format!(
+            "__W2RMemory::copy(::std::ptr::addr_of_mut!({}), {dst} {}, ::std::ptr::addr_of!({}), {src} {}, {len} {})",
+            options.state(self),
+            self.u64_cast(),
+            options.state(source),
+            source.u64_cast(),
+            len_cast,
+        )
+    }
+
+    /// Current size in pages.
+    pub fn emit_size(&self, options: &EmitOptions) -> String {
+        let ty = self.address_type();
+        if options.safe {
+            format!("(self.{self}.size() as {ty})")
+        } else if self.is_sandboxed(options) {
+            format!("(__W2RMemory::size(::std::ptr::addr_of!({})) as {ty})", options.state(self))
+        } else {
+            format!("(::std::arch::wasm32::memory_size(0) as {ty})")
+        }
+    }
+
+    /// Grow by `delta` pages, evaluating to the previous size or -1.
+    pub fn emit_grow(&self, delta: &str, options: &EmitOptions) -> String {
+        let ty = self.address_type();
+        let delta_cast = if self.memory64 { "as u64" } else { "as u32 as u64" };
+        if options.safe {
+            bind(&[("d", delta)], |operands| format!("(self.{self}.grow({} {delta_cast}) as {ty})", operands[0]))
+        } else if self.is_sandboxed(options) {
+            format!("(__W2RMemory::grow(::std::ptr::addr_of_mut!({}), {delta} {delta_cast}) as {ty})", options.state(self))
+        } else {
+            // `usize::MAX` signals failure
+            format!("(::std::arch::wasm32::memory_grow(0, {delta} {}) as isize as {ty})", self.usize_cast())
+        }
+    }
+
+    /// Empty sandboxed memory.
+    pub fn emit_new(&self) -> String {
+        format!("__W2RMemory::new({})", self.maximum_pages())
+    }
+
+    /// Static holding an emulated or sandboxed memory; empty until `setup`
+    /// runs.
+    pub fn emit_code(&self, options: &EmitOptions) -> Vec<String> {
+        if self.is_sandboxed(options) {
+            vec!["".to_string(), format!("static mut {self}: __W2RMemory = {};", self.emit_new())]
+        } else {
+            vec![]
+        }
+    }
+
+    /// Statement of `setup` allocating the initial pages of an emulated or
+    /// sandboxed memory.
+    pub fn emit_allocation(&self, options: &EmitOptions) -> Option<String> {
+        let len = self.initial * PAGE_SIZE;
+        // an instance owns its memories, statics are borrowed through a
+        // raw pointer
+        let memory = if options.instance { options.state(self) } else { options.state_mut(self) };
+        self.is_sandboxed(options).then(|| match options.safe {
+            true => format!("{memory}.bytes.resize({len}, 0);"),
+            // stored as `u128` words
+            false => format!("{memory}.words.resize({}, 0);", len / 16),
+        })
+    }
+}
+
+/// `code` applied to the `operands` of a safe access. Operands that read the
+/// instance are bound to their variables first, since the access borrows
+/// one of its memories while its arguments are evaluated.
+fn bind(operands: &[(&str, &str)], code: impl Fn(&[&str]) -> String) -> String {
+    if operands.iter().all(|(_, value)| !value.contains("self.")) {
+        return code(&operands.iter().map(|(_, value)| *value).collect::<Vec<_>>())
+    }
+    let names = operands.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    let values = operands.iter().map(|(_, value)| *value).collect::<Vec<_>>();
+    match operands.len() {
+        1 => format!("{{ let {} = {}; {} }}", names[0], values[0], code(&names)),
+        _ => format!("{{ let ({}) = ({}); {} }}", names.join(", "), values.join(", "), code(&names)),
+    }
+}
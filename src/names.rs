@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// Names given to the parts of a module by its `name` custom section, as
+/// they appear there.
+#[derive(Debug, Clone, Default)]
+pub struct Names {
+    pub module: Option<String>,
+    pub functions: BTreeMap<u32, String>,
+    /// Names of the parameters and locals of each function.
+    pub locals: BTreeMap<u32, BTreeMap<u32, String>>,
+    /// Names of the labels of each function, indexed by the order in which
+    /// their blocks appear.
+    pub labels: BTreeMap<u32, BTreeMap<u32, String>>,
+    pub globals: BTreeMap<u32, String>,
+    pub data: BTreeMap<u32, String>,
+}
+
+impl Names {
+    /// Read the `name` section. A malformed subsection is skipped, along
+    /// with everything after it.
+    pub fn read(mut reader: wasmparser::NameSectionReader) -> Self {
+        let mut names = Self::default();
+
+        while !reader.eof() {
+            match reader.read() {
+                Ok(wasmparser::Name::Module(name)) => names.module = name.get_name().ok().map(str::to_string),
+                Ok(wasmparser::Name::Function(map)) => names.functions = read_map(map),
+                Ok(wasmparser::Name::Local(map)) => names.locals = read_indirect_map(map),
+                Ok(wasmparser::Name::Label(map)) => names.labels = read_indirect_map(map),
+                Ok(wasmparser::Name::Global(map)) => names.globals = read_map(map),
+                Ok(wasmparser::Name::Data(map)) => names.data = read_map(map),
+                Ok(_) => continue,
+                Err(_) => break,
+            }
+        }
+
+        names
+    }
+}
+
+fn read_map(map: wasmparser::NameMap) -> BTreeMap<u32, String> {
+    map.get_map().map(read_naming_reader).unwrap_or_default()
+}
+
+fn read_indirect_map(map: wasmparser::IndirectNameMap) -> BTreeMap<u32, BTreeMap<u32, String>> {
+    let mut names = BTreeMap::new();
+    if let Ok(mut reader) = map.get_indirect_map() {
+        for _ in 0..reader.get_indirect_count() {
+            match reader.read() {
+                Ok(naming) => {
+                    if let Ok(map) = naming.get_map() {
+                        names.insert(naming.indirect_index, read_naming_reader(map));
+                    }
+                },
+                Err(_) => break,
+            }
+        }
+    }
+    names
+}
+
+fn read_naming_reader(mut reader: wasmparser::NamingReader) -> BTreeMap<u32, String> {
+    let mut names = BTreeMap::new();
+    for _ in 0..reader.get_count() {
+        match reader.read() {
+            Ok(naming) => names.insert(naming.index, naming.name.to_string()),
+            Err(_) => break,
+        };
+    }
+    names
+}
+
+/// Strict and reserved keywords of every edition; none can name an item,
+/// a variable or a label.
+const KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in",
+    "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+    "trait", "true", "type", "unsafe", "use", "where", "while", "async", "await", "dyn", "abstract", "become", "box",
+    "do", "final", "macro", "override", "priv", "typeof", "unsized", "virtual", "yield", "try", "gen",
+];
+
+/// Items of the prelude the emitted code calls by name, which a function or
+/// static of the same name would shadow, and the functions it defines.
+pub const RESERVED_ITEMS: &[&str] = &["drop", "Some", "None", "Ok", "Err", "Box", "setup", "main", "new"];
+
+/// Methods every instance has, which an exported function must not be named
+/// after; methods are called on `self` and cannot shadow the prelude.
+pub const RESERVED_METHODS: &[&str] = &["new", "setup"];
+
+/// Fields every instance has besides the statics of the module.
+pub const RESERVED_FIELDS: &[&str] = &["host"];
+
+/// Types of the emitted instance code that a trait of the same name would
+/// shadow.
+pub const RESERVED_TYPES: &[&str] = &["Instance", "Option", "Vec", "Box", "Some", "None", "Ok", "Err"];
+
+/// Variables bound by the emitted code around the expressions it evaluates,
+/// which a local of the same name would be shadowed by.
+pub const RESERVED_LOCALS: &[&str] = &[
+    "a", "d", "f", "i", "n", "r", "s", "v", "e1", "e2", "byte", "bytes", "grown", "items", "payload", "rest",
+    "segment", "size", "slot", "slots", "table",
+];
+
+/// `name` turned into an ASCII identifier: other characters become
+/// underscores, and a leading digit or a keyword gets an extra underscore.
+pub fn identifier(name: &str) -> String {
+    let mut identifier = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect::<String>();
+
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    if identifier == "_" || KEYWORDS.contains(&identifier.as_str()) {
+        identifier.push('_');
+    }
+
+    identifier
+}
+
+/// Attribute exporting the item named `identifier` as `name`.
+pub fn export_attribute(identifier: &str, name: &str) -> String {
+    if identifier == name {
+        "#[no_mangle]".to_string()
+    } else {
+        format!("#[export_name = {name:?}]")
+    }
+}
+
+/// Identifier for `name` that is neither `used` yet nor of the form of
+/// the names the decompiler generates itself; the result is added to `used`.
+pub fn assign(name: &str, used: &mut BTreeSet<String>) -> String {
+    assign_unreserved(name, &[], used)
+}
+
+/// Identifier for a static named `name`, like [`assign`], that is also none
+/// of the [`RESERVED_LOCALS`], as a `let` binding cannot shadow a static, and
+/// none of the [`RESERVED_FIELDS`], as statics are the fields of an instance.
+pub fn assign_static(name: &str, used: &mut BTreeSet<String>) -> String {
+    assign_unreserved(name, &[RESERVED_LOCALS, RESERVED_FIELDS].concat(), used)
+}
+
+fn assign_unreserved(name: &str, reserved: &[&str], used: &mut BTreeSet<String>) -> String {
+    let mut name = identifier(name);
+    if is_generated(&name) {
+        name.insert(0, '_');
+    }
+    let taken = |candidate: &String| used.contains(candidate) || reserved.contains(&candidate.as_str());
+    if taken(&name) {
+        name = (1..).map(|n| format!("{name}_{n}")).find(|candidate| !taken(candidate)).unwrap();
+    }
+    used.insert(name.clone());
+    name
+}
+
+/// Type name for `name` in upper camel case, like `WasiSnapshotPreview1` for
+/// `wasi_snapshot_preview1`, that is not `used` yet; it is added to `used`.
+pub fn assign_type(name: &str, used: &mut BTreeSet<String>) -> String {
+    let camel = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+        })
+        .collect::<String>();
+    assign(if camel.is_empty() { "Host" } else { &camel }, used)
+}
+
+/// Path of the item a Rust symbol names, mangled in the legacy or the v0
+/// scheme, without the hash and crate disambiguators; `None` when `symbol`
+/// is not mangled.
+pub fn demangle(symbol: &str) -> Option<String> {
+    rustc_demangle::try_demangle(symbol).ok().map(|path| format!("{path:#}"))
+}
+
+/// Crate at the start of a demangled `path`, looking into qualified paths:
+/// `core` for `<core::fmt::Error as core::fmt::Debug>::fmt`; `None` when
+/// the path has a single segment.
+pub fn path_crate(path: &str) -> Option<&str> {
+    let mut start = None;
+    for (i, c) in path.char_indices() {
+        if c.is_alphanumeric() || c == '_' {
+            start.get_or_insert(i);
+        } else if let Some(start) = start.take() {
+            if path[i..].starts_with("::") {
+                return Some(&path[start..i])
+            }
+        }
+    }
+    None
+}
+
+/// Name of the item at the end of a demangled `path`, skipping its generic
+/// arguments: `write` for `core::fmt::write`, and `main_closure` for the
+/// closures of `main` (`main::{closure#0}`).
+pub fn item_name(path: &str) -> String {
+    let segments = split_path(path).into_iter().filter(|segment| !segment.starts_with('<')).collect::<Vec<_>>();
+    segments_name(&segments).unwrap_or_else(|| path.to_string())
+}
+
+fn segments_name(segments: &[&str]) -> Option<String> {
+    let (last, parents) = segments.split_last()?;
+    match last.strip_prefix('{') {
+        Some(inner) => {
+            let kind = inner.trim_start_matches('{').split(|c: char| !c.is_ascii_alphanumeric() && c != '_').next().unwrap_or_default();
+            match segments_name(parents) {
+                Some(parent) => Some(format!("{parent}_{kind}")),
+                None => Some(kind.to_string()),
+            }
+        },
+        None => Some(last.split('<').next().unwrap_or(last).to_string()),
+    }
+}
+
+/// `path` split at the `::` separators that are not inside generic
+/// arguments, qualified paths, types or disambiguators.
+fn split_path(path: &str) -> Vec<&str> {
+    let mut segments = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut previous = None;
+    let mut chars = path.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '<' | '(' | '[' | '{' => depth += 1,
+            '>' if previous == Some('-') => (),
+            '>' | ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 && matches!(chars.peek(), Some((_, ':'))) => {
+                segments.push(&path[start..i]);
+                chars.next();
+                start = i + 2;
+            },
+            _ => (),
+        }
+        previous = Some(c);
+    }
+    segments.push(&path[start..]);
+
+    segments
+}
+
+/// Whether `name` has the form of the variables and items the decompiler
+/// names itself: a lowercase letter followed by a digit, like `p0` or
+/// `v1_2`, or the `__w2r` prefix of statics and helpers.
+fn is_generated(name: &str) -> bool {
+    let mut chars = name.chars();
+    let numbered = matches!((chars.next(), chars.next()), (Some(c), Some(d)) if c.is_ascii_lowercase() && d.is_ascii_digit());
+    numbered || name.to_ascii_lowercase().starts_with("__w2r")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demangles_legacy_and_v0_symbols() {
+        assert_eq!(demangle("_ZN4core3fmt5write17h0123456789abcdefE").as_deref(), Some("core::fmt::write"));
+        assert_eq!(demangle("_RNvCs1234_7mycrate3foo").as_deref(), Some("mycrate::foo"));
+        assert_eq!(demangle("_RNCNvCs1234_7mycrate4main0").as_deref(), Some("mycrate::main::{closure#0}"));
+        assert_eq!(demangle("memcpy"), None);
+    }
+
+    #[test]
+    fn names_closures_after_their_parent() {
+        assert_eq!(item_name("main::{closure#0}"), "main_closure");
+        assert_eq!(item_name("std::rt::lang_start::{{closure}}"), "lang_start_closure");
+        assert_eq!(item_name("{closure#0}"), "closure");
+    }
+
+    #[test]
+    fn names_items_of_generic_and_qualified_paths() {
+        let path = "<core::fmt::Error as core::fmt::Debug>::fmt";
+        assert_eq!(item_name(path), "fmt");
+        assert_eq!(path_crate(path), Some("core"));
+
+        assert_eq!(item_name("core::ptr::drop_in_place<alloc::string::String>"), "drop_in_place");
+        assert_eq!(item_name("alloc::vec::Vec<T>::push"), "push");
+        assert_eq!(path_crate("alloc::vec::Vec<T>::push"), Some("alloc"));
+        assert_eq!(item_name("<T as core::any::Any>::type_id"), "type_id");
+        assert_eq!(path_crate("main"), None);
+    }
+
+    #[test]
+    fn sanitizes_identifiers() {
+        assert_eq!(identifier("my-func"), "my_func");
+        assert_eq!(identifier("match"), "match_");
+        assert_eq!(identifier("Self"), "Self_");
+        assert_eq!(identifier("1st"), "_1st");
+        assert_eq!(identifier("_"), "__");
+        assert_eq!(identifier(""), "__");
+        assert_eq!(identifier("caf\u{e9}"), "caf_");
+    }
+
+    #[test]
+    fn assigns_unused_identifiers() {
+        let mut used = BTreeSet::new();
+        assert_eq!(assign("count", &mut used), "count");
+        assert_eq!(assign("count", &mut used), "count_1");
+        assert_eq!(assign("count", &mut used), "count_2");
+        assert_eq!(assign("loop", &mut used), "loop_");
+        assert_eq!(assign("loop", &mut used), "loop__1");
+        assert!(used.contains("count_2"));
+    }
+
+    #[test]
+    fn escapes_generated_names() {
+        let mut used = BTreeSet::new();
+        assert_eq!(assign("p0", &mut used), "_p0");
+        assert_eq!(assign("v1_2", &mut used), "_v1_2");
+        assert_eq!(assign("__w2r_memory0", &mut used), "___w2r_memory0");
+        assert_eq!(assign("__W2RTrap", &mut used), "___W2RTrap");
+        assert_eq!(assign("pp0", &mut used), "pp0");
+    }
+
+    #[test]
+    fn keeps_statics_clear_of_reserved_locals() {
+        let mut used = BTreeSet::new();
+        assert_eq!(assign_static("n", &mut used), "n_1");
+        assert_eq!(assign_static("size", &mut used), "size_1");
+        assert_eq!(assign("n", &mut used), "n");
+        assert_eq!(assign_static("counter", &mut used), "counter");
+        assert_eq!(assign_static("host", &mut used), "host_1");
+    }
+
+    #[test]
+    fn camel_cases_type_names() {
+        let mut used = BTreeSet::new();
+        assert_eq!(assign_type("wasi_snapshot_preview1", &mut used), "WasiSnapshotPreview1");
+        assert_eq!(assign_type("env", &mut used), "Env");
+        assert_eq!(assign_type("--", &mut used), "Host");
+        assert_eq!(assign_type("env", &mut used), "Env_1");
+    }
+}
@@ -0,0 +1,87 @@
+/// Settings controlling the shape of the emitted Rust code.
+#[derive(Debug, Clone, Default)]
+pub struct EmitOptions {
+    /// Trap through `__w2r_trap` exactly where the spec does (integer division
+    /// by zero or overflow, unrepresentable float to integer truncation, ...)
+    /// instead of relying on Rust's panics and unchecked conversions.
+    pub trapping: bool,
+    /// Back every memory with a bounds-checked `__W2RMemory` instead of the
+    /// native memory of wasm32, so that the code also runs on other targets.
+    pub sandboxed: bool,
+    /// Emit an `Instance` struct owning the memories, globals and tables,
+    /// with the functions as its methods and one trait per import module
+    /// for the host to implement. Its memories are always sandboxed.
+    pub instance: bool,
+    /// Run `setup` automatically, as a constructor the linker calls before
+    /// anything else, instead of exporting it.
+    pub auto_setup: bool,
+    /// Directory, relative to the emitted source file, from which the data
+    /// segments are `include_bytes!`ed instead of being written out as byte
+    /// strings.
+    pub data_dir: Option<String>,
+    /// Emit only safe Rust: an instance whose functions return traps as
+    /// `Err(__W2RTrap)`, propagated with `?`, instead of panicking.
+    pub safe: bool,
+    /// Spread the imports and functions over the module files of a crate,
+    /// as [`Context::emit_crate`](crate::context::Context::emit_crate) does;
+    /// they are then visible to the whole crate.
+    pub modules: bool,
+}
+
+impl EmitOptions {
+    /// Path of the module-level item `name`: a field of `self` when emitting
+    /// an instance.
+    pub fn state(&self, name: impl std::fmt::Display) -> String {
+        if self.instance {
+            format!("self.{name}")
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// Shared reference to the module-level item `name`. Statics are
+    /// borrowed through a raw pointer to avoid references to `static mut`.
+    pub fn state_ref(&self, name: impl std::fmt::Display) -> String {
+        if self.safe {
+            self.state(name)
+        } else {
+            format!("(&*::std::ptr::addr_of!({}))", self.state(name))
+        }
+    }
+
+    /// Mutable reference to the module-level item `name`.
+    pub fn state_mut(&self, name: impl std::fmt::Display) -> String {
+        if self.safe {
+            self.state(name)
+        } else {
+            format!("(&mut *::std::ptr::addr_of_mut!({}))", self.state(name))
+        }
+    }
+
+    /// Code trapping with the `__W2RTrap` variant `kind`.
+    pub fn trap(&self, kind: &str) -> String {
+        if self.safe {
+            format!("return Err(__W2RTrap::{kind})")
+        } else {
+            format!("__w2r_trap(__W2RTrap::{kind})")
+        }
+    }
+
+    /// Visibility of the items that other module files of a crate use.
+    pub fn visibility(&self) -> &'static str {
+        if self.modules {
+            "pub(crate) "
+        } else {
+            ""
+        }
+    }
+
+    /// Suffix propagating the trap a call may return.
+    pub fn try_suffix(&self) -> &'static str {
+        if self.safe {
+            "?"
+        } else {
+            ""
+        }
+    }
+}
@@ -1,44 +1,51 @@
 use wasmparser::{Parser, Payload};
 use crate::context::Context;
+use crate::expression::ParserError;
+use crate::names::Names;
 
 
-pub fn parse(data: &[u8]) -> Context {
+/// Read the module encoded in `data`, failing on the first function using an
+/// operator that cannot be decompiled.
+pub fn parse(data: &[u8]) -> Result<Context, ParserError<'_>> {
     let parser = Parser::new(0);
 
     let mut c = Context::builder();
 
-    for result in parser.parse_all(data) {
-        if let Ok(payload) = result {
-            match payload {
-                Payload::FunctionSection(funcs) => c = c.set_funcs(funcs),
-                Payload::TypeSection(types) => c = c.set_types(types),
-                Payload::ImportSection(imports) => c = c.set_imports(imports),
-                Payload::CodeSectionEntry(body) => c = c.add_code_section(body),
-                Payload::TableSection(_) => {},
-                Payload::MemorySection(memory) => c = c.set_memory(memory),
-                Payload::GlobalSection(_) => {},
-                Payload::ExportSection(exports) => c = c.set_exports(exports),
-                Payload::StartSection { .. } => {},
-                Payload::DataCountSection { .. } => {},
-                Payload::DataSection(reader) => c = c.add_data_section(reader),
-                Payload::ElementSection(_) => {}
-                Payload::CustomSection(_)
-                | Payload::Version { .. }
-                | Payload::TagSection(_)
-                | Payload::ComponentSection { .. } 
-                | Payload::ComponentTypeSection(_)
-                | Payload::ComponentImportSection(_)
-                | Payload::ComponentFunctionSection(_)
-                | Payload::ModuleSection { .. }   
-                | Payload::InstanceSection(_)
-                | Payload::ComponentExportSection(_)
-                | Payload::ComponentStartSection(_)
-                | Payload::UnknownSection { .. }
-                | Payload::AliasSection(_)
-                | Payload::CodeSectionStart { .. }
-                | Payload::End(_)
-                => { }
-            }
+    for payload in parser.parse_all(data).flatten() {
+        match payload {
+            Payload::FunctionSection(funcs) => c = c.set_funcs(funcs),
+            Payload::TypeSection(types) => c = c.set_types(types),
+            Payload::ImportSection(imports) => c = c.set_imports(imports),
+            Payload::CodeSectionEntry(body) => c = c.add_code_section(body),
+            Payload::TableSection(tables) => c = c.set_tables(tables),
+            Payload::MemorySection(memory) => c = c.set_memory(memory),
+            Payload::GlobalSection(globals) => c = c.set_globals(globals),
+            Payload::ExportSection(exports) => c = c.set_exports(exports),
+            Payload::StartSection { func, .. } => c = c.set_start(func),
+            Payload::DataCountSection { .. } => {},
+            Payload::DataSection(reader) => c = c.add_data_section(reader),
+            Payload::ElementSection(elements) => c = c.set_elements(elements),
+            Payload::TagSection(tags) => c = c.set_tags(tags),
+            Payload::CustomSection(section) if section.name() == "name" => {
+                if let Ok(reader) = wasmparser::NameSectionReader::new(section.data(), section.data_offset()) {
+                    c = c.set_names(Names::read(reader));
+                }
+            },
+            Payload::CustomSection(_)
+            | Payload::Version { .. }
+            | Payload::ComponentSection { .. } 
+            | Payload::ComponentTypeSection(_)
+            | Payload::ComponentImportSection(_)
+            | Payload::ComponentFunctionSection(_)
+            | Payload::ModuleSection { .. }   
+            | Payload::InstanceSection(_)
+            | Payload::ComponentExportSection(_)
+            | Payload::ComponentStartSection(_)
+            | Payload::UnknownSection { .. }
+            | Payload::AliasSection(_)
+            | Payload::CodeSectionStart { .. }
+            | Payload::End(_)
+            => { }
         }
     }
     
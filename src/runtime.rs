@@ -0,0 +1,515 @@
+//! Support code called by the emitted functions. Only the helpers a module
+//! actually references are included in its output.
+
+use crate::options::EmitOptions;
+
+
+struct Helper {
+    /// Text whose presence in the emitted code requires this helper.
+    key: String,
+    code: String,
+}
+
+impl Helper {
+    fn function(name: &str, code: String) -> Self {
+        Self { key: format!("{name}("), code }
+    }
+
+    fn item(name: &str, code: &str) -> Self {
+        Self { key: name.to_string(), code: code.to_string() }
+    }
+
+    /// Whether `code` needs this helper: it mentions the key, other than at
+    /// the end of a longer identifier like that of a function `___w2r_trap`
+    /// of the module.
+    fn is_used_by(&self, code: &str) -> bool {
+        code.match_indices(&self.key).any(|(i, _)| !code[..i].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_'))
+    }
+}
+
+const TRAP: &str = "#[allow(dead_code)]
+#[derive(Debug)]
+enum __W2RTrap {
+    Unreachable,
+    IntegerDivideByZero,
+    IntegerOverflow,
+    InvalidConversionToInteger,
+    UndefinedElement,
+    IndirectCallTypeMismatch,
+    MemoryOutOfBounds,
+    TableOutOfBounds,
+    UnalignedAtomic,
+}
+
+#[cold]
+#[inline(never)]
+fn __w2r_trap(kind: __W2RTrap) -> ! {
+    panic!(\"wasm trap: {:?}\", kind)
+}";
+
+/// Kind of trap returned by safe code, which can be matched on by callers.
+const SAFE_TRAP: &str = "#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum __W2RTrap {
+    Unreachable,
+    IntegerDivideByZero,
+    IntegerOverflow,
+    InvalidConversionToInteger,
+    UndefinedElement,
+    IndirectCallTypeMismatch,
+    MemoryOutOfBounds,
+    TableOutOfBounds,
+    UnalignedAtomic,
+}";
+
+/// Non-null `funcref`: a type-erased function pointer tagged with the
+/// canonical index of its signature.
+const FUNC_REF: &str = "#[derive(Clone, Copy)]
+struct __W2RFuncRef {
+    ty: u32,
+    func: unsafe fn(),
+}";
+
+/// Non-null `funcref` of safe code: the index of the function, which the
+/// instance dispatches on.
+const SAFE_FUNC_REF: &str = "#[derive(Clone, Copy)]
+struct __W2RFuncRef {
+    ty: u32,
+    func: u32,
+}";
+
+/// Non-null `externref`: an opaque handle to a host value.
+const EXTERN_REF: &str = "#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct __W2RExternRef(u32);";
+
+/// Panic payload of a WASM exception: the index of its tag and a tuple of
+/// the values it carries. Cloned by `rethrow`. Edition 2015 reads
+/// `dyn ::std::...` as a path, hence the relative `std`.
+const EXCEPTION: &str = "#[derive(Clone)]
+struct __W2RException {
+    tag: u32,
+    values: ::std::sync::Arc<dyn std::any::Any + Send + Sync>,
+}";
+
+/// `memory.grow` of a vector of bytes, or of wider words, by `delta` pages,
+/// up to `maximum` pages. Returns the previous size in pages, or -1 on
+/// failure.
+const MEMORY_GROW: &str = "fn __w2r_memory_grow<T: Clone + Default>(memory: &mut Vec<T>, delta: u64, maximum: u64) -> i64 {
+    let page = 65536 / ::std::mem::size_of::<T>() as u64;
+    let pages = memory.len() as u64 / page;
+    let grown = pages.checked_add(delta).filter(|total| *total <= maximum);
+    match grown.and_then(|total| <usize as ::std::convert::TryFrom<u64>>::try_from(total * page).ok()) {
+        Some(len) if memory.try_reserve(len - memory.len()).is_ok() => {
+            memory.resize(len, T::default());
+            pages as i64
+        },
+        _ => -1,
+    }
+}";
+
+/// Linear memory of the sandboxed backend: bytes accessed through
+/// bounds-checked, little-endian accessors. They take the memory as a raw
+/// pointer to its static, so that no reference to it is held while the
+/// operands of an access are evaluated. The bytes are stored as `u128`
+/// words, so that the aligned addresses of atomic accesses are aligned
+/// pointers.
+const MEMORY: &str = "struct __W2RMemory {
+    words: Vec<u128>,
+    maximum: u64,
+}
+
+#[allow(dead_code)]
+impl __W2RMemory {
+    const fn new(maximum: u64) -> Self {
+        Self { words: Vec::new(), maximum }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.words.as_ptr().cast(), self.words.len() * 16) }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { ::std::slice::from_raw_parts_mut(self.words.as_mut_ptr().cast(), self.words.len() * 16) }
+    }
+
+    fn range(&self, addr: u64, offset: u64, len: u64) -> ::std::ops::Range<usize> {
+        match addr.checked_add(offset).and_then(|start| Some((start, start.checked_add(len)?))) {
+            Some((start, end)) if end <= self.bytes().len() as u64 => start as usize..end as usize,
+            _ => __w2r_trap(__W2RTrap::MemoryOutOfBounds),
+        }
+    }
+
+    unsafe fn load<const N: usize>(memory: *const Self, addr: u64, offset: u64) -> [u8; N] {
+        let memory = &*memory;
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&memory.bytes()[memory.range(addr, offset, N as u64)]);
+        bytes
+    }
+
+    unsafe fn store<const N: usize>(memory: *mut Self, addr: u64, offset: u64, bytes: [u8; N]) {
+        let memory = &mut *memory;
+        let range = memory.range(addr, offset, N as u64);
+        memory.bytes_mut()[range].copy_from_slice(&bytes);
+    }
+
+    unsafe fn pointer<T>(memory: *mut Self, addr: u64, offset: u64) -> *mut T {
+        let memory = &mut *memory;
+        let range = memory.range(addr, offset, ::std::mem::size_of::<T>() as u64);
+        memory.bytes_mut()[range].as_mut_ptr().cast()
+    }
+
+    unsafe fn atomic<T>(memory: *mut Self, addr: u64, offset: u64) -> *mut T {
+        let pointer = Self::pointer(memory, addr, offset);
+        if (addr + offset) % ::std::mem::size_of::<T>() as u64 != 0 {
+            __w2r_trap(__W2RTrap::UnalignedAtomic)
+        }
+        pointer
+    }
+
+    unsafe fn slice<'a>(memory: *mut Self, addr: u64, len: u64) -> &'a mut [u8] {
+        let memory = &mut *memory;
+        let range = memory.range(addr, 0, len);
+        &mut memory.bytes_mut()[range]
+    }
+
+    unsafe fn copy(memory: *mut Self, dst: u64, source: *const Self, src: u64, len: u64) {
+        let src = (&*source).range(src, 0, len);
+        let dst = (&*memory).range(dst, 0, len);
+        if ::std::ptr::eq(memory, source) {
+            (&mut *memory).bytes_mut().copy_within(src, dst.start)
+        } else {
+            (&mut *memory).bytes_mut()[dst].copy_from_slice(&(&*source).bytes()[src])
+        }
+    }
+
+    unsafe fn size(memory: *const Self) -> u64 {
+        (&*memory).bytes().len() as u64 / 65536
+    }
+
+    unsafe fn grow(memory: *mut Self, delta: u64) -> i64 {
+        let memory = &mut *memory;
+        __w2r_memory_grow(&mut memory.words, delta, memory.maximum)
+    }
+}";
+
+/// Linear memory of safe code, whose accessors borrow it and return an
+/// out of bounds access as an error.
+const SAFE_MEMORY: &str = "struct __W2RMemory {
+    bytes: Vec<u8>,
+    maximum: u64,
+}
+
+#[allow(dead_code)]
+impl __W2RMemory {
+    const fn new(maximum: u64) -> Self {
+        Self { bytes: Vec::new(), maximum }
+    }
+
+    fn range(&self, addr: u64, offset: u64, len: u64) -> Result<::std::ops::Range<usize>, __W2RTrap> {
+        match addr.checked_add(offset).and_then(|start| Some((start, start.checked_add(len)?))) {
+            Some((start, end)) if end <= self.bytes.len() as u64 => Ok(start as usize..end as usize),
+            _ => Err(__W2RTrap::MemoryOutOfBounds),
+        }
+    }
+
+    fn load<const N: usize>(&self, addr: u64, offset: u64) -> Result<[u8; N], __W2RTrap> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&self.bytes[self.range(addr, offset, N as u64)?]);
+        Ok(bytes)
+    }
+
+    fn store<const N: usize>(&mut self, addr: u64, offset: u64, bytes: [u8; N]) -> Result<(), __W2RTrap> {
+        let range = self.range(addr, offset, N as u64)?;
+        self.bytes[range].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn atomic<const N: usize>(&mut self, addr: u64, offset: u64) -> Result<&mut [u8; N], __W2RTrap> {
+        let range = self.range(addr, offset, N as u64)?;
+        if range.start % N != 0 {
+            return Err(__W2RTrap::UnalignedAtomic)
+        }
+        <&mut [u8; N] as ::std::convert::TryFrom<&mut [u8]>>::try_from(&mut self.bytes[range]).map_err(|_| __W2RTrap::MemoryOutOfBounds)
+    }
+
+    fn slice(&mut self, addr: u64, len: u64) -> Result<&mut [u8], __W2RTrap> {
+        let range = self.range(addr, 0, len)?;
+        Ok(&mut self.bytes[range])
+    }
+
+    fn copy_within(&mut self, dst: u64, src: u64, len: u64) -> Result<(), __W2RTrap> {
+        let src = self.range(src, 0, len)?;
+        let dst = self.range(dst, 0, len)?;
+        self.bytes.copy_within(src, dst.start);
+        Ok(())
+    }
+
+    fn copy_from(&mut self, dst: u64, source: &Self, src: u64, len: u64) -> Result<(), __W2RTrap> {
+        let src = source.range(src, 0, len)?;
+        let dst = self.range(dst, 0, len)?;
+        self.bytes[dst].copy_from_slice(&source.bytes[src]);
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.bytes.len() as u64 / 65536
+    }
+
+    fn grow(&mut self, delta: u64) -> i64 {
+        __w2r_memory_grow(&mut self.bytes, delta, self.maximum)
+    }
+}";
+
+/// Bulk accesses to the native memory, by name. Address 0 is null to Rust,
+/// which only volatile accesses may touch, so a range starting there has its
+/// first byte accessed on its own.
+const NATIVE_BULK: [(&str, &str); 4] = [
+    ("__w2r_native_read", "unsafe fn __w2r_native_read(src: usize, bytes: &mut [u8]) {
+    if bytes.is_empty() {
+        return
+    }
+    let start = if src == 0 {
+        bytes[0] = ::std::ptr::read_volatile(0 as *const u8);
+        1
+    } else {
+        0
+    };
+    ::std::ptr::copy_nonoverlapping((src + start) as *const u8, bytes[start..].as_mut_ptr(), bytes.len() - start);
+}"),
+    ("__w2r_native_write", "unsafe fn __w2r_native_write(dst: usize, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return
+    }
+    let start = if dst == 0 {
+        ::std::ptr::write_volatile(0 as *mut u8, bytes[0]);
+        1
+    } else {
+        0
+    };
+    ::std::ptr::copy_nonoverlapping(bytes[start..].as_ptr(), (dst + start) as *mut u8, bytes.len() - start);
+}"),
+    ("__w2r_native_fill", "unsafe fn __w2r_native_fill(dst: usize, value: u8, len: usize) {
+    if len == 0 {
+        return
+    }
+    let start = if dst == 0 {
+        ::std::ptr::write_volatile(0 as *mut u8, value);
+        1
+    } else {
+        0
+    };
+    ::std::ptr::write_bytes((dst + start) as *mut u8, value, len - start);
+}"),
+    ("__w2r_native_copy", "unsafe fn __w2r_native_copy(dst: usize, src: usize, len: usize) {
+    if len == 0 {
+        return
+    }
+    if dst != 0 && src != 0 {
+        ::std::ptr::copy(src as *const u8, dst as *mut u8, len);
+        return
+    }
+    // bytewise, in the direction that reads each byte before it is overwritten
+    let copy = |i: usize| ::std::ptr::write_volatile((dst + i) as *mut u8, ::std::ptr::read_volatile((src + i) as *const u8));
+    if dst <= src {
+        (0..len).for_each(copy)
+    } else {
+        (0..len).rev().for_each(copy)
+    }
+}"),
+];
+
+/// Exclusive bounds of the floats whose truncation is representable, for
+/// each integer, float and signedness, with the Rust type the truncation
+/// casts to.
+const TRUNCATIONS: [(&str, &str, &str, &str, &str, &str); 8] = [
+    ("i32", "f32", "s", "-2147483904.0", "2147483648.0", "i32"),
+    ("i32", "f32", "u", "-1.0", "4294967296.0", "u32"),
+    ("i32", "f64", "s", "-2147483649.0", "2147483648.0", "i32"),
+    ("i32", "f64", "u", "-1.0", "4294967296.0", "u32"),
+    ("i64", "f32", "s", "-9223373136366403584.0", "9223372036854775808.0", "i64"),
+    ("i64", "f32", "u", "-1.0", "18446744073709551616.0", "u64"),
+    ("i64", "f64", "s", "-9223372036854777856.0", "9223372036854775808.0", "i64"),
+    ("i64", "f64", "u", "-1.0", "18446744073709551616.0", "u64"),
+];
+
+fn helpers(options: &EmitOptions) -> Vec<Helper> {
+    let mut helpers = vec![
+        if options.safe { Helper::item("__W2RTrap", SAFE_TRAP) } else { Helper::function("__w2r_trap", TRAP.to_string()) },
+        Helper::item("__W2RFuncRef", if options.safe { SAFE_FUNC_REF } else { FUNC_REF }),
+        Helper::item("__W2RExternRef", EXTERN_REF),
+        Helper::item("__W2RException", EXCEPTION),
+        Helper::function("__w2r_memory_grow", MEMORY_GROW.to_string()),
+        Helper::item("__W2RMemory", if options.safe { SAFE_MEMORY } else { MEMORY }),
+    ];
+
+    // safe code returns traps, and its callers propagate them with `?`
+    let trap = |kind: &str| options.trap(kind);
+    let result = |ty: &str| if options.safe { format!("Result<{ty}, __W2RTrap>") } else { ty.to_string() };
+    let ok = |value: String| if options.safe { format!("Ok({value})") } else { value };
+
+    for (int, uint) in [("i32", "u32"), ("i64", "u64")] {
+        helpers.push(Helper::function(&format!("__w2r_{int}_div_s"), format!(
+"fn __w2r_{int}_div_s(a: {int}, b: {int}) -> {} {{
+    match b {{
+        0 => {},
+        -1 if a == {int}::MIN => {},
+        _ => {},
+    }}
+}}", result(int), trap("IntegerDivideByZero"), trap("IntegerOverflow"), ok("a / b".to_string()))));
+        helpers.push(Helper::function(&format!("__w2r_{int}_div_u"), format!(
+"fn __w2r_{int}_div_u(a: {int}, b: {int}) -> {} {{
+    if b == 0 {{
+        {}
+    }}
+    {}
+}}", result(int), trap("IntegerDivideByZero"), ok(format!("((a as {uint}) / (b as {uint})) as {int}")))));
+        helpers.push(Helper::function(&format!("__w2r_{int}_rem_s"), format!(
+"fn __w2r_{int}_rem_s(a: {int}, b: {int}) -> {} {{
+    if b == 0 {{
+        {}
+    }}
+    {}
+}}", result(int), trap("IntegerDivideByZero"), ok("a.wrapping_rem(b)".to_string()))));
+        helpers.push(Helper::function(&format!("__w2r_{int}_rem_u"), format!(
+"fn __w2r_{int}_rem_u(a: {int}, b: {int}) -> {} {{
+    if b == 0 {{
+        {}
+    }}
+    {}
+}}", result(int), trap("IntegerDivideByZero"), ok(format!("((a as {uint}) % (b as {uint})) as {int}")))));
+    }
+
+    // NaNs propagate and -0 orders below +0, unlike `f32::min`/`f32::max`
+    for float in ["f32", "f64"] {
+        helpers.push(Helper::function(&format!("__w2r_{float}_min"), format!(
+"fn __w2r_{float}_min(a: {float}, b: {float}) -> {float} {{
+    if a.is_nan() || b.is_nan() {{
+        a + b
+    }} else if a == b {{
+        {float}::from_bits(a.to_bits() | b.to_bits())
+    }} else if a < b {{
+        a
+    }} else {{
+        b
+    }}
+}}")));
+        helpers.push(Helper::function(&format!("__w2r_{float}_max"), format!(
+"fn __w2r_{float}_max(a: {float}, b: {float}) -> {float} {{
+    if a.is_nan() || b.is_nan() {{
+        a + b
+    }} else if a == b {{
+        {float}::from_bits(a.to_bits() & b.to_bits())
+    }} else if a > b {{
+        a
+    }} else {{
+        b
+    }}
+}}")));
+    }
+
+    for (name, code) in NATIVE_BULK {
+        helpers.push(Helper::function(name, code.to_string()));
+    }
+
+    for (int, float, sign, lower, upper, cast) in TRUNCATIONS {
+        let name = format!("__w2r_{int}_trunc_{float}_{sign}");
+        let cast = if cast == int { format!("x as {int}") } else { format!("x as {cast} as {int}") };
+        helpers.push(Helper::function(&name, format!(
+"fn {name}(x: {float}) -> {} {{
+    if x.is_nan() {{
+        {}
+    }}
+    if !(x > {lower}{float} && x < {upper}{float}) {{
+        {}
+    }}
+    {}
+}}", result(int), trap("InvalidConversionToInteger"), trap("IntegerOverflow"), ok(cast))));
+    }
+
+    helpers
+}
+
+/// Definitions of every helper referenced by `lines`, directly or through
+/// another helper.
+pub fn emit_helpers(lines: &[String], options: &EmitOptions) -> Vec<String> {
+    let helpers = helpers(options);
+    let mut used = vec![false; helpers.len()];
+    let mut code = lines.join("\n");
+
+    loop {
+        let mut changed = false;
+        for (helper, used) in helpers.iter().zip(used.iter_mut()) {
+            if !*used && helper.is_used_by(&code) {
+                *used = true;
+                code.push_str(&helper.code);
+                changed = true;
+            }
+        }
+        if !changed {
+            break
+        }
+    }
+
+    helpers
+        .iter()
+        .zip(used)
+        .filter(|(_, used)| *used)
+        .flat_map(|(helper, _)| {
+            std::iter::once("".to_string()).chain(helper.code.lines().map(|line| line.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Range of the integers a truncation to `cast` can produce.
+    fn range(cast: &str) -> (i128, i128) {
+        match cast {
+            "i32" => (i32::MIN.into(), i32::MAX.into()),
+            "u32" => (0, u32::MAX.into()),
+            "i64" => (i64::MIN.into(), i64::MAX.into()),
+            "u64" => (0, u64::MAX.into()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn truncation_bounds_are_the_closest_unrepresentable_floats() {
+        for (int, float, sign, lower, upper, cast) in TRUNCATIONS {
+            let name = format!("__w2r_{int}_trunc_{float}_{sign}");
+            let (min, max) = range(cast);
+            let representable = |x: f64| (min..=max).contains(&(x.trunc() as i128));
+            // the bounds, which must be exact, and their neighbours inside
+            let (lower, upper) = (lower.parse::<f64>().unwrap(), upper.parse::<f64>().unwrap());
+            let (above_lower, below_upper) = match float {
+                "f32" => {
+                    assert!(lower as f32 as f64 == lower && upper as f32 as f64 == upper, "bounds of {name}");
+                    ((lower as f32).next_up() as f64, (upper as f32).next_down() as f64)
+                },
+                _ => (lower.next_up(), upper.next_down()),
+            };
+            assert!(!representable(lower) && representable(above_lower), "lower bound of {name}");
+            assert!(!representable(upper) && representable(below_upper), "upper bound of {name}");
+        }
+    }
+
+    #[test]
+    fn truncations_trap_outside_their_bounds() {
+        let options = EmitOptions::default();
+        let helpers = helpers(&options);
+        for (int, float, sign, lower, upper, _) in TRUNCATIONS {
+            let name = format!("__w2r_{int}_trunc_{float}_{sign}");
+            let helper = helpers.iter().find(|helper| helper.key == format!("{name}(")).unwrap();
+            assert!(helper.code.contains(&format!("!(x > {lower}{float} && x < {upper}{float})")), "{name}");
+        }
+    }
+
+    #[test]
+    fn helpers_are_not_used_by_longer_identifiers() {
+        let helper = Helper::function("__w2r_native_copy", String::new());
+        assert!(helper.is_used_by("{ __w2r_native_copy(a, b, c); }"));
+        assert!(!helper.is_used_by("self.___w2r_native_copy(a, b, c)?;"));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::expression::{Expression, LevelKind};
+use crate::expression::{emit_address, emit_lanes, escape_variable, escaping_try, exception_variable, sandboxed_address, table_out_of_bounds, tuple_type, AtomicAccess, Expression, Level, LevelKind, SimdAccess};
+use crate::wasm_type::WASMType;
+use crate::options::EmitOptions;
 
 #[allow(dead_code)]
 #[derive(Clone, Debug)]
@@ -9,6 +11,12 @@ pub enum Statement {
     Nop,
     GlobalSet(String, Expression),
     Unassigned(Expression),
+    Call(Expression),
+    Let {
+        names: Vec<String>,
+        value: Expression,
+        mutable: bool,
+    },
     I32Store(Expression, Expression, u8, u64),
     I64Store(Expression, Expression, u8, u64),
     F32Store(Expression, Expression, u8, u64),
@@ -19,63 +27,175 @@ pub enum Statement {
     I64Store16(Expression, Expression, u8, u64),
     I64Store32(Expression, Expression, u8, u64),
     Drop(Expression),
-    Block(Vec<Statement>, u32),
-    Loop(Vec<Statement>, u32),
+    MemoryCopy {
+        dst: Expression,
+        src: Expression,
+        len: Expression,
+        /// Whether the length is an `i64`.
+        memory64: bool,
+    },
+    MemoryFill {
+        dst: Expression,
+        value: Expression,
+        len: Expression,
+        memory64: bool,
+    },
+    MemoryInit {
+        segment: Option<String>,
+        dst: Expression,
+        src: Expression,
+        len: Expression,
+    },
+    DataDrop(Option<String>),
+    TableSet(String, Expression, Expression),
+    TableFill(String, Expression, Expression, Expression),
+    TableCopy {
+        dst_table: String,
+        src_table: String,
+        dst: Expression,
+        src: Expression,
+        len: Expression,
+    },
+    TableInit {
+        table: String,
+        ty: WASMType,
+        segment: Option<String>,
+        dst: Expression,
+        src: Expression,
+        len: Expression,
+    },
+    ElemDrop(Option<String>),
+    AtomicStore(AtomicAccess, Expression, Expression, u64),
+    AtomicFence,
+    SimdStore(SimdAccess, Expression, Expression, u64),
+    Block(Vec<Statement>, String, Vec<String>),
+    Loop(Vec<Statement>, String, Vec<String>),
+    If {
+        cond: Expression,
+        then_stmts: Vec<Statement>,
+        else_stmts: Vec<Statement>,
+        /// Label of the block, if branched to.
+        label: Option<String>,
+    },
     BrIf {
         cond: Expression,
-        block_depth: u32,
-        relative_depth: u32
+        label: String,
+        value: Option<Expression>,
     },
     Br {
-        block_depth: u32,
-        relative_depth: u32
+        label: String,
+        value: Option<Expression>,
     },
     BrTable {
         cond: Expression,
-        stack: Vec<LevelKind>,
+        stack: Vec<Level>,
         table: Vec<u32>,
         default: u32,
+        value: Option<Expression>,
     },
     Continue {
-        block_depth: u32,
-        relative_depth: u32,
+        label: String,
+        params: Vec<String>,
+        value: Option<Expression>,
     },
     ContinueIf {
         cond: Expression,
-        block_depth: u32,
-        relative_depth: u32
+        label: String,
+        params: Vec<String>,
+        value: Option<Expression>,
     },
+    Try {
+        body: Vec<Statement>,
+        depth: u32,
+        label: String,
+        results: Vec<String>,
+        escapes: Vec<Escape>,
+        catches: Vec<Catch>,
+        catch_all: Option<Vec<Statement>>,
+        /// Rethrows exceptions from the body instead of handling them.
+        delegate: Option<Box<Statement>>,
+    },
+    /// Return from the body of the `try` at `depth` to branch outside of it.
+    Escape {
+        depth: u32,
+        code: u32,
+        variable: Option<String>,
+        value: Option<Expression>,
+    },
+    Throw {
+        tag: u32,
+        types: Vec<WASMType>,
+        values: Vec<Expression>,
+    },
+    Rethrow(String),
+    /// Resume unwinding with a caught panic payload.
+    Resume(Expression),
     RawRust(Vec<String>),
 }
 
+/// Handler of a `catch` clause, with the variables bound to the values of
+/// the caught exception.
+#[derive(Clone, Debug)]
+pub struct Catch {
+    pub tag: u32,
+    pub types: Vec<WASMType>,
+    pub names: Vec<String>,
+    pub statements: Vec<Statement>,
+}
+
+/// Branch or delegation leaving the body of a `try`, identified by the code
+/// its closure returns, and the statement repeating it outside the closure.
+#[derive(Clone, Debug)]
+pub struct Escape {
+    pub code: u32,
+    pub variable: Option<String>,
+    pub dispatch: Statement,
+}
+
 // add support for indentation
 // handle contextual meaning for br, br_if, and br_table between loop and block (continue vs break)
 
 pub const INDENTATION: usize = 4;
 
 impl Statement {
-    pub fn emit_code(&self, indentation: usize) -> Vec<String> {
+    pub fn emit_code(&self, indentation: usize, options: &EmitOptions) -> Vec<String> {
+        if let Some(store) = self.emit_sandboxed_store(options) {
+            return vec![format!("{:indentation$}{store};", " ")]
+        }
+
         let mut lines = vec![];
         match self {
-            Self::LocalSet(index, expr) => lines.push(format!("{:indentation$}{} = {};", " ", index, expr.emit_code())),
+            Self::LocalSet(index, expr) => lines.push(format!("{:indentation$}{} = {};", " ", index, expr.emit_code(options))),
             Self::Return(expr) => {
                 match expr {
-                    Some(e) => lines.push(format!("{:indentation$}return {};", " ", e.emit_code())),
+                    Some(e) if options.safe => lines.push(format!("{:indentation$}return Ok({});", " ", e.emit_code(options))),
+                    Some(e) => lines.push(format!("{:indentation$}return {};", " ", e.emit_code(options))),
+                    None if options.safe => lines.push(format!("{:indentation$}return Ok(());", " ")),
                     None => lines.push(format!("{:indentation$}return;", " ")),
                 }
             }
-            Self::Unreachable => lines.push(format!("{:indentation$}unreachable!();", " ")),
-            Self::Nop => lines.push(format!("{:indentation$};", " ")),
-            Self::GlobalSet(index, expr) => lines.push(format!("{:indentation$}{} = {};", " ", index, expr.emit_code())),
-            Self::Unassigned(expr) => lines.push(format!("{:indentation$}{}", " ", expr.emit_code())),
+            Self::Unreachable => if options.trapping {
+                lines.push(format!("{:indentation$}{};", " ", options.trap("Unreachable")))
+            } else {
+                lines.push(format!("{:indentation$}unreachable!();", " "))
+            },
+            Self::Nop => {},
+            Self::GlobalSet(index, expr) => lines.push(format!("{:indentation$}{} = {};", " ", options.state(index), expr.emit_code(options))),
+            Self::Unassigned(expr) if options.safe => lines.push(format!("{:indentation$}Ok({})", " ", expr.emit_code(options))),
+            Self::Unassigned(expr) => lines.push(format!("{:indentation$}{}", " ", expr.emit_code(options))),
+            Self::Call(expr) => lines.push(format!("{:indentation$}{};", " ", expr.emit_code(options))),
+            Self::Let { names, value, mutable } => {
+                let names = names.iter().map(|name| if *mutable { format!("mut {name}") } else { name.clone() }).collect::<Vec<_>>();
+                lines.push(format!("{:indentation$}let {} = {};", " ", pattern(&names), value.emit_code(options)))
+            },
             Self::I32Store(ptr_expr, value_expr, align, offset) => {
                 let method = if *align == 2 {
                     "write"
                 } else {
                     "write_unaligned"
                 };
-                let ptr_code = ptr_expr.emit_code();
-                let value_code = value_expr.emit_code();
+                let ptr_code = ptr_expr.emit_code(options);
+                let value_code = value_expr.emit_code(options);
                 if *offset == 0 {
                     lines.push(format!("{:indentation$}({ptr_code} as *mut i32).{method}({value_code});", " "))
                 } else {
@@ -88,8 +208,8 @@ impl Statement {
                 } else {
                     "write_unaligned"
                 };
-                let ptr_code = ptr_expr.emit_code();
-                let value_code = value_expr.emit_code();
+                let ptr_code = ptr_expr.emit_code(options);
+                let value_code = value_expr.emit_code(options);
                 if *offset == 0 {
                     lines.push(format!("{:indentation$}({ptr_code} as *mut i64).{method}({value_code});", " "))
                 } else {
@@ -102,8 +222,8 @@ impl Statement {
                 } else {
                     "write_unaligned"
                 };
-                let ptr_code = ptr_expr.emit_code();
-                let value_code = value_expr.emit_code();
+                let ptr_code = ptr_expr.emit_code(options);
+                let value_code = value_expr.emit_code(options);
                 if *offset == 0 {
                     lines.push(format!("{:indentation$}({ptr_code} as *mut f32).{method}({value_code});", " "))
                 } else {
@@ -116,8 +236,8 @@ impl Statement {
                 } else {
                     "write_unaligned"
                 };
-                let ptr_code = ptr_expr.emit_code();
-                let value_code = value_expr.emit_code();
+                let ptr_code = ptr_expr.emit_code(options);
+                let value_code = value_expr.emit_code(options);
                 if *offset == 0 {
                     lines.push(format!("{:indentation$}({ptr_code} as *mut f64).{method}({value_code});", " "))
                 } else {
@@ -126,8 +246,8 @@ impl Statement {
             },
             Self::I32Store8(ptr_expr, value_expr, _, offset) => {
                 let method = "write";
-                let ptr_code = ptr_expr.emit_code();
-                let value_code = value_expr.emit_code();
+                let ptr_code = ptr_expr.emit_code(options);
+                let value_code = format!("{} as i8", value_expr.emit_code(options));
                 if *offset == 0 {
                     lines.push(format!("{:indentation$}({ptr_code} as *mut i8).{method}({value_code});", " "))
                 } else {
@@ -140,8 +260,8 @@ impl Statement {
                 } else {
                     "write_unaligned"
                 };
-                let ptr_code = ptr_expr.emit_code();
-                let value_code = value_expr.emit_code();
+                let ptr_code = ptr_expr.emit_code(options);
+                let value_code = format!("{} as i16", value_expr.emit_code(options));
                 if *offset == 0 {
                     lines.push(format!("{:indentation$}({ptr_code} as *mut i16).{method}({value_code});", " "))
                 } else {
@@ -150,8 +270,8 @@ impl Statement {
             },
             Self::I64Store8(ptr_expr, value_expr, _, offset) => {
                 let method = "write";
-                let ptr_code = ptr_expr.emit_code();
-                let value_code = value_expr.emit_code();
+                let ptr_code = ptr_expr.emit_code(options);
+                let value_code = format!("{} as i8", value_expr.emit_code(options));
                 if *offset == 0 {
                     lines.push(format!("{:indentation$}({ptr_code} as *mut i8).{method}({value_code});", " "))
                 } else {
@@ -164,8 +284,8 @@ impl Statement {
                 } else {
                     "write_unaligned"
                 };
-                let ptr_code = ptr_expr.emit_code();
-                let value_code = value_expr.emit_code();
+                let ptr_code = ptr_expr.emit_code(options);
+                let value_code = format!("{} as i16", value_expr.emit_code(options));
                 if *offset == 0 {
                     lines.push(format!("{:indentation$}({ptr_code} as *mut i16).{method}({value_code});", " "))
                 } else {
@@ -178,8 +298,8 @@ impl Statement {
                 } else {
                     "write_unaligned"
                 };
-                let ptr_code = ptr_expr.emit_code();
-                let value_code = value_expr.emit_code();
+                let ptr_code = ptr_expr.emit_code(options);
+                let value_code = format!("{} as i32", value_expr.emit_code(options));
                 if *offset == 0 {
                     lines.push(format!("{:indentation$}({ptr_code} as *mut i32).{method}({value_code});", " "))
                 } else {
@@ -190,68 +310,291 @@ impl Statement {
                 lines.push(format!(
                     "{:indentation$}drop({});",
                     " ",
-                    expr.emit_code(),
+                    expr.emit_code(options),
                 ))
             },
-            Self::Block(stmts, depth) => {
-                lines.push(format!("{:indentation$}'B{depth}: loop {{", " "));
-                
-                for stmt in stmts.iter() {
-                    lines.extend(stmt.emit_code(indentation + INDENTATION));
+            Self::Block(stmts, label, results) | Self::Loop(stmts, label, results) => {
+                lines.extend(emit_block(stmts, label, results, indentation, options));
+            },
+            Self::If { cond, then_stmts, else_stmts, label } => {
+                let inner = if let Some(label) = label {
+                    lines.push(format!("{:indentation$}'{label}: loop {{", " "));
+                    indentation + INDENTATION
+                } else {
+                    indentation
+                };
+
+                lines.push(format!("{:inner$}if {} != 0 {{", " ", cond.emit_code(options)));
+
+                for stmt in then_stmts.iter() {
+                    lines.extend(stmt.emit_code(inner + INDENTATION, options));
                 }
 
-                lines.push(format!("{:indentation$}break;", " ", indentation=indentation+INDENTATION));
-                lines.push(format!("{:indentation$}}};", " "));
-            },
-            Self::Loop(stmts, depth) => {
-                lines.push(format!("{:indentation$}'B{depth}: loop {{", " "));
-                
-                for stmt in stmts.iter() {
-                    lines.extend(stmt.emit_code(indentation + INDENTATION));
+                if !else_stmts.is_empty() {
+                    lines.push(format!("{:inner$}}} else {{", " "));
+
+                    for stmt in else_stmts.iter() {
+                        lines.extend(stmt.emit_code(inner + INDENTATION, options));
+                    }
                 }
 
-                lines.push(format!("{:indentation$}}};", " "));
+                lines.push(format!("{:inner$}}}", " "));
+
+                if label.is_some() {
+                    lines.push(format!("{:indentation$}break;", " ", indentation=inner));
+                    lines.push(format!("{:indentation$}}};", " "));
+                }
             },
-            Self::BrIf { cond, block_depth, relative_depth } => {
-                lines.push(format!("{:indentation$}if {} != 0 {{ break 'B{} }}", " ", cond.emit_code(), block_depth - relative_depth))
+            Self::BrIf { cond, label, value } => {
+                lines.push(format!("{:indentation$}if {} != 0 {{ {} }}", " ", cond.emit_code(options), emit_break(label, value, options)))
             },
-            Self::Br { block_depth, relative_depth } => {
-                lines.push(format!("{:indentation$}break 'B{};", " ", block_depth - relative_depth))
+            Self::Br { label, value } => {
+                lines.push(format!("{:indentation$}{};", " ", emit_break(label, value, options)))
             },
-            Self::BrTable { cond, stack, table, default } => {
-                lines.push(format!("{:indentation$}match {} {{", " ", cond.emit_code()));
-
-                for (i, relative_depth) in table.iter().enumerate() {
-                    let instruction = {
-                        let block_depth = stack.len() - 1;
-                        match stack.get(block_depth - *relative_depth as usize) {
-                            Some(LevelKind::Block) => "break",
-                            Some(LevelKind::Loop) => "continue",
-                            None => unreachable!()
-                        }
-                    };
-                    lines.push(format!("{:indentation$}{i} => {instruction} 'B{},", " ", (stack.len() - 1) as u32 - relative_depth))
-                }
+            Self::BrTable { cond, stack, table, default, value } => {
+                lines.push(format!("{:indentation$}match {} {{", " ", cond.emit_code(options)));
 
-                let instruction = {
-                    let block_depth = stack.len() - 1;
-                    match stack.get(block_depth - *default as usize) {
-                        Some(LevelKind::Block) => "break",
-                        Some(LevelKind::Loop) => "continue",
+                let instruction = |relative_depth: u32| {
+                    let depth = (stack.len() - 1) as u32 - relative_depth;
+                    match stack.get(depth as usize) {
+                        Some(Level { arity, .. }) if escaping_try(stack, depth).is_some() => {
+                            let try_depth = escaping_try(stack, depth).unwrap();
+                            emit_escape(depth, &(*arity > 0).then(|| escape_variable(try_depth, depth)), value, options)
+                        },
+                        Some(Level { kind: LevelKind::Function(_), .. }) => match value {
+                            Some(value) if options.safe => format!("return Ok({})", value.emit_code(options)),
+                            Some(value) => format!("return {}", value.emit_code(options)),
+                            None if options.safe => "return Ok(())".to_string(),
+                            None => "return".to_string(),
+                        },
+                        Some(Level { kind: LevelKind::Block | LevelKind::If | LevelKind::Try | LevelKind::Catch, label, .. }) => emit_break(label, value, options),
+                        Some(Level { kind: LevelKind::Loop, params, label, .. }) => emit_continue(label, params, value, options),
                         None => unreachable!()
                     }
                 };
 
-                lines.push(format!("{:indentation$}_ => {instruction} 'B{},", " ", (stack.len() - 1) as u32 - default));
+                for (i, relative_depth) in table.iter().enumerate() {
+                    lines.push(format!("{:indentation$}{i} => {},", " ", instruction(*relative_depth)))
+                }
+
+                lines.push(format!("{:indentation$}_ => {},", " ", instruction(*default)));
 
                 lines.push(format!("{:indentation$}}}", " "));
             },
-            Self::Continue { block_depth, relative_depth } => {
-                lines.push(format!("{:indentation$}continue 'B{}", " ", block_depth - relative_depth));
+            Self::Continue { label, params, value } => {
+                lines.push(format!("{:indentation$}{};", " ", emit_continue(label, params, value, options)));
             },
-            Self::ContinueIf { cond, block_depth, relative_depth } => {
-                lines.push(format!("{:indentation$}if {} != 0 {{ continue 'B{} }}", " ", cond.emit_code(), block_depth - relative_depth));
+            Self::ContinueIf { cond, label, params, value } => {
+                lines.push(format!("{:indentation$}if {} != 0 {{ {} }}", " ", cond.emit_code(options), emit_continue(label, params, value, options)));
             },
+            Self::MemoryCopy { dst, src, len, memory64 } => match (sandboxed_address(dst, options), sandboxed_address(src, options)) {
+                ((Some(dst_memory), dst), (Some(src_memory), src)) => lines.push(format!(
+                    "{:indentation$}{};",
+                    " ",
+                    dst_memory.emit_copy(&dst.emit_code(options), src_memory, &src.emit_code(options), &len.emit_code(options), options),
+                )),
+                // between the native memory and an emulated one
+                ((Some(dst_memory), dst), (None, src)) => lines.push(format!(
+                    "{:indentation$}{{ let (d, s, n) = ({}, {}, {}); __w2r_native_read(s as u32 as usize, {}) }};",
+                    " ",
+                    dst.emit_code(options),
+                    src.emit_code(options),
+                    len.emit_code(options),
+                    dst_memory.emit_slice("d", "n", options),
+                )),
+                ((None, dst), (Some(src_memory), src)) => lines.push(format!(
+                    "{:indentation$}{{ let (d, s, n) = ({}, {}, {}); __w2r_native_write(d as u32 as usize, {}) }};",
+                    " ",
+                    dst.emit_code(options),
+                    src.emit_code(options),
+                    len.emit_code(options),
+                    src_memory.emit_slice("s", "n", options),
+                )),
+                _ => lines.push(format!(
+                    "{:indentation$}{{ let (d, s, n) = ({}, {}, {}); __w2r_native_copy(d as u32 as usize, s as u32 as usize, n {}) }};",
+                    " ",
+                    dst.emit_code(options),
+                    src.emit_code(options),
+                    len.emit_code(options),
+                    length_cast(*memory64),
+                )),
+            },
+            Self::MemoryFill { dst, value, len, memory64 } => match sandboxed_address(dst, options) {
+                (Some(memory), dst) => lines.push(format!(
+                    "{:indentation$}{{ let (d, v, n) = ({}, {}, {}); {}.fill(v as u8) }};",
+                    " ",
+                    dst.emit_code(options),
+                    value.emit_code(options),
+                    len.emit_code(options),
+                    memory.emit_slice("d", "n", options),
+                )),
+                (None, dst) => lines.push(format!(
+                    "{:indentation$}{{ let (d, v, n) = ({}, {}, {}); __w2r_native_fill(d as u32 as usize, v as u8, n {}) }};",
+                    " ",
+                    dst.emit_code(options),
+                    value.emit_code(options),
+                    len.emit_code(options),
+                    length_cast(*memory64),
+                )),
+            },
+            Self::MemoryInit { segment, dst, src, len } => {
+                let out_of_bounds = if options.trapping {
+                    options.trap("MemoryOutOfBounds")
+                } else {
+                    "panic!(\"out of bounds memory access\")".to_string()
+                };
+                let (memory, dst) = sandboxed_address(dst, options);
+                let copy = match memory {
+                    Some(memory) => format!("{}.copy_from_slice(bytes)", memory.emit_slice("d", "bytes.len()", options)),
+                    None => "__w2r_native_write(d as u32 as usize, bytes)".to_string(),
+                };
+                lines.push(format!(
+                    "{:indentation$}{{ let (d, s, n) = ({}, {}, {}); let segment: &[u8] = {}; match segment.get(s as u32 as usize..).and_then(|rest| rest.get(..n as u32 as usize)) {{ Some(bytes) => {copy}, None => {out_of_bounds} }} }};",
+                    " ",
+                    dst.emit_code(options),
+                    src.emit_code(options),
+                    len.emit_code(options),
+                    segment.as_ref().map_or("&[]".to_string(), |segment| options.state(segment)),
+                ))
+            },
+            Self::DataDrop(segment) => {
+                if let Some(segment) = segment {
+                    lines.push(format!("{:indentation$}{} = &[];", " ", options.state(segment)))
+                }
+            },
+            Self::TableSet(table, index, value) => lines.push(format!(
+                "{:indentation$}{{ let (i, v) = ({}, {}); match {}.to_mut().get_mut(i as u32 as usize) {{ Some(slot) => *slot = v, None => {} }} }};",
+                " ",
+                index.emit_code(options),
+                value.emit_code(options),
+                options.state_mut(table),
+                table_out_of_bounds(options),
+            )),
+            Self::TableFill(table, dst, value, len) => lines.push(format!(
+                "{:indentation$}{{ let (d, v, n) = ({}, {}, {}); match {}.to_mut().get_mut(d as u32 as usize..).and_then(|rest| rest.get_mut(..n as u32 as usize)) {{ Some(slots) => slots.fill(v), None => {} }} }};",
+                " ",
+                dst.emit_code(options),
+                value.emit_code(options),
+                len.emit_code(options),
+                options.state_mut(table),
+                table_out_of_bounds(options),
+            )),
+            Self::TableCopy { dst_table, src_table, dst, src, len } => lines.push(format!(
+                "{:indentation$}{{ let (d, s, n) = ({}, {}, {}); let items = {}.get(s as u32 as usize..).and_then(|rest| rest.get(..n as u32 as usize)).map(|items| items.to_vec()); match (items, {}.to_mut().get_mut(d as u32 as usize..).and_then(|rest| rest.get_mut(..n as u32 as usize))) {{ (Some(items), Some(slots)) => slots.copy_from_slice(&items), _ => {} }} }};",
+                " ",
+                dst.emit_code(options),
+                src.emit_code(options),
+                len.emit_code(options),
+                options.state_ref(src_table),
+                options.state_mut(dst_table),
+                table_out_of_bounds(options),
+            )),
+            Self::TableInit { table, ty, segment, dst, src, len } => lines.push(format!(
+                "{:indentation$}{{ let (d, s, n) = ({}, {}, {}); let segment: &[{ty}] = {}; match (segment.get(s as u32 as usize..).and_then(|rest| rest.get(..n as u32 as usize)), {}.to_mut().get_mut(d as u32 as usize..).and_then(|rest| rest.get_mut(..n as u32 as usize))) {{ (Some(items), Some(slots)) => slots.copy_from_slice(items), _ => {} }} }};",
+                " ",
+                dst.emit_code(options),
+                src.emit_code(options),
+                len.emit_code(options),
+                // the segments of an instance are vectors
+                match segment {
+                    Some(segment) if options.instance => format!("&{}", options.state(segment)),
+                    Some(segment) => segment.clone(),
+                    None => "&[]".to_string(),
+                },
+                options.state_mut(table),
+                table_out_of_bounds(options),
+            )),
+            Self::ElemDrop(segment) => {
+                if let Some(segment) = segment {
+                    let empty = if options.instance { "Vec::new()" } else { "&[]" };
+                    lines.push(format!("{:indentation$}{} = {empty};", " ", options.state(segment)))
+                }
+            },
+            Self::AtomicStore(access, addr, value, offset) => match sandboxed_address(addr, options) {
+                (Some(memory), addr) if options.safe => lines.push(format!(
+                    "{:indentation$}{{ let (a, v) = ({}, {} as {}); *{} = v.to_le_bytes(); }}",
+                    " ",
+                    addr.emit_code(options),
+                    value.emit_code(options),
+                    access.int_type(),
+                    access.emit_bytes("a", *offset, memory),
+                )),
+                (memory, addr) => lines.push(format!(
+                    "{:indentation$}{}.store({} as {}, ::std::sync::atomic::Ordering::SeqCst);",
+                    " ",
+                    access.emit_atomic(&addr.emit_code(options), *offset, memory, options),
+                    value.emit_code(options),
+                    access.int_type(),
+                )),
+            },
+            Self::AtomicFence => lines.push(format!("{:indentation$}::std::sync::atomic::fence(::std::sync::atomic::Ordering::SeqCst);", " ")),
+            Self::SimdStore(access, addr, value, offset) => {
+                let (memory, addr) = sandboxed_address(addr, options);
+                match access.lane {
+                    Some(lane) => lines.push(format!(
+                        "{:indentation$}{{ let (a, v) = ({}, {}); ::std::arch::wasm32::{}{}(v, {}) }};",
+                        " ",
+                        addr.emit_code(options),
+                        value.emit_code(options),
+                        access.name,
+                        emit_lanes(&[lane]),
+                        emit_address("a", *offset, access.pointee, memory, options),
+                    )),
+                    None => lines.push(format!(
+                        "{:indentation$}::std::arch::wasm32::{}({}, {});",
+                        " ",
+                        access.name,
+                        emit_address(&addr.emit_code(options), *offset, access.pointee, memory, options),
+                        value.emit_code(options),
+                    )),
+                }
+            },
+            Self::Try { body, depth, label, results, escapes, catches, catch_all, delegate } => {
+                // the body runs in a closure; branches out of it return an
+                // escape code and are repeated once `catch_unwind` is done
+                let inner = indentation + INDENTATION;
+                if results.is_empty() {
+                    lines.push(format!("{:indentation$}'{label}: loop {{", " "));
+                } else {
+                    lines.push(format!("{:indentation$}let {} = '{label}: loop {{", " ", pattern(results)));
+                }
+                for variable in escapes.iter().filter_map(|escape| escape.variable.as_ref()) {
+                    lines.push(format!("{:inner$}let mut {variable} = None;", " "));
+                }
+                lines.push(format!("{:inner$}match ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {{", " "));
+                lines.extend(emit_block(body, label, results, inner + INDENTATION, options));
+                let value = if results.is_empty() { "()".to_string() } else { pattern(results) };
+                lines.push(format!("{:indentation$}Ok::<_, u32>({value})", " ", indentation=inner+INDENTATION));
+                lines.push(format!("{:inner$}}})) {{", " "));
+                if results.is_empty() {
+                    lines.push(format!("{:indentation$}Ok(Ok(())) => break '{label},", " ", indentation=inner+INDENTATION));
+                } else {
+                    lines.push(format!("{:indentation$}Ok(Ok({value})) => break '{label} {value},", " ", indentation=inner+INDENTATION));
+                }
+                for escape in escapes.iter() {
+                    lines.push(format!("{:indentation$}Ok(Err({})) => {{", " ", escape.code, indentation=inner+INDENTATION));
+                    lines.extend(escape.dispatch.emit_code(inner + 2 * INDENTATION, options));
+                    lines.push(format!("{:indentation$}}},", " ", indentation=inner+INDENTATION));
+                }
+                lines.push(format!("{:indentation$}Ok(Err(_)) => unreachable!(),", " ", indentation=inner+INDENTATION));
+                lines.extend(emit_handlers(*depth, label, results, catches, catch_all, delegate, inner + INDENTATION, options));
+                lines.push(format!("{:inner$}}}", " "));
+                lines.push(format!("{:indentation$}}};", " "));
+            },
+            Self::Escape { code, variable, value, .. } => {
+                lines.push(format!("{:indentation$}{};", " ", emit_escape(*code, variable, value, options)))
+            },
+            Self::Throw { tag, types, values } => {
+                let values = values.iter().map(|value| value.emit_code(options)).collect::<Vec<_>>();
+                lines.push(format!(
+                    "{:indentation$}::std::panic::resume_unwind(Box::new(__W2RException {{ tag: {tag}, values: ::std::sync::Arc::<{}>::new({}) }}));",
+                    " ",
+                    tuple_type(types),
+                    tuple(&values),
+                ))
+            },
+            Self::Rethrow(exception) => lines.push(format!("{:indentation$}::std::panic::resume_unwind({exception}.clone());", " ")),
+            Self::Resume(payload) => lines.push(format!("{:indentation$}::std::panic::resume_unwind({});", " ", payload.emit_code(options))),
             Self::RawRust(raw_lines) => {
                 for raw_line in raw_lines.iter() {
                     lines.push(format!("{:indentation$}{}", " ", raw_line));
@@ -260,5 +603,145 @@ impl Statement {
         };
         lines
     }
+
+    /// Store through a sandboxed memory, if this is a store and the backend
+    /// is sandboxed: the value is wrapped to the stored type.
+    fn emit_sandboxed_store(&self, options: &EmitOptions) -> Option<String> {
+        let (addr, value, offset, stored) = match self {
+            Self::I32Store(addr, value, _, offset) => (addr, value, offset, "i32"),
+            Self::I64Store(addr, value, _, offset) => (addr, value, offset, "i64"),
+            Self::F32Store(addr, value, _, offset) => (addr, value, offset, "f32"),
+            Self::F64Store(addr, value, _, offset) => (addr, value, offset, "f64"),
+            Self::I32Store8(addr, value, _, offset) | Self::I64Store8(addr, value, _, offset) => (addr, value, offset, "u8"),
+            Self::I32Store16(addr, value, _, offset) | Self::I64Store16(addr, value, _, offset) => (addr, value, offset, "u16"),
+            Self::I64Store32(addr, value, _, offset) => (addr, value, offset, "u32"),
+            _ => return None,
+        };
+        let (Some(memory), addr) = sandboxed_address(addr, options) else {
+            return None
+        };
+        Some(memory.emit_store(&addr.emit_code(options), *offset, &value.emit_code(options), stored, options))
+    }
+}
+
+
+fn length_cast(memory64: bool) -> &'static str {
+    if memory64 { "as u64 as usize" } else { "as u32 as usize" }
+}
+
+fn pattern(names: &[String]) -> String {
+    match names {
+        [name] => name.clone(),
+        _ => format!("({})", names.join(", ")),
+    }
 }
 
+/// Tuple expression or pattern, with a trailing comma for a single element.
+fn tuple(items: &[String]) -> String {
+    match items {
+        [item] => format!("({item},)"),
+        _ => format!("({})", items.join(", ")),
+    }
+}
+
+fn emit_block(stmts: &[Statement], label: &str, results: &[String], indentation: usize, options: &EmitOptions) -> Vec<String> {
+    let mut lines = vec![];
+
+    // blocks with results end in an explicit `break` carrying the values
+    if results.is_empty() {
+        lines.push(format!("{:indentation$}'{label}: loop {{", " "));
+    } else {
+        lines.push(format!("{:indentation$}let {} = '{label}: loop {{", " ", pattern(results)));
+    }
+
+    for stmt in stmts.iter() {
+        lines.extend(stmt.emit_code(indentation + INDENTATION, options));
+    }
+
+    if results.is_empty() {
+        lines.push(format!("{:indentation$}break;", " ", indentation=indentation+INDENTATION));
+    }
+    lines.push(format!("{:indentation$}}};", " "));
+
+    lines
+}
+
+/// Match arm handling the panic payload caught from the body of the `try` at
+/// `depth`. Only WASM exceptions are caught; traps and other panics resume.
+#[allow(clippy::too_many_arguments)]
+fn emit_handlers(depth: u32, label: &str, results: &[String], catches: &[Catch], catch_all: &Option<Vec<Statement>>, delegate: &Option<Box<Statement>>, indentation: usize, options: &EmitOptions) -> Vec<String> {
+    let mut lines = vec![];
+    let inner = indentation + INDENTATION;
+
+    if let Some(delegate) = delegate {
+        lines.push(format!("{:indentation$}Err(payload) => {{", " "));
+        lines.extend(delegate.emit_code(inner, options));
+        lines.push(format!("{:indentation$}}},", " "));
+        return lines
+    }
+    if catches.is_empty() && catch_all.is_none() {
+        lines.push(format!("{:indentation$}Err(payload) => ::std::panic::resume_unwind(payload),", " "));
+        return lines
+    }
+
+    let exception = exception_variable(depth);
+    let handler = |lines: &mut Vec<String>, statements: &[Statement]| {
+        for stmt in statements.iter() {
+            lines.extend(stmt.emit_code(inner + INDENTATION, options));
+        }
+        if results.is_empty() {
+            lines.push(format!("{:indentation$}break '{label};", " ", indentation=inner+INDENTATION));
+        }
+    };
+
+    lines.push(format!("{:indentation$}Err(payload) => match payload.downcast::<__W2RException>() {{", " "));
+    for catch in catches.iter() {
+        lines.push(format!("{:inner$}Ok({exception}) if {exception}.tag == {} => {{", " ", catch.tag));
+        if !catch.names.is_empty() {
+            lines.push(format!(
+                "{:indentation$}let {} = *{exception}.values.downcast_ref::<{}>().unwrap();",
+                " ",
+                tuple(&catch.names),
+                tuple_type(&catch.types),
+                indentation=inner+INDENTATION,
+            ));
+        }
+        handler(&mut lines, &catch.statements);
+        lines.push(format!("{:inner$}}},", " "));
+    }
+    match catch_all {
+        Some(statements) => {
+            lines.push(format!("{:inner$}Ok({exception}) => {{", " "));
+            handler(&mut lines, statements);
+            lines.push(format!("{:inner$}}},", " "));
+        },
+        None => lines.push(format!("{:inner$}Ok({exception}) => ::std::panic::resume_unwind({exception}),", " ")),
+    }
+    lines.push(format!("{:inner$}Err(payload) => ::std::panic::resume_unwind(payload),", " "));
+    lines.push(format!("{:indentation$}}},", " "));
+
+    lines
+}
+
+/// Return from the closure of a `try` body with the escape `code`, after
+/// storing the carried values in `variable`.
+fn emit_escape(code: u32, variable: &Option<String>, value: &Option<Expression>, options: &EmitOptions) -> String {
+    match (variable, value) {
+        (Some(variable), Some(value)) => format!("{{ {variable} = Some({}); return Err({code}) }}", value.emit_code(options)),
+        _ => format!("return Err({code})"),
+    }
+}
+
+fn emit_break(label: &str, value: &Option<Expression>, options: &EmitOptions) -> String {
+    match value {
+        Some(value) => format!("break '{label} {}", value.emit_code(options)),
+        None => format!("break '{label}"),
+    }
+}
+
+fn emit_continue(label: &str, params: &[String], value: &Option<Expression>, options: &EmitOptions) -> String {
+    match value {
+        Some(value) => format!("{{ {} = {}; continue '{label} }}", pattern(params), value.emit_code(options)),
+        None => format!("continue '{label}"),
+    }
+}
@@ -0,0 +1,77 @@
+use crate::expression::Expression;
+use crate::options::EmitOptions;
+use crate::statement::INDENTATION;
+use crate::wasm_type::WASMType;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub index: u32,
+    pub ty: WASMType,
+    pub initial: u32,
+    pub maximum: Option<u32>,
+    /// Reference stored in each slot.
+    pub elements: Vec<Expression>,
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "__w2r_table{}", self.index)
+    }
+}
+
+impl Table {
+    pub fn new(index: u32, ty: wasmparser::TableType) -> Self {
+        Self {
+            index,
+            ty: ty.element_type.into(),
+            initial: ty.initial,
+            maximum: ty.maximum,
+            elements: vec![Expression::RefNull; ty.initial as usize],
+        }
+    }
+
+    /// Store `elements` starting at slot `offset`, growing the table if the
+    /// segment does not fit.
+    pub fn init(&mut self, offset: usize, elements: &[Expression]) {
+        if self.elements.len() < offset + elements.len() {
+            self.elements.resize(offset + elements.len(), Expression::RefNull);
+        }
+        self.elements[offset..offset + elements.len()].clone_from_slice(elements);
+    }
+
+    /// Tables start out borrowing their initial contents and are copied into
+    /// a `Vec` the first time they are written to or grown.
+    pub fn emit_code(&self, options: &EmitOptions) -> Vec<String> {
+        let mut lines = vec!["".to_string()];
+
+        lines.push(format!("static mut {self}: ::std::borrow::Cow<'static, [{}]> = ::std::borrow::Cow::Borrowed(&[", self.ty));
+
+        for element in self.elements.iter() {
+            lines.push(format!("{:INDENTATION$}{},", " ", element.emit_code(options)));
+        }
+
+        lines.push("]);".to_string());
+
+        lines
+    }
+
+    /// Field of an instance holding this table.
+    pub fn emit_field(&self) -> String {
+        format!("{self}: ::std::borrow::Cow<'static, [{}]>", self.ty)
+    }
+
+    /// Initializer of the field of an instance, which owns its copy.
+    pub fn emit_owned(&self, options: &EmitOptions) -> Vec<String> {
+        let mut lines = vec![format!("{self}: ::std::borrow::Cow::Owned(vec![")];
+
+        for element in self.elements.iter() {
+            lines.push(format!("{:INDENTATION$}{},", " ", element.emit_code(options)));
+        }
+
+        lines.push("])".to_string());
+
+        lines
+    }
+}
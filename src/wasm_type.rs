@@ -1,12 +1,29 @@
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum WASMType {
     I32,
     I64,
     F32,
     F64,
+    V128,
+    FuncRef,
+    ExternRef,
+}
+
+impl WASMType {
+    /// Rust literal for the default value of a local of this type.
+    pub fn zero(&self) -> &'static str {
+        match self {
+            Self::I32 => "0i32",
+            Self::I64 => "0i64",
+            Self::F32 => "0f32",
+            Self::F64 => "0f64",
+            Self::V128 => "::std::arch::wasm32::u64x2(0, 0)",
+            Self::FuncRef | Self::ExternRef => "None",
+        }
+    }
 }
 
 impl Display for WASMType {
@@ -16,6 +33,9 @@ impl Display for WASMType {
             Self::I64 => "i64",
             Self::F32 => "f32",
             Self::F64 => "f64",
+            Self::V128 => "::std::arch::wasm32::v128",
+            Self::FuncRef => "Option<__W2RFuncRef>",
+            Self::ExternRef => "Option<__W2RExternRef>",
         })
     }
 }
@@ -27,7 +47,9 @@ impl From<wasmparser::Type> for WASMType {
             wasmparser::Type::I64 => Self::I64,
             wasmparser::Type::F32 => Self::F32,
             wasmparser::Type::F64 => Self::F64,
-            _ => unimplemented!()
+            wasmparser::Type::V128 => Self::V128,
+            wasmparser::Type::FuncRef => Self::FuncRef,
+            wasmparser::Type::ExternRef => Self::ExternRef,
         }
     }
 }
@@ -39,7 +61,9 @@ impl From<&wasmparser::Type> for WASMType {
             wasmparser::Type::I64 => Self::I64,
             wasmparser::Type::F32 => Self::F32,
             wasmparser::Type::F64 => Self::F64,
-            _ => unimplemented!()
+            wasmparser::Type::V128 => Self::V128,
+            wasmparser::Type::FuncRef => Self::FuncRef,
+            wasmparser::Type::ExternRef => Self::ExternRef,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,202 @@
+//! End-to-end tests: each fixture in `tests/golden` is assembled from WAT,
+//! decompiled, compiled together with a driver and run. A driver
+//! `<fixture>_<mode>.rs` is run against the code decompiled with the options of
+//! its mode, and what it prints must match `<fixture>_<mode>.out`. Code for
+//! the native memory of wasm32 is instead recompiled to wasm32 and its exports
+//! must return what those of the fixture return.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wasm2rs::options::EmitOptions;
+use wasmi::Val;
+
+fn golden_file(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(name)
+}
+
+fn read(name: &str) -> String {
+    fs::read_to_string(golden_file(name)).unwrap_or_else(|err| panic!("Unable to read {name}: {err}"))
+}
+
+fn decompile(fixture: &str, options: &EmitOptions) -> Vec<String> {
+    let wasm = wat::parse_file(golden_file(&format!("{fixture}.wat"))).unwrap();
+    wasm2rs::parser::parse(&wasm).unwrap().emit_code(options).unwrap()
+}
+
+/// Compile the decompiled fixture natively with its driver and compare what
+/// the driver prints with the expected output. Memory 0 must be sandboxed (or
+/// unused) as it is otherwise the linear memory of a wasm32 target.
+fn run_native(fixture: &str, mode: &str, options: EmitOptions) {
+    let test = format!("{fixture}_{mode}");
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&dir).unwrap();
+
+    // the driver brings its own `main`
+    let mut code = decompile(fixture, &options)
+        .into_iter()
+        .filter(|line| line != "#![no_main]")
+        .collect::<Vec<_>>();
+    code.push(read(&format!("{test}.rs")));
+    let source = dir.join(format!("{test}.rs"));
+    let binary = dir.join(&test);
+    fs::write(&source, code.join("\n")).unwrap();
+
+    let compiled = Command::new("rustc")
+        .args(["--edition", "2021", "-A", "warnings", "-o"])
+        .arg(&binary)
+        .arg(&source)
+        .output()
+        .unwrap();
+    assert!(compiled.status.success(), "{test} does not compile:\n{}", String::from_utf8_lossy(&compiled.stderr));
+
+    let output = Command::new(&binary).output().unwrap();
+    assert!(output.status.success(), "{test} failed:\n{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), read(&format!("{test}.out")), "output of {test}");
+}
+
+/// Outcome of each of `calls` in turn on an instance of `wasm`, after
+/// calling `setup` first if the module exports it: the results, or `None` if
+/// the call trapped.
+fn call_exports(wasm: &[u8], calls: &[(&str, &[Val])]) -> Vec<Option<String>> {
+    let engine = wasmi::Engine::default();
+    let module = wasmi::Module::new(&engine, wasm).unwrap();
+    let mut store = wasmi::Store::new(&engine, ());
+    let instance = wasmi::Linker::<()>::new(&engine).instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+
+    if let Some(setup) = instance.get_func(&store, "setup") {
+        setup.call(&mut store, &[], &mut []).unwrap();
+    }
+
+    calls
+        .iter()
+        .map(|(name, params)| {
+            let func = instance.get_func(&store, name).unwrap_or_else(|| panic!("{name} is not exported"));
+            let mut results = func.ty(&store).results().iter().map(|ty| Val::default(*ty)).collect::<Vec<_>>();
+            func.call(&mut store, params, &mut results).ok().map(|_| format!("{name}: {results:?}"))
+        })
+        .collect()
+}
+
+/// Recompile the decompiled fixture to wasm32 and compare what its exports
+/// return for `calls` with what those of the fixture return.
+fn run_wasm32(fixture: &str, options: EmitOptions, calls: &[(&str, &[Val])]) {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join(format!("{fixture}_wasm32.rs"));
+    let binary = dir.join(format!("{fixture}_wasm32.wasm"));
+    fs::write(&source, decompile(fixture, &options).join("\n")).unwrap();
+
+    let compiled = Command::new("rustc")
+        .args(["--edition", "2021", "-A", "warnings", "--target", "wasm32-unknown-unknown", "--crate-type", "cdylib", "-o"])
+        .arg(&binary)
+        .arg(&source)
+        .output()
+        .unwrap();
+    assert!(compiled.status.success(), "{fixture} does not compile:\n{}", String::from_utf8_lossy(&compiled.stderr));
+
+    let original = wat::parse_file(golden_file(&format!("{fixture}.wat"))).unwrap();
+    assert_eq!(call_exports(&fs::read(&binary).unwrap(), calls), call_exports(&original, calls), "results of {fixture}");
+}
+
+/// Build the crate decompiled from the fixture with cargo and compare what
+/// its exports return for `calls` with what those of the fixture return.
+fn run_crate(fixture: &str, options: EmitOptions, calls: &[(&str, &[Val])]) {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden").join(format!("{fixture}_crate"));
+    let _ = fs::remove_dir_all(&dir);
+    let original = wat::parse_file(golden_file(&format!("{fixture}.wat"))).unwrap();
+    for (path, bytes) in wasm2rs::parser::parse(&original).unwrap().emit_crate(fixture, &options).unwrap() {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, bytes).unwrap();
+    }
+
+    // the flags of the crate's `.cargo/config.toml` must not be overridden
+    let built = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["build", "--quiet"])
+        .current_dir(&dir)
+        .env("CARGO_TARGET_DIR", dir.join("target"))
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .output()
+        .unwrap();
+    assert!(built.status.success(), "{fixture} does not build:\n{}", String::from_utf8_lossy(&built.stderr));
+
+    let binary = dir.join("target").join("wasm32-unknown-unknown").join("debug").join(format!("{fixture}.wasm"));
+    assert_eq!(call_exports(&fs::read(binary).unwrap(), calls), call_exports(&original, calls), "results of {fixture}");
+}
+
+#[test]
+fn names() {
+    run_native("names", "free", EmitOptions::default());
+}
+
+#[test]
+fn tables() {
+    run_native("tables", "instance", EmitOptions { instance: true, ..Default::default() });
+    run_native("tables", "safe", EmitOptions { safe: true, ..Default::default() });
+}
+
+#[test]
+fn exports() {
+    run_native("exports", "instance", EmitOptions { instance: true, ..Default::default() });
+}
+
+#[test]
+fn atomics() {
+    run_native("atomics", "safe", EmitOptions { safe: true, ..Default::default() });
+}
+
+#[test]
+fn exceptions() {
+    run_native("exceptions", "free", EmitOptions::default());
+}
+
+#[test]
+fn tail_calls() {
+    run_native("tail_calls", "free", EmitOptions::default());
+}
+
+#[test]
+fn safe_exceptions() {
+    let wasm = wat::parse_str("(module (tag) (func (export \"throw\") throw 0))").unwrap();
+    let context = wasm2rs::parser::parse(&wasm).unwrap();
+    let error = context.emit_code(&EmitOptions { safe: true, ..Default::default() }).unwrap_err();
+    assert_eq!(error.to_string(), "exception handling");
+}
+
+#[test]
+fn memories() {
+    run_native("memories", "free", EmitOptions::default());
+}
+
+#[test]
+fn bulk_memory() {
+    run_wasm32("bulk", EmitOptions::default(), &[
+        ("fill", &[Val::I32(0), Val::I32(7), Val::I32(3)]),
+        ("load", &[Val::I32(0)]),
+        ("copy", &[Val::I32(0), Val::I32(16), Val::I32(8)]),
+        ("load", &[Val::I32(0)]),
+        ("copy", &[Val::I32(17), Val::I32(16), Val::I32(4)]),
+        ("copy", &[Val::I32(16), Val::I32(0), Val::I32(0)]),
+        ("load", &[Val::I32(16)]),
+        ("copy", &[Val::I32(8), Val::I32(0), Val::I32(4)]),
+        ("load", &[Val::I32(8)]),
+        ("init", &[Val::I32(0), Val::I32(1), Val::I32(4)]),
+        ("load", &[Val::I32(0)]),
+        ("init", &[Val::I32(0), Val::I32(2), Val::I32(4)]),
+        ("drop", &[]),
+        ("init", &[Val::I32(0), Val::I32(0), Val::I32(1)]),
+        ("fill", &[Val::I32(0), Val::I32(0), Val::I32(0)]),
+        ("load", &[Val::I32(0)]),
+    ]);
+}
+
+#[test]
+fn crate_memory_layout() {
+    run_crate("stack", EmitOptions::default(), &[
+        ("sum_data", &[]),
+        ("sum", &[Val::I32(9)]),
+        ("sum", &[Val::I32(100)]),
+    ]);
+}
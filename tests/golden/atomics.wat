@@ -0,0 +1,40 @@
+(module
+  (memory 1 1 shared)
+  (data (i32.const 8) "\01\02\03\04")
+  (func (export "load") (param $addr i32) (result i32)
+    local.get $addr
+    i32.atomic.load)
+  (func (export "load8") (param $addr i32) (result i64)
+    local.get $addr
+    i64.atomic.load8_u)
+  (func (export "store16") (param $addr i32) (param $value i64)
+    local.get $addr
+    local.get $value
+    i64.atomic.store16)
+  (func (export "add") (param $addr i32) (param $value i32) (result i32)
+    local.get $addr
+    local.get $value
+    i32.atomic.rmw.add)
+  (func (export "sub8") (param $addr i32) (param $value i32) (result i32)
+    local.get $addr
+    local.get $value
+    i32.atomic.rmw8.sub_u)
+  (func (export "xchg") (param $addr i32) (param $value i64) (result i64)
+    local.get $addr
+    local.get $value
+    i64.atomic.rmw.xchg)
+  (func (export "cmpxchg") (param $addr i32) (param $expected i32) (param $replacement i32) (result i32)
+    local.get $addr
+    local.get $expected
+    local.get $replacement
+    i32.atomic.rmw.cmpxchg)
+  (func (export "wait") (param $addr i32) (param $expected i32) (result i32)
+    atomic.fence
+    local.get $addr
+    local.get $expected
+    i64.const 0
+    memory.atomic.wait32)
+  (func (export "notify") (param $addr i32) (result i32)
+    local.get $addr
+    i32.const 1
+    memory.atomic.notify))
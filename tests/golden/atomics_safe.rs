@@ -0,0 +1,20 @@
+fn main() {
+    let mut instance = Instance::new(()).unwrap();
+    println!("{:?}", instance.load(8));
+    println!("{:?}", instance.load8(11));
+    println!("{:?}", instance.add(8, 0x10));
+    println!("{:?}", instance.sub8(8, 0x12));
+    println!("{:?}", instance.load(8));
+    println!("{:?}", instance.store16(10, 0x7fff_abcd));
+    println!("{:?}", instance.xchg(8, -1));
+    println!("{:?}", instance.cmpxchg(8, 0, 5));
+    println!("{:?}", instance.cmpxchg(8, -1, 5));
+    println!("{:?}", instance.load(8));
+    println!("{:?}", instance.wait(8, 5));
+    println!("{:?}", instance.wait(8, 6));
+    println!("{:?}", instance.notify(8));
+    println!("{:?}", instance.load(9));
+    println!("{:?}", instance.sub8(65535, 1));
+    println!("{:?}", instance.load(65536));
+    println!("{:?}", instance.notify(65534));
+}
@@ -0,0 +1,24 @@
+(module
+  (memory 1)
+  (data $passive "\01\02\03\04\05")
+  (data (i32.const 16) "abcdefgh")
+  (func (export "fill") (param i32 i32 i32)
+    local.get 0
+    local.get 1
+    local.get 2
+    memory.fill)
+  (func (export "copy") (param i32 i32 i32)
+    local.get 0
+    local.get 1
+    local.get 2
+    memory.copy)
+  (func (export "init") (param i32 i32 i32)
+    local.get 0
+    local.get 1
+    local.get 2
+    memory.init $passive)
+  (func (export "drop")
+    data.drop $passive)
+  (func (export "load") (param i32) (result i64)
+    local.get 0
+    i64.load))
@@ -0,0 +1,65 @@
+(module
+  (tag $error (param i32))
+  (tag $other)
+  (func $throw_if_large (param $x i32)
+    local.get $x
+    i32.const 10
+    i32.gt_s
+    if
+      local.get $x
+      throw $error
+    end)
+  (func (export "caught") (param $x i32) (result i32)
+    try (result i32)
+      local.get $x
+      call $throw_if_large
+      local.get $x
+    catch $error
+      i32.const 100
+      i32.add
+    end)
+  (func (export "rethrown") (param $x i32) (result i32)
+    try (result i32)
+      try
+        local.get $x
+        call $throw_if_large
+      catch_all
+        rethrow 0
+      end
+      i32.const 0
+    catch $error
+    end)
+  (func (export "caught_all") (result i32)
+    try (result i32)
+      throw $other
+    catch $error
+      drop
+      i32.const 1
+    catch_all
+      i32.const 2
+    end)
+  (func (export "delegated") (param $x i32) (result i32)
+    try (result i32)
+      try
+        local.get $x
+        call $throw_if_large
+      delegate 0
+      i32.const 5
+    catch $error
+      i32.const 1000
+      i32.add
+    end)
+  (func (export "escaped") (param $x i32) (result i32)
+    block $out (result i32)
+      try
+        i32.const 7
+        local.get $x
+        br_if $out
+        drop
+      catch_all
+      end
+      i32.const 8
+    end)
+  (func (export "uncaught") (param $x i32)
+    local.get $x
+    call $throw_if_large))
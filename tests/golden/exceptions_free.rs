@@ -0,0 +1,12 @@
+fn main() {
+    // uncaught exceptions are reported by `catch_unwind` instead
+    ::std::panic::set_hook(Box::new(|_| ()));
+    unsafe {
+        println!("{} {}", caught(3), caught(11));
+        println!("{} {}", rethrown(3), rethrown(12));
+        println!("{}", caught_all());
+        println!("{} {}", delegated(4), delegated(20));
+        println!("{} {}", escaped(1), escaped(0));
+        println!("{} {}", ::std::panic::catch_unwind(|| uncaught(1)).is_err(), ::std::panic::catch_unwind(|| uncaught(11)).is_err());
+    }
+}
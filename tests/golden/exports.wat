@@ -0,0 +1,15 @@
+(module
+  (import "env" "counter" (global $counter (mut i32)))
+  (global $host (export "host") (mut i32) (i32.const 10))
+  (global $dashed (export "my-global") i32 (i32.const 3))
+  (func (export "drop") (result i32)
+    global.get $host
+    global.get $counter
+    i32.add)
+  (func (export "new") (result i32)
+    global.get $dashed)
+  (func (export "bump")
+    global.get $counter
+    i32.const 1
+    i32.add
+    global.set $counter))
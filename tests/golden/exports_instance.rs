@@ -0,0 +1,17 @@
+struct Host;
+
+impl Env for Host {
+    fn counter(&self) -> i32 {
+        5
+    }
+}
+
+fn main() {
+    let mut instance = Instance::new(Host);
+    unsafe {
+        instance.bump();
+        println!("{}", instance.drop());
+        println!("{}", instance.new_1());
+    }
+    println!("{} {}", instance.host_1, instance.my_global);
+}
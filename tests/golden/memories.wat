@@ -0,0 +1,21 @@
+(module
+  (memory $wide i64 1)
+  (memory $narrow 1)
+  (data (memory $wide) (i64.const 8) "\01\02\03\04")
+  (data (memory $narrow) (i32.const 0) "hello")
+  (func (export "load_wide") (param i64) (result i32)
+    local.get 0
+    i32.load $wide)
+  (func (export "load_narrow") (param i32) (result i32)
+    local.get 0
+    i32.load8_u $narrow)
+  (func (export "copy") (param i64 i32 i32)
+    local.get 0
+    local.get 1
+    local.get 2
+    memory.copy $wide $narrow)
+  (func (export "grow") (result i64)
+    i64.const 1
+    memory.grow $wide)
+  (func (export "size") (result i32)
+    memory.size $narrow))
@@ -0,0 +1,17 @@
+fn trapped(f: impl FnOnce() + ::std::panic::UnwindSafe) -> bool {
+    ::std::panic::set_hook(Box::new(|_| {}));
+    ::std::panic::catch_unwind(f).is_err()
+}
+
+fn main() {
+    unsafe {
+        setup();
+        println!("{:#x}", load_wide(8));
+        println!("{}", trapped(|| { load_wide((1 << 32) + 8); }));
+        copy(100, 1, 4);
+        println!("{:#x}", load_wide(100));
+        println!("{} {}", load_narrow(4), size());
+        println!("{}", trapped(|| { load_narrow(65536); }));
+        println!("{} {:#x}", grow(), load_wide(65536));
+    }
+}
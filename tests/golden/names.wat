@@ -0,0 +1,14 @@
+(module
+  (func $_ZN7mycrate3add17h0123456789abcdefE (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.add)
+  (func $_RNvCs1234_7mycrate6square (param i32) (result i32)
+    local.get 0
+    local.get 0
+    i32.mul)
+  (func (export "run") (param i32) (result i32)
+    local.get 0
+    call $_RNvCs1234_7mycrate6square
+    i32.const 1
+    call $_ZN7mycrate3add17h0123456789abcdefE))
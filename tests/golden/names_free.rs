@@ -0,0 +1,8 @@
+fn main() {
+    unsafe {
+        setup();
+        println!("{}", add(2, 3));
+        println!("{}", square(4));
+        println!("{}", run(5));
+    }
+}
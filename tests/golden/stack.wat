@@ -0,0 +1,39 @@
+(module
+  (memory 17)
+  (global $__stack_pointer (mut i32) (i32.const 1048576))
+  (data (i32.const 1048576) "\09\00\00\00")
+  ;; sum of the squares up to $n, keeping $n in a frame of the shadow stack
+  (func $sum (export "sum") (param $n i32) (result i32)
+    (local $frame i32)
+    global.get $__stack_pointer
+    i32.const 16
+    i32.sub
+    local.tee $frame
+    global.set $__stack_pointer
+    local.get $frame
+    local.get $n
+    i32.store offset=12
+    local.get $n
+    i32.eqz
+    if (result i32)
+      i32.const 0
+    else
+      local.get $n
+      i32.const 1
+      i32.sub
+      call $sum
+      local.get $frame
+      i32.load offset=12
+      local.get $frame
+      i32.load offset=12
+      i32.mul
+      i32.add
+    end
+    local.get $frame
+    i32.const 16
+    i32.add
+    global.set $__stack_pointer)
+  (func (export "sum_data") (result i32)
+    i32.const 1048576
+    i32.load
+    call $sum))
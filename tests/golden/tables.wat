@@ -0,0 +1,22 @@
+(module
+  (import "env" "__indirect_function_table" (table $table 3 funcref))
+  (import "env" "__table_base" (global $table_base i32))
+  (type $binary (func (param i32 i32) (result i32)))
+  (func $add (type $binary)
+    local.get 0
+    local.get 1
+    i32.add)
+  (func $sub (type $binary)
+    local.get 0
+    local.get 1
+    i32.sub)
+  (elem (global.get $table_base) func $add $sub)
+  (func (export "apply") (param $f i32) (param $a i32) (param $b i32) (result i32)
+    local.get $a
+    local.get $b
+    global.get $table_base
+    local.get $f
+    i32.add
+    call_indirect (type $binary))
+  (func (export "size") (result i32)
+    table.size $table))
@@ -0,0 +1,16 @@
+struct Host;
+
+impl Env for Host {
+    fn __table_base(&self) -> i32 {
+        1
+    }
+}
+
+fn main() {
+    let mut instance = Instance::new(Host);
+    unsafe {
+        println!("{}", instance.size());
+        println!("{}", instance.apply(0, 7, 2));
+        println!("{}", instance.apply(1, 7, 2));
+    }
+}
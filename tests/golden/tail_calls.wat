@@ -0,0 +1,58 @@
+(module
+  (type $unary (func (param i64) (result i64)))
+  (table 2 funcref)
+  (elem (i32.const 0) $double $negate)
+  ;; deep self recursion; $extra is set on every call but starts at zero
+  (func $sum (export "sum") (param $n i64) (param $acc i64) (result i64)
+    (local $extra i64)
+    local.get $n
+    i64.eqz
+    if
+      local.get $acc
+      return
+    end
+    local.get $extra
+    local.get $n
+    i64.add
+    local.set $extra
+    local.get $n
+    i64.const 1
+    i64.sub
+    local.get $acc
+    local.get $extra
+    i64.add
+    return_call $sum)
+  (func $is_even (export "is_even") (param $n i32) (result i32)
+    local.get $n
+    i32.eqz
+    if
+      i32.const 1
+      return
+    end
+    local.get $n
+    i32.const 1
+    i32.sub
+    return_call $is_odd)
+  (func $is_odd (param $n i32) (result i32)
+    local.get $n
+    i32.eqz
+    if
+      i32.const 0
+      return
+    end
+    local.get $n
+    i32.const 1
+    i32.sub
+    return_call $is_even)
+  (func $double (type $unary)
+    local.get 0
+    i64.const 2
+    i64.mul)
+  (func $negate (type $unary)
+    i64.const 0
+    local.get 0
+    i64.sub)
+  (func (export "apply") (param $f i32) (param $x i64) (result i64)
+    local.get $x
+    local.get $f
+    return_call_indirect (type $unary)))
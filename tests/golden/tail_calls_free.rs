@@ -0,0 +1,7 @@
+fn main() {
+    unsafe {
+        println!("{}", sum(1_000_000, 0));
+        println!("{} {}", is_even(10), is_even(7));
+        println!("{} {}", apply(0, 21), apply(1, 21));
+    }
+}